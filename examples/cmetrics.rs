@@ -1,5 +1,5 @@
 use anyhow::Result;
-use concurrency::CmapMetrics;
+use concurrency::{CmapMetrics, MetricsReporter};
 use rand::Rng;
 use std::{thread, time::Duration};
 
@@ -20,17 +20,21 @@ fn main() -> Result<()> {
         request_worker(metrics.clone())?;
     }
 
+    // 每 2 秒打印一次，原来 main 里手写的 loop + sleep + println 交给 MetricsReporter
+    // 默认的 formatter 按 key 排序，sink 打印到 stdout
+    let _reporter = MetricsReporter::builder()
+        .interval(Duration::from_secs(2))
+        .start(metrics);
+    // 打印结果：
+    // call.thread.worker.0: 8
+    // call.thread.worker.1: 5
+    // req{page="1"}: 32
+    // req{page="2"}: 30
+    // req{page="3"}: 30
+    // req{page="4"}: 27
+
     loop {
-        thread::sleep(Duration::from_secs(2));
-        // println!("{:?}", metrics.snapshot());
-        println!("{}", metrics); // 需要 impl Display
-                                 // 打印结果：
-                                 // req.page.4: 27
-                                 // req.page.1: 32
-                                 // call.thread.worker.1: 5
-                                 // req.page.2: 30
-                                 // call.thread.worker.0: 8
-                                 // req.page.3: 30
+        thread::park(); // worker 线程一直在跑，main 线程只需要不退出
     }
 
     // Ok(())
@@ -66,7 +70,8 @@ fn request_worker(metrics: CmapMetrics) -> Result<()> {
             let page = rng.gen_range(1..5);
 
             // "?" operator can only be used in the closure that returns Result or Option
-            metrics.inc(format!("req.page.{}", page))?; // metrics.inc(format!("req.page.{}", page)).unwrap(); use ? instead of unwrap to propagate the error
+            // 打印出来是 req{page="4"}，而不是把 page 拼进 key 里的 req.page.4
+            metrics.inc_with_labels("req", &[("page", &page.to_string())])?; // use ? instead of unwrap to propagate the error
         }
         #[allow(unreachable_code)]
         Ok::<_, anyhow::Error>(()) // 因为上面是 loop，所以这里永远不会执行到；但如果没有这一行，编译器会报错，因为上面用了 ?，所以这里需要返回一个 Result。
@@ -1,20 +1,41 @@
 // Title: A simple Redis server
-// Description: A simple Redis server that accepts connections and returns +OK for any input.
-// it is a dummy redis server that accepts connections and returns +OK for any input.
+// Description: A simple Redis server that stores strings, lists and hashes with optional TTLs, and supports pub/sub.
+// 命令在 concurrency::redis::Db 上执行，所有连接共享同一个 DashMap<String, Entry>：
+// 每个 Entry 是一个带类型的值（string / list / hash）加上可选的过期时间，用错类型的命令访问时回复 WRONGTYPE；
+// 过期的 key 在访问时删除，后台的 sweeper 每秒再清一次。PUBLISH / SUBSCRIBE 走 Db 里的 PubSub。
 // redis-cli -h 127.0.0.1 -p 6379，将尝试连接到本地主机的 6379 端口
+// redis-cli -p 6379 SET foo bar / GET foo / GETSET foo baz
+// redis-cli -p 6379 INCR n / INCRBY n 10 / DECR n
+// redis-cli -p 6379 KEYS "user:*" / EXISTS foo n / DEL foo n
+// redis-cli -p 6379 EXPIRE foo 10 / TTL foo / PEXPIRE foo 500 / PTTL foo
+// redis-cli -p 6379 RPUSH list a b c / LPOP list / LRANGE list 0 -1
+// redis-cli -p 6379 HSET user name bob age 3 / HGET user name / HGETALL user / HDEL user age
+// redis-cli -p 6379 SUBSCRIBE news，另一个终端 redis-cli -p 6379 PUBLISH news hello
+// redis-cli -p 6379 INFO 返回服务器自己的 metrics：连接数、命令数、读写的字节数、每种命令的次数
+// 同时最多 MAX_CONNECTIONS 个连接（环境变量 MAX_CONNECTIONS 可以修改），多出来的和 redis 一样回复错误后关闭
+// Ctrl-C：不再 accept 新连接，通知所有连接在处理完当前命令后关闭，最多等 SHUTDOWN_TIMEOUT 再退出
 
-use std::net::SocketAddr;
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use concurrency::{
+    redis::{decode_command, is_command, message_reply, Db, Reply, Subscriber},
+    CmapMetrics, FormatOptions, MetricsBackend,
+};
 use tokio::{
     io::{self, AsyncWriteExt},
     net::TcpListener,
+    sync::{watch, Semaphore},
+    task::JoinSet,
 };
 use tracing::{info, warn};
 
 const BUF_SIZE: usize = 4096; // 4KB
                               // 通常情况下，我们会使用一个固定大小的缓冲区来读取数据，这个缓冲区的大小可以根据实际情况来调整，比如 4KB，8KB，16KB 等，这个缓冲区的大小不是越大越好，因为缓冲区越大，内存占用就越大，而且可能会导致内存碎片，所以需要根据实际情况来调整
                               // 这里是字节还是位？这里是字节，1 字节 = 8 位。1KB = 1024 字节，1MB = 1024KB，1GB = 1024MB
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CONNECTIONS: usize = 1024;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -30,37 +51,148 @@ async fn main() -> Result<()> {
                                                 // Yes, you can think of TcpListener as a wrapper for handling incoming network requests over TCP.
                                                 // It provides an asynchronous interface for listening to and accepting incoming TCP connections.
 
+    // 所有连接共享同一个 CmapMetrics，clone 只是增加 Arc 的引用计数
+    let metrics = CmapMetrics::new();
+    let db = Db::new();
+    // 没有人访问的过期 key 靠后台 task 清理
+    db.spawn_sweeper(Duration::from_secs(1));
+
+    // 每个连接持有一个 permit，task 结束时 permit drop，名额还回去
+    let max_connections = env::var("MAX_CONNECTIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(MAX_CONNECTIONS);
+    let limit = Arc::new(Semaphore::new(max_connections));
+    info!("DumyRedis: max connections {}", max_connections);
+
+    // watch 只保留最新的值，每个连接拿一个 receiver，收到 true 就退出
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    // JoinSet 记住所有连接的 task，关闭的时候可以等它们结束
+    let mut conns = JoinSet::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
     loop {
-        let (stream, raddr) = listener.accept().await?;
+        let (mut stream, raddr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut ctrl_c => {
+                info!("Received Ctrl-C, shutting down");
+                break;
+            }
+        };
         info!("Accepted connection from: {}", raddr); // 打印客户端的地址 remote address
+                                                      // 名额用完时不排队：排队的连接对客户端来说就是卡住了，直接拒绝让客户端自己重试
+        let Ok(permit) = limit.clone().try_acquire_owned() else {
+            warn!("Rejected connection from {}: too many connections", raddr);
+            metrics.inc("connections.rejected")?;
+            // 回复放到单独的 task 里，不让一个不读数据的客户端卡住 accept
+            tokio::spawn(async move {
+                let _ = stream
+                    .write_all(b"-ERR max number of clients reached\r\n")
+                    .await;
+            });
+            continue;
+        };
+        metrics.inc("connections.accepted")?;
+        // 不管连接是正常关闭、出错还是 panic，guard drop 的时候都会减掉
+        let active = metrics.in_flight("connections.active")?;
 
-        tokio::spawn(async move {
+        let metrics = metrics.clone();
+        let db = db.clone();
+        let mut shutdown = shutdown_rx.clone();
+        conns.spawn(async move {
+            let _permit = permit;
+            let _active = active;
             // process_redis_conn(stream).await.unwrap();
-            if let Err(e) = process_redis_conn(stream, raddr).await {
+            if let Err(e) = process_redis_conn(stream, raddr, &metrics, &db, &mut shutdown).await {
                 warn!("Error processing conn with {}: {:?}", raddr, e);
             }
         });
+        // 顺手回收已经结束的连接，JoinSet 不会一直变大
+        while conns.try_join_next().is_some() {}
     }
+
+    // 先关掉 listener，新的连接直接被拒绝；再通知已有的连接
+    drop(listener);
+    shutdown_tx.send_replace(true);
+    let drain = async { while conns.join_next().await.is_some() {} };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, drain).await.is_err() {
+        warn!(
+            "{} connections still open after {:?}, aborting",
+            conns.len(),
+            SHUTDOWN_TIMEOUT
+        );
+        conns.shutdown().await;
+    }
+    info!("DumyRedis: bye");
+    Ok(())
 }
 
-async fn process_redis_conn(mut stream: tokio::net::TcpStream, raddr: SocketAddr) -> Result<()> {
+async fn process_redis_conn(
+    mut stream: tokio::net::TcpStream,
+    raddr: SocketAddr,
+    metrics: &CmapMetrics,
+    db: &Db,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<()> {
+    // 订阅了 channel 之后，除了等客户端的命令，还要同时等 PUBLISH 过来的消息
+    let mut subscriber = Subscriber::new(db.pubsub().clone());
+    // 没有 decode 完的半条命令留在 buf 里，等下一次 read 补齐
+    let mut buf = BytesMut::with_capacity(BUF_SIZE);
     loop {
         // Wait for the socket to be readable
-        stream.readable().await?;
-
-        let mut buf = Vec::with_capacity(BUF_SIZE);
+        tokio::select! {
+            ready = stream.readable() => ready?,
+            // 只在等下一条命令的时候检查，正在处理的命令会先写完回复
+            _ = shutdown.changed() => break,
+            Some((channel, message)) = subscriber.recv() => {
+                let reply = message_reply(&channel, message).encode();
+                stream.write_all(&reply).await?;
+                metrics.inc_by("bytes.written", reply.len() as i64)?;
+                continue;
+            }
+        }
 
         // Try to read data, this may still fail with `WouldBlock`
         // if the readiness event is a false positive.
+        // 每次至少留出 BUF_SIZE 的空间，不然缓冲区满了之后每次只能读几十个字节
+        buf.reserve(BUF_SIZE);
         match stream.try_read_buf(&mut buf) {
             Ok(0) => break, // EOF, end of file, 说明没有东西读了，直接退出循环
             Ok(n) => {
                 info!("read {} bytes", n);
                 // The from_utf8_lossy function in Rust is a method provided by the std::string::String module.
                 // It is used to convert a slice of bytes (&[u8]) into a String, replacing any invalid UTF-8 sequences with the Unicode replacement character � (U+FFFD).
-                let line = String::from_utf8_lossy(&buf);
+                let line = String::from_utf8_lossy(&buf[buf.len() - n..]);
                 info!("read: {:?}", line);
-                stream.write_all(b"+OK\r\n").await?; // 接收到任何数据，都返回 +OK\r\n
+                metrics.inc_by("bytes.read", n as i64)?;
+
+                // 每条完整的命令回复一次，pipeline 的多条命令的回复合在一起写
+                let mut reply = Vec::new();
+                let mut protocol_error = false;
+                loop {
+                    match decode_command(&mut buf) {
+                        Ok(Some(args)) => {
+                            reply.extend(handle_command(&args, metrics, db, &mut subscriber)?)
+                        }
+                        Ok(None) => break,
+                        // 和 redis 一样，协议错了之后的数据已经没法解析，回复错误后关闭连接
+                        Err(e) => {
+                            reply.extend(
+                                Reply::error(format!("ERR Protocol error: {}", e)).encode(),
+                            );
+                            protocol_error = true;
+                            break;
+                        }
+                    }
+                }
+                if !reply.is_empty() {
+                    stream.write_all(&reply).await?;
+                    metrics.inc_by("bytes.written", reply.len() as i64)?;
+                }
+                if protocol_error {
+                    break;
+                }
             }
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                 // WouldBlock 是操作系统返回的错误，表示当前操作会阻塞，需要等待
@@ -75,6 +207,52 @@ async fn process_redis_conn(mut stream: tokio::net::TcpStream, raddr: SocketAddr
     Ok(())
 }
 
+// INFO 返回 metrics，SUBSCRIBE/UNSUBSCRIBE 改连接自己的订阅，其它命令交给 Db 执行
+fn handle_command(
+    args: &[Bytes],
+    metrics: &CmapMetrics,
+    db: &Db,
+    subscriber: &mut Subscriber,
+) -> Result<Vec<u8>> {
+    let cmd = command_name(args);
+    metrics.inc("commands.processed")?;
+    // 不认识的命令都记成 UNKNOWN，客户端不能用任意的命令名制造出无限多的 key
+    let label = if cmd == "INFO" || is_command(&cmd) {
+        cmd.as_str()
+    } else {
+        "UNKNOWN"
+    };
+    metrics.inc_with_labels("commands", &[("cmd", label)])?;
+    let reply = if let Some(replies) = subscriber.execute(args) {
+        replies.iter().flat_map(|r| r.encode()).collect()
+    } else if subscriber.is_subscribed() {
+        // 和 redis 一样，订阅状态下只接受订阅相关的命令
+        Reply::error(format!(
+            "ERR Can't execute '{}': only SUBSCRIBE / UNSUBSCRIBE are allowed in this context",
+            cmd.to_ascii_lowercase()
+        ))
+        .encode()
+    } else if cmd == "INFO" {
+        info_reply(metrics)
+    } else {
+        db.execute(args).encode()
+    };
+    Ok(reply)
+}
+
+// 命令名是第一个参数，统一转成大写，作为 commands{cmd=..} 的 label
+fn command_name(args: &[Bytes]) -> String {
+    args.first()
+        .map(|name| String::from_utf8_lossy(name).to_ascii_uppercase())
+        .unwrap_or_else(|| "UNKNOWN".to_string())
+}
+
+// INFO 的回复是一个 bulk string，内容是按 key 排好序、对齐的表
+fn info_reply(metrics: &CmapMetrics) -> Vec<u8> {
+    let body = metrics.format_with(&FormatOptions::default());
+    Reply::Bulk(Some(Bytes::from(body))).encode()
+}
+
 // io::ErrorKind::WouldBlock
 // is a variant of the ErrorKind enum in Rust's standard library,
 // specifically within the std::io module. It represents a non-blocking operation that would block if it were allowed to proceed.
@@ -81,7 +81,7 @@ fn producer(idx: usize, tx: mpsc::Sender<Msg>) -> Result<()> {
 
         // 以上代码不会自动退出loop，如果要强行退出，需要一个退出条件。
         // random exit the producer
-        if rand::random::<u8>() % 5 == 0 {
+        if rand::random::<u8>().is_multiple_of(5) {
             println!("Producer {} exiting", idx);
             // break; // 因为要退出循环，所以，需要一个返回值，这里只是 break，就是报错。
             return Ok(()); // 或者在 loop 之后，返回一个 Ok(())，表示正常退出。
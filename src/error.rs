@@ -0,0 +1,52 @@
+// vector/matrix 的失败原因。函数仍然返回 anyhow::Result，需要区分原因的调用者可以用
+// err.downcast_ref::<concurrency::Error>() 拿到具体的变体来 match，而不是去比较字符串。
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    // 向量长度、a.col 与 b.row 之类的一维长度不一致
+    #[error("{what} dimensions do not match, expected {expected}, got {got}")]
+    DimensionMismatch {
+        what: &'static str,
+        expected: usize,
+        got: usize,
+    },
+    // 矩阵形状 (row, col) 不符合要求
+    #[error("{what} dimensions do not match, expected {}x{}, got {}x{}", expected.0, expected.1, got.0, got.1)]
+    ShapeMismatch {
+        what: &'static str,
+        expected: (usize, usize),
+        got: (usize, usize),
+    },
+    #[error("Cross product requires 3-element vectors, got {lhs} and {rhs}")]
+    CrossDimension { lhs: usize, rhs: usize },
+    #[error("{what} matrix must be square, got {row}x{col}")]
+    NotSquare {
+        what: &'static str,
+        row: usize,
+        col: usize,
+    },
+    #[error("Matrix is not symmetric at ({row}, {col})")]
+    NotSymmetric { row: usize, col: usize },
+    #[error("Triplet ({row}, {col}) out of bounds for a {nrows}x{ncols} matrix")]
+    OutOfBounds {
+        row: usize,
+        col: usize,
+        nrows: usize,
+        ncols: usize,
+    },
+    #[error("Integer overflow in output cell ({row}, {col})")]
+    Overflow { row: usize, col: usize },
+    #[error("{0} is undefined for a zero vector")]
+    ZeroVector(&'static str),
+    #[error("{0} is not representable as f64")]
+    NotRepresentable(&'static str),
+    #[error("VectorView stride must be positive")]
+    InvalidStride,
+    #[error("Matrix multiply cancelled")]
+    Cancelled,
+    #[error("{what} worker queue closed")]
+    QueueClosed { what: &'static str },
+    #[error("{what} worker panicked: {message}")]
+    WorkerPanicked { what: &'static str, message: String },
+}
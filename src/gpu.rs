@@ -0,0 +1,191 @@
+// GPU 后端：用 wgpu 的 compute shader 计算 f32 矩阵乘法，每个 invocation 计算结果中的一个单元 (i, j)。
+// 初始化（adapter、device、pipeline）很慢，所以只做一次，结果缓存在 OnceLock 里；没有可用的 GPU 时缓存 None。
+// 任何一步失败都返回 None，由调用者回退到 CPU 的 worker pool。
+use std::{any::Any, borrow::Cow, sync::OnceLock};
+
+use wgpu::util::DeviceExt;
+
+use crate::{Layout, Matrix};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+const SHADER: &str = r#"
+struct Dims {
+    m: u32,
+    k: u32,
+    n: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<storage, read> a: array<f32>;
+@group(0) @binding(1) var<storage, read> b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> c: array<f32>;
+@group(0) @binding(3) var<uniform> dims: Dims;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    let j = id.y;
+    if (i >= dims.m || j >= dims.n) {
+        return;
+    }
+    var sum = 0.0;
+    for (var p = 0u; p < dims.k; p = p + 1u) {
+        sum = sum + a[i * dims.k + p] * b[p * dims.n + j];
+    }
+    c[i * dims.n + j] = sum;
+}
+"#;
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+// T 不是 f32、没有 GPU、或者矩阵超出了 GPU 的限制时返回 None。
+// 先检查类型和 GPU，再按需转成 RowMajor，回退到 CPU 的时候不会白白复制一遍 a 和 b
+pub(crate) fn try_multiply<T: Clone + 'static>(a: &Matrix<T>, b: &Matrix<T>) -> Option<Vec<T>> {
+    let a = (a as &dyn Any).downcast_ref::<Matrix<f32>>()?;
+    let b = (b as &dyn Any).downcast_ref::<Matrix<f32>>()?;
+    let gpu = Gpu::get()?;
+    let (m, k, n) = (a.row, a.col, b.col);
+    let c = gpu.multiply(&row_major(a), &row_major(b), m, k, n)?;
+    // T 就是 f32，这里只是把类型换回来
+    (Box::new(c) as Box<dyn Any>)
+        .downcast::<Vec<T>>()
+        .ok()
+        .map(|c| *c)
+}
+
+// 已经是 RowMajor 时直接借用 data
+fn row_major(m: &Matrix<f32>) -> Cow<'_, [f32]> {
+    match m.layout {
+        Layout::RowMajor => Cow::Borrowed(&m.data),
+        Layout::ColMajor => Cow::Owned(m.to_layout(Layout::RowMajor).data),
+    }
+}
+
+impl Gpu {
+    fn get() -> Option<&'static Gpu> {
+        static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+        GPU.get_or_init(|| pollster::block_on(Gpu::new())).as_ref()
+    }
+
+    async fn new() -> Option<Gpu> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .ok()?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default())
+            .await
+            .ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("matrix multiply"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("matrix multiply"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Some(Gpu {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    // a 是 m * k，b 是 k * n，都是 RowMajor
+    fn multiply(&self, a: &[f32], b: &[f32], m: usize, k: usize, n: usize) -> Option<Vec<f32>> {
+        let limits = self.device.limits();
+        let max_len = limits.max_storage_buffer_binding_size as usize / size_of::<f32>();
+        let max_groups = limits.max_compute_workgroups_per_dimension as usize;
+        // 空缓冲区不能绑定；太大的矩阵放不进一个 storage buffer
+        if m * k * n == 0
+            || a.len().max(b.len()).max(m * n) > max_len
+            || m.div_ceil(WORKGROUP_SIZE as usize)
+                .max(n.div_ceil(WORKGROUP_SIZE as usize))
+                > max_groups
+        {
+            return None;
+        }
+
+        let storage = |label, data: &[f32]| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(data),
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+        };
+        let a_buf = storage("a", a);
+        let b_buf = storage("b", b);
+        let size = (m * n * size_of::<f32>()) as u64;
+        let c_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("c"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let dims = [m as u32, k as u32, n as u32, 0];
+        let dims_buf = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("dims"),
+                contents: bytemuck::cast_slice(&dims),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        // storage buffer 不能被 CPU 直接读，先 copy 到一个可以 map 的 staging buffer
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[&a_buf, &b_buf, &c_buf, &dims_buf]
+                .iter()
+                .enumerate()
+                .map(|(i, buf)| wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource: buf.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (m as u32).div_ceil(WORKGROUP_SIZE),
+                (n as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&c_buf, 0, &staging, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        staging.map_async(wgpu::MapMode::Read, .., move |r| {
+            let _ = tx.send(r);
+        });
+        self.device.poll(wgpu::PollType::wait_indefinitely()).ok()?;
+        rx.recv().ok()?.ok()?;
+        let data = bytemuck::cast_slice::<u8, f32>(&staging.get_mapped_range(..).ok()?).to_vec();
+        staging.unmap();
+        Some(data)
+    }
+}
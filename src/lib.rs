@@ -1,7 +1,32 @@
+mod error;
+#[cfg(feature = "gpu")]
+mod gpu;
 mod matrix;
 mod metrics;
+pub mod redis;
+#[cfg(feature = "simd")]
+mod simd;
+mod sparse;
+mod structured;
 mod vector;
 
-pub use matrix::{multiply, Matrix};
-pub use metrics::{AmapMetrics, CmapMetrics};
-pub use vector::{dot_product, Vector};
+pub use error::Error;
+pub use matrix::{
+    multiply, multiply_acc, multiply_async, multiply_batch, multiply_checked, multiply_into,
+    multiply_seq, multiply_with, Algorithm, Backend, CancellationToken, Layout, Matrix,
+    MultiplyOptions, ProgressFn,
+};
+pub use metrics::{
+    global, AmapGauges, AmapMetrics, AtomicF64, CmapGauges, CmapMetrics, CounterFamily,
+    CsvAppender, EvictionPolicy, FormatOptions, GaugeFamily, HistogramFamily, InFlight,
+    IndexedMetrics, Key, Metadata, Meter, MetricId, MetricKey, MetricsBackend, MetricsLayer,
+    MetricsReporter, OverflowPolicy, Quantiles, RateTracker, Registry, ReporterBuilder, Scoped,
+    ShardedMetrics, Snapshot, SnapshotDiff, StatsdExporter, Sweeper, Timer, TypedMetrics,
+    WindowedCounter,
+};
+pub use sparse::SparseMatrix;
+pub use structured::{SymmetricMatrix, Triangle, TriangularMatrix};
+pub use vector::{
+    dot_product, dot_product_acc, dot_product_checked, dot_product_par, dot_product_par_acc,
+    dot_product_stable, Vector, VectorView,
+};
@@ -1,16 +1,38 @@
-use anyhow::{anyhow, Result}; // anyhow::anyhow 是个宏，用来创建一个 anyhow::Error 类型的错误。Result 是一个类型别名，它是 anyhow::Result 类型的别名。
+use anyhow::Result; // Result 是一个类型别名，它是 anyhow::Result 类型的别名。错误本身是 crate::Error，通过 .into() 装进 anyhow::Error。
+use num_complex::Complex;
+use num_traits::{CheckedAdd, CheckedMul, Num};
 use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
     fmt,
-    ops::{Add, AddAssign, Mul},
-    sync::mpsc,
+    ops::{Add, AddAssign, Mul, Neg, Range, Sub},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     thread,
 };
 
-use crate::{dot_product, Vector};
+#[cfg(feature = "crossbeam")]
+use crossbeam_channel::{Receiver, Sender};
+#[cfg(not(feature = "crossbeam"))]
+use std::sync::{mpsc::Sender, Mutex};
+
+use crate::{
+    vector::{dot_slice_acc, dot_slice_checked, dot_slice_scalar},
+    Error, Vector,
+};
 // what is crate?
 // crate 是一个 Rust 项目的根目录。在一个 crate 中，可以有多个模块，每个模块可以包含多个函数、结构体、枚举等。
 
-const NUM_THREADS: usize = 4;
+pub(crate) const NUM_THREADS: usize = 4;
+// Algorithm::Auto 下，方阵边长达到这个值才会切换到 Strassen；递归到 STRASSEN_LEAF 以下就改用普通的三重循环。
+const STRASSEN_THRESHOLD: usize = 512;
+const STRASSEN_LEAF: usize = 64;
+// 每个 worker 消息处理的行数，以及 worker 内部列分块的宽度
+const DEFAULT_BLOCK_SIZE: usize = 64;
+// 乘法次数少于这个值的矩阵相乘不开线程
+const SEQUENTIAL_THRESHOLD: usize = 32 * 32 * 32;
 
 // 声明一个矩阵的结构
 // [[1, 2], [1, 2], [1, 2]] => [1, 2, 1, 2, 1, 2] // 计算机比较喜欢后一种形式，因为它更加紧凑。前一种形式中，每个元素都是一个数组，指针指向增加复杂性
@@ -22,126 +44,907 @@ const NUM_THREADS: usize = 4;
 // }
 
 pub struct Matrix<T> {
-    data: Vec<T>, // 一维数组，其中包含矩阵的所有元素。其中 T 用泛型表示，可以是任意类型。如果用 i32 表示，那么这个矩阵就是一个整数矩阵。
-    row: usize,
-    col: usize,
+    pub(crate) data: Vec<T>, // 一维数组，其中包含矩阵的所有元素。其中 T 用泛型表示，可以是任意类型。如果用 i32 表示，那么这个矩阵就是一个整数矩阵。
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+    pub(crate) layout: Layout,
+}
+
+// data 中元素的排列方式。RowMajor：一行接一行（C 的习惯）；ColMajor：一列接一列（Fortran/BLAS 的习惯）
+// 从 Fortran/BLAS 导入的数据可以直接用 ColMajor 构造，不需要先转置一遍。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    #[default]
+    RowMajor,
+    ColMajor,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    // 方阵、足够大、并且元素是有符号整数或浮点数时用 Strassen，否则用多线程的逐单元 dot_product
+    #[default]
+    Auto,
+    Naive,
+    Strassen,
+}
+
+// 计算放在哪里：Gpu 目前只支持 f32，而且需要开启 gpu feature（cargo build --features gpu）；
+// 没开 feature、元素不是 f32、或者机器上没有可用的 GPU 时，都会回退到 CPU 的 worker pool。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+// 进度回调：progress(completed_cells, total_cells)，由 worker 线程在每算完一批行之后调用，所以需要 Send + Sync
+pub type ProgressFn = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct MultiplyOptions {
+    pub algorithm: Algorithm,
+    pub backend: Backend,
+    pub block_size: usize, // 每个 worker 消息处理 block_size 行，worker 内部按 block_size 列分块
+    pub cancel: Option<CancellationToken>, // 调用者在其它线程里 cancel() 之后，multiply_with 会尽快返回错误
+    // 浮点结果逐位可复现：每个单元都按 k 从小到大累加（与 multiply_seq 相同），不用 SIMD、不用 GPU，
+    // Auto 也不会选 Strassen。结果与线程数、block_size、调度顺序都无关，CI 可以直接比较
+    pub deterministic: bool,
+    pub progress: Option<ProgressFn>,
+    pub sequential_threshold: usize, // a.row * a.col * b.col（乘法次数）小于这个值时，不开线程，直接在当前线程里算
+}
+
+// dyn Fn 没有实现 Debug，所以手动实现
+impl fmt::Debug for MultiplyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MultiplyOptions")
+            .field("algorithm", &self.algorithm)
+            .field("backend", &self.backend)
+            .field("block_size", &self.block_size)
+            .field("cancel", &self.cancel)
+            .field("deterministic", &self.deterministic)
+            .field(
+                "progress",
+                &self.progress.as_ref().map(|_| "Fn(usize, usize)"),
+            )
+            .field("sequential_threshold", &self.sequential_threshold)
+            .finish()
+    }
+}
+
+impl Default for MultiplyOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            backend: Backend::default(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            cancel: None,
+            deterministic: false,
+            progress: None,
+            sequential_threshold: SEQUENTIAL_THRESHOLD,
+        }
+    }
+}
+
+impl MultiplyOptions {
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+
+    fn report_progress(&self, completed: usize, total: usize) {
+        if let Some(progress) = &self.progress {
+            progress(completed, total);
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+// 取消一个正在进行的 multiply_with：clone 一份交给 MultiplyOptions，在另一个线程里调用 cancel()。
+// worker 在每个工作单元（一批行）之间检查它，所以取消不是立刻生效的，最多再算完手上的那一批。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// 可配置版本的 multiply。Strassen 需要做减法，所以这里比 multiply 多了一个 Sub 的约束。
+// 注意：Strassen 的中间结果可能是负数，无符号整数在 debug 模式下会因为下溢而 panic，
+// 所以 Auto 只对 signed_or_float 认识的类型选择 Strassen；不要对无符号整数显式选择 Algorithm::Strassen。
+pub fn multiply_with<T>(a: &Matrix<T>, b: &Matrix<T>, opts: &MultiplyOptions) -> Result<Matrix<T>>
+where
+    T: Mul<Output = T>
+        + Add<Output = T>
+        + Sub<Output = T>
+        + AddAssign
+        + Default
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    if a.col != b.row {
+        return Err(Error::DimensionMismatch {
+            what: "Matrix inner",
+            expected: a.col,
+            got: b.row,
+        }
+        .into());
+    }
+
+    #[cfg(feature = "gpu")]
+    if opts.backend == Backend::Gpu && !opts.deterministic {
+        opts.check_cancelled()?;
+        if let Some(data) = crate::gpu::try_multiply(a, b) {
+            let total = a.row * b.col;
+            opts.report_progress(total, total);
+            return Ok(Matrix {
+                data,
+                row: a.row,
+                col: b.col,
+                layout: Layout::RowMajor,
+            });
+        }
+    }
+
+    let use_strassen = match opts.algorithm {
+        Algorithm::Naive => false,
+        Algorithm::Strassen => true,
+        Algorithm::Auto => {
+            !opts.deterministic
+                && a.row == a.col
+                && b.row == b.col
+                && a.row >= STRASSEN_THRESHOLD
+                && signed_or_float::<T>()
+        }
+    };
+    if !use_strassen {
+        let kernel = if opts.deterministic {
+            dot_slice_scalar::<T, T>
+        } else {
+            dot_slice_acc::<T, T>
+        };
+        let data = par_dot_product(a, b, opts, kernel)?;
+        return Ok(Matrix {
+            data,
+            row: a.row,
+            col: b.col,
+            layout: Layout::RowMajor,
+        });
+    }
+
+    // Strassen 要求边长是 2 的幂，所以先用 0 把两个矩阵补成 n * n，算完后再截取 a.row * b.col 的部分
+    let n = a.row.max(a.col).max(b.col).next_power_of_two();
+    let c = strassen(&a.pad(n), &b.pad(n), n, true, opts.cancel.as_ref());
+    opts.check_cancelled()?;
+    // Strassen 的子问题不对应输出中固定的单元，所以只在全部完成时报告一次
+    let total = a.row * b.col;
+    opts.report_progress(total, total);
+    let data = (0..a.row)
+        .flat_map(|i| c[i * n..i * n + b.col].iter().cloned())
+        .collect();
+
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+        layout: Layout::RowMajor,
+    })
 }
 
 pub fn multiply<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
 where
-    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Copy + Send + 'static,
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    let data = par_dot_product(a, b, &MultiplyOptions::default(), dot_slice_acc::<T, T>)?;
+
+    // 矩阵相乘的结果，是一个 a.row * b.col 的矩阵，这个矩阵中的每个元素的下标是 i * b.col + j，其中 i 是行号，j 是列号。
+    // 上述的计算可以这么思考：从最终结果的矩阵中，定位任意一个元素为：data[i * b.col + j]；然后，这个元素是由 a 矩阵的第 i 行和 b 矩阵的第 j 列相乘得到的。
+    // 所以，我们需要遍历 a 矩阵的第 i 行和 b 矩阵的第 j 列，然后，把它们的乘积累加到 data[i * b.col + j] 中。
+    // k 是 a 矩阵的列号，也是 b 矩阵的行号。
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+        layout: Layout::RowMajor,
+    })
+}
+
+// 单线程的三重循环版本，不经过 worker/channel，用作 benchmark 的基线；很小的矩阵直接用它也更快
+pub fn multiply_seq<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Mul<Output = T> + AddAssign + Default + Clone,
+{
+    if a.col != b.row {
+        return Err(Error::DimensionMismatch {
+            what: "Matrix inner",
+            expected: a.col,
+            got: b.row,
+        }
+        .into());
+    }
+    let mut data = vec![T::default(); a.row * b.col];
+    for i in 0..a.row {
+        for j in 0..b.col {
+            for k in 0..a.col {
+                // offset 处理了 RowMajor/ColMajor 两种排列；+= 来自 AddAssign trait
+                data[i * b.col + j] +=
+                    a.data[a.offset(i, k)].clone() * b.data[b.offset(k, j)].clone();
+            }
+        }
+    }
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+        layout: Layout::RowMajor,
+    })
+}
+
+// 把 a * b 的结果写进已有的 out 中，out 的形状必须是 a.row * b.col。
+// 迭代算法（幂迭代、马尔可夫链）在循环里反复相乘时，可以一直复用同一个 out，不用每次都分配一个新的 Vec。
+pub fn multiply_into<T>(a: &Matrix<T>, b: &Matrix<T>, out: &mut Matrix<T>) -> Result<()>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    // 所有检查都在修改 out 之前完成，失败时 out 保持原样
+    if a.col != b.row {
+        return Err(Error::DimensionMismatch {
+            what: "Matrix inner",
+            expected: a.col,
+            got: b.row,
+        }
+        .into());
+    }
+    if out.row != a.row || out.col != b.col {
+        return Err(Error::ShapeMismatch {
+            what: "Output matrix",
+            expected: (a.row, b.col),
+            got: (out.row, out.col),
+        }
+        .into());
+    }
+    // 结果总是按 RowMajor 写入
+    out.layout = Layout::RowMajor;
+    par_dot_product_into(
+        a,
+        b,
+        &MultiplyOptions::default(),
+        dot_slice_acc::<T, T>,
+        full_range,
+        &mut out.data,
+    )
+}
+
+// 在 tokio 的 async handler 里直接调用 multiply 会阻塞 runtime 的 worker 线程，
+// multiply_async 通过 spawn_blocking 把计算放到 tokio 的 blocking 线程池里，await 期间 runtime 可以继续处理其它任务。
+// spawn_blocking 要求闭包是 'static 的，所以这里接收 Matrix 的所有权，而不是引用。
+pub async fn multiply_async<T>(a: Matrix<T>, b: Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    tokio::task::spawn_blocking(move || multiply(&a, &b)).await?
+}
+
+// 批量相乘：所有矩阵对共用同一组 NUM_THREADS 个 worker，而不是每对都重新开一次线程。
+// 每对矩阵按 DEFAULT_BLOCK_SIZE 行切成若干批，全部放进同一个共享队列；小矩阵通常一对就是一批。
+// 每对的结果互相独立：某一对维度不匹配只会让它自己的那一项返回 Err。
+pub fn multiply_batch<T>(pairs: &[(&Matrix<T>, &Matrix<T>)]) -> Vec<Result<Matrix<T>>>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    // (第几对, a 的哪几行, 结果发回的 oneshot)
+    type Job<T> = (usize, Range<usize>, oneshot::Sender<Result<Vec<T>>>);
+
+    // 与 par_dot_product_into 一样：a 换成 RowMajor，b 转置成 bt
+    let prepared = pairs
+        .iter()
+        .map(|(a, b)| {
+            // 这里保留具体的 Error，后面每一对都可以 clone 一份自己的错误
+            if a.col != b.row {
+                return Err(Error::DimensionMismatch {
+                    what: "Matrix inner",
+                    expected: a.col,
+                    got: b.row,
+                });
+            }
+            let a_data = match a.layout {
+                Layout::RowMajor => Cow::Borrowed(&a.data[..]),
+                Layout::ColMajor => Cow::Owned(a.to_layout(Layout::RowMajor).data),
+            };
+            let bt = match b.layout {
+                Layout::RowMajor => b.transpose().data,
+                Layout::ColMajor => b.data.clone(),
+            };
+            Ok((a_data, bt))
+        })
+        .collect::<Vec<_>>();
+    let prepared = &prepared;
+
+    let (sender, receivers) = work_queue::<Job<T>>();
+    // thread::scope 让 worker 直接借用 prepared，不需要为每一对建 Arc
+    thread::scope(|s| {
+        let handles = receivers
+            .into_iter()
+            .map(|rx| {
+                s.spawn(move || {
+                    while let Ok((idx, row, tx)) = rx.recv() {
+                        let Ok((a_data, bt)) = &prepared[idx] else {
+                            continue;
+                        };
+                        let k = pairs[idx].0.col;
+                        let mut values = vec![T::default(); row.len() * pairs[idx].1.col];
+                        let values = compute_rows(
+                            &a_data[row.start * k..row.end * k],
+                            row.clone(),
+                            bt,
+                            DEFAULT_BLOCK_SIZE,
+                            dot_slice_acc::<T, T>,
+                            full_range,
+                            &mut values,
+                        )
+                        .map(|_| values);
+                        // 发送失败说明调用者已经不再等这一批了
+                        let _ = tx.send(values);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // map: 所有矩阵对的所有批次都发进同一个队列
+        let mut pending = Vec::with_capacity(pairs.len());
+        for (idx, ((a, b), p)) in pairs.iter().zip(prepared).enumerate() {
+            let mut receivers = Vec::new();
+            // a.col == 0 时结果全是 default（空的求和），不需要 worker
+            if p.is_ok() && a.col > 0 {
+                for i0 in (0..a.row).step_by(DEFAULT_BLOCK_SIZE) {
+                    let (tx, rx) = oneshot::channel();
+                    let i1 = (i0 + DEFAULT_BLOCK_SIZE).min(a.row);
+                    if sender.send((idx, i0..i1, tx)).is_err() {
+                        break;
+                    }
+                    receivers.push(rx);
+                }
+            }
+            pending.push((a.row * b.col, receivers));
+        }
+        drop(sender);
+
+        // reduce: 按批次顺序拼出每一对的结果。
+        // worker panic 时它手上那一批的 oneshot 被 drop，recv 返回 RecvError，这一对先记为 None
+        let results = pending
+            .into_iter()
+            .zip(prepared)
+            .zip(pairs)
+            .map(|(((len, receivers), p), (a, b))| {
+                if let Err(e) = p {
+                    return Some(Err(e.clone().into()));
+                }
+                let mut data = Vec::with_capacity(len);
+                for rx in receivers {
+                    match rx.recv() {
+                        Ok(Ok(values)) => data.extend(values),
+                        Ok(Err(e)) => return Some(Err(e)),
+                        Err(_) => return None,
+                    }
+                }
+                data.resize(len, T::default());
+                Some(Ok(Matrix {
+                    data,
+                    row: a.row,
+                    col: b.col,
+                    layout: Layout::RowMajor,
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        // 只有收不到结果的那几对换成 panic 的原因，其它对自己的错误（比如形状不对）保持不变
+        let panicked = handles
+            .into_iter()
+            .filter_map(|h| h.join().err())
+            .map(|e| panic_message(&e))
+            .next()
+            .unwrap_or_else(|| "worker exited without replying".to_string());
+        results
+            .into_iter()
+            .map(|r| {
+                r.unwrap_or_else(|| {
+                    Err(Error::WorkerPanicked {
+                        what: "Matrix",
+                        message: panicked.clone(),
+                    }
+                    .into())
+                })
+            })
+            .collect()
+    })
+}
+
+// 与 multiply 相同，但每个输出单元都累加到更宽的类型 A 中，比如 Matrix<i32> * Matrix<i32> => Matrix<i64>。
+pub fn multiply_acc<T, A>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<A>>
+where
+    T: Clone + Send + Sync + 'static,
+    A: From<T> + Mul<Output = A> + AddAssign + Default + Clone + Send + 'static,
+{
+    let data = par_dot_product(a, b, &MultiplyOptions::default(), dot_slice_acc::<T, A>)?;
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+        layout: Layout::RowMajor,
+    })
+}
+
+// 整数矩阵相乘时，dot_product 溢出会悄悄 wrap（release 模式下）。
+// multiply_checked 用 checked_mul/checked_add 计算，一旦某个输出单元溢出，就返回一个指明 (row, col) 的错误。
+pub fn multiply_checked<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: CheckedMul + CheckedAdd + Default + Clone + Send + Sync + 'static,
+{
+    let data = par_dot_product(a, b, &MultiplyOptions::default(), dot_slice_checked)?
+        .into_iter()
+        .enumerate()
+        .map(|(idx, value)| {
+            value.ok_or_else(|| {
+                Error::Overflow {
+                    row: idx / b.col,
+                    col: idx % b.col,
+                }
+                .into()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+        layout: Layout::RowMajor,
+    })
+}
+
+// multiply / multiply_acc / multiply_checked 共用的 map-reduce 骨架：每个输出单元 (i, j) 都是 a 的第 i 行与 b 的第 j 列的 kernel 结果。
+// kernel 是一个函数指针（fn 天然是 Copy + Send），所以可以直接 move 进每个 worker 线程。
+// 每个消息是 a 中连续的 block_size 行，worker 返回结果矩阵中对应的整行。
+// b 在开始时转置一次，通过 Arc 共享给所有 worker：转置后 b 的第 j 列就是 bt 中连续的一段，不再需要 step_by + collect。
+// worker 内部再按 block_size 列分块：一块列被这批的每一行重复使用，留在 cache 中（cache locality）。
+fn par_dot_product<T, O>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    opts: &MultiplyOptions,
+    kernel: fn(&[T], &[T]) -> Result<O>,
+) -> Result<Vec<O>>
+where
+    T: Clone + Send + Sync + 'static,
+    O: Default + Clone + Send + 'static,
+{
+    let mut data = vec![O::default(); a.row * b.col];
+    par_dot_product_into(a, b, opts, kernel, full_range, &mut data)?;
+    Ok(data)
+}
+
+// 输出单元 (i, j) 真正需要累加的下标范围（a 的第 i 行、b 的第 j 列里的 0..k 的一段），范围外的部分在结构上为 0。
+// 三角矩阵之类的结构可以借此跳过一半的乘法；空的范围表示这个单元直接是 default。
+pub(crate) type ActiveRange = fn(usize, usize, usize) -> Range<usize>;
+
+pub(crate) fn full_range(_i: usize, _j: usize, k: usize) -> Range<usize> {
+    0..k
+}
+
+// 与 par_dot_product 相同，但结果直接写进调用者提供的 out（长度必须是 a.row * b.col），并且只在 active 范围内调用 kernel
+pub(crate) fn par_dot_product_into<T, O>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    opts: &MultiplyOptions,
+    kernel: fn(&[T], &[T]) -> Result<O>,
+    active: ActiveRange,
+    out: &mut [O],
+) -> Result<()>
+where
+    T: Clone + Send + Sync + 'static,
+    O: Default + Clone + Send + 'static,
 {
     // + Debug
     if a.col != b.row {
-        return Err(anyhow!("Matrix dimensions do not match, a.col != b.row"));
+        return Err(Error::DimensionMismatch {
+            what: "Matrix inner",
+            expected: a.col,
+            got: b.row,
+        }
+        .into());
+    }
+    let block_size = opts.block_size.max(1);
+    let (k, n) = (b.row, b.col);
+    // 当 a.col == 0 时，chunks(0) 会 panic，所以直接返回全是 default 的结果（空的求和）
+    if k == 0 {
+        out.fill(O::default());
+        return Ok(());
+    }
+    // worker 需要 a 的整行和 b 的整列都是连续的：
+    // a 如果是 ColMajor 就先换成 RowMajor；b 如果是 ColMajor，它的 data 本身就是 bt，不需要转置
+    let a_data = match a.layout {
+        Layout::RowMajor => Cow::Borrowed(&a.data[..]),
+        Layout::ColMajor => Cow::Owned(a.to_layout(Layout::RowMajor).data),
+    };
+    let bt = Arc::new(match b.layout {
+        Layout::RowMajor => b.transpose().data,
+        Layout::ColMajor => b.data.clone(),
+    });
+    let total = a.row * n;
+
+    // 矩阵很小时，开线程、建 channel 的开销远大于计算本身（2x2 的矩阵原来要开 4 个线程），直接在当前线程里算
+    if a.row * k * n < opts.sequential_threshold {
+        opts.check_cancelled()?;
+        compute_rows(&a_data, 0..a.row, &bt, block_size, kernel, active, out)?;
+        opts.report_progress(total, total);
+        return Ok(());
     }
 
-    // (0..NUM_THREADS) is a iteraor. map is a iterator adapter.
-    let senders = (0..NUM_THREADS)
-        .map(|_| {
-            // 不需要返回值。创建 NUM_THREADS 个线程，每个线程都有一个 mpsc::Sender 实例。
-            let (tx, rx) = mpsc::channel::<Msg<T>>(); //channel 的泛型参数，需要把要传递的数据类型传给它。
+    let completed = Arc::new(AtomicUsize::new(0));
 
-            thread::spawn(move || {
-                for msg in rx {
-                    let value = dot_product(msg.input.row, msg.input.col)?;
+    let (sender, receivers) = work_queue::<Msg<T, O>>(); //channel 的泛型参数，需要把要传递的数据类型传给它。
+
+    // 保留每个 worker 的 JoinHandle，这样 worker 的 panic 和返回的 Err 不会被悄悄丢掉
+    let handles = receivers
+        .into_iter()
+        .map(|rx| {
+            // 创建 NUM_THREADS 个线程，它们从同一个队列里取消息：谁先算完手上的一批，谁就去取下一批，
+            // 这样某些批次特别慢时（比如稀疏程度不均匀的行），其它 worker 不会闲着等。
+            let bt = Arc::clone(&bt);
+            let opts = opts.clone();
+            let completed = Arc::clone(&completed);
+
+            let handle = thread::spawn(move || {
+                while let Ok(msg) = rx.recv() {
+                    // 已经取消了：直接丢弃这个消息（连同它的 oneshot sender），调用者的 recv 会因此返回错误
+                    if opts.is_cancelled() {
+                        continue;
+                    }
+                    let MsgInput { row, rows } = msg.input;
+                    // kernel 出错时不要用 ? 退出 worker 循环，而是把错误本身通过 oneshot 发回给调用者，
+                    // 这样调用者拿到的是原始的错误，而不是一个无关的 RecvError
+                    let mut values = vec![O::default(); row.len() * n];
+                    let values = compute_rows(
+                        &rows,
+                        row.clone(),
+                        &bt,
+                        block_size,
+                        kernel,
+                        active,
+                        &mut values,
+                    )
+                    .map(|_| {
+                        let done =
+                            completed.fetch_add(values.len(), Ordering::Relaxed) + values.len();
+                        opts.report_progress(done, total);
+                        values
+                    });
                     // 做完 dot_product 之后，把结果发送给发送者。
-                    // 2, 因为 error 不能在两个线程中发送，所以这里需要用 if let Err(e) = msg.sender.send(MsgOutput { ... }) {} 来处理错误。
-                    if let Err(e) = msg.sender.send(MsgOutput {
-                        idx: msg.input.idx,
-                        value,
-                    }) {
+                    // 2, 因为 anyhow::Error 是 Send 的，所以可以把整个 Result 发回去；发送失败说明调用者已经提前返回了（比如另一批出错）。
+                    if let Err(e) = msg.sender.send(MsgOutput { row, values }) {
                         eprintln!("Send error: {:?}", e);
                     }
                 }
                 Ok::<_, anyhow::Error>(()) // 1，因为编译器需要确定错误的类型，所以这里需要 Ok::<_, anyhow::Error>(())。
             });
-            tx
+            handle
         })
         .collect::<Vec<_>>();
 
+    // map + reduce 放在单独的函数里：不论它正常结束还是中途出错返回，sender 都会在它返回时被 drop，
+    // 每个 worker 的 recv 循环随之结束，下面的 join 才不会永远等下去。
+    let result = map_reduce(&a_data, k, n, block_size, opts, sender, out);
+
+    for handle in handles {
+        handle.join().map_err(|e| Error::WorkerPanicked {
+            what: "Matrix",
+            message: panic_message(&e),
+        })??;
+    }
+    result
+}
+
+// 计算结果矩阵中的 row 这几行，写进 values：rows 是 a 的这几行（首尾相接），bt 是转置后的 b
+// 按 block_size 列分块：一块列被这几行重复使用，留在 cache 中
+fn compute_rows<T, O: Default>(
+    rows: &[T],
+    row: Range<usize>,
+    bt: &[T],
+    block_size: usize,
+    kernel: fn(&[T], &[T]) -> Result<O>,
+    active: ActiveRange,
+    values: &mut [O],
+) -> Result<()> {
+    if row.is_empty() {
+        return Ok(());
+    }
+    let k = rows.len() / row.len();
+    let n = values.len() / row.len();
+    for j0 in (0..n).step_by(block_size) {
+        let j1 = (j0 + block_size).min(n);
+        // b 的 j0..j1 列，即 bt 的 j0..j1 行，每列长度为 k，首尾相接
+        let cols = &bt[j0 * k..j1 * k];
+        for (i, r) in row.clone().zip(rows.chunks(k)) {
+            for (j, c) in (j0..j1).zip(cols.chunks(k)) {
+                let range = active(i, j, k);
+                values[(i - row.start) * n + j] = if range.is_empty() {
+                    O::default()
+                } else {
+                    kernel(&r[range.clone()], &c[range])?
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+// a 是 RowMajor 的 data，k 是 a 的列数
+fn map_reduce<T, O>(
+    a: &[T],
+    k: usize,
+    n: usize,
+    block_size: usize,
+    opts: &MultiplyOptions,
+    sender: Sender<Msg<T, O>>,
+    data: &mut [O],
+) -> Result<()>
+where
+    T: Clone,
+    O: Clone,
+{
+    let m = a.len() / k;
+
     // let mut data = vec![0; a.row * b.col];
     // let mut data = Vec::with_capacity(a.row * b.col);
-    let matrix_len = a.row * b.col;
-    let mut data = vec![T::default(); matrix_len];
-    let mut receivers = Vec::with_capacity(matrix_len);
-
-    // for i in 0..a.row {
-    //     for j in 0..b.col {
-    //         for k in 0..a.col {
-    //             data[i * b.col + j] += a.data[i * a.col + k] * b.data[k * b.col + j]; // 因为每个Matrix<T>的data是Vec<T>，所以可以直接用[]访问
-    //             // +=, AddAssign trait  // copy trait 保证了 T 类型的实例可以直接赋值给 data[i * b.col + j]。
-    //         }
-    //     }
-    // }
-    // map-reduce: map phrase
-    for i in 0..a.row {
-        for j in 0..b.col {
-            // 如果把 下面一行，移到 上一层循环中，dot_product(row, col)? 会报错，因为 used of moved value: row
-            // 除非把 dot_product(row.clone(), col)?，但是这样会导致性能下降。
-            // 或者把引用传给 dot_product(&row, &col)?，但是这样会导致 dot_product 的签名变得复杂。需要把 dot_product 的签名改为 fn dot_product<T>(a: &Vector<T>, b: &Vector<T>) -> Result<T>，而且 Vector<T> 也要修改。
-            // 所以，这里，我们选择在内层循环中，创建 row 和 col 的实例。
-            let row = Vector::new(&a.data[i * a.col..(i + 1) * a.col]); // creates a new Vector instance from the slice representing the i-th row of the matrix a.
-            let col_data = b.data[j..]
-                .iter()
-                .step_by(b.col)
-                .copied()
-                .collect::<Vec<_>>();
-            let col = Vector::new(col_data);
-            let idx = i * b.col + j;
-            let input = MsgInput::new(idx, row, col);
-            let (tx, rx) = oneshot::channel();
-            let msg = Msg::new(input, tx);
-            if let Err(e) = senders[idx % NUM_THREADS].send(msg) {
-                // sender 是一个 vec of tx（其实就是mpsc::Sender<Msg<T>>），所以，我们可以用 idx % NUM_THREADS 来选择一个发送者。
-                eprintln!("Send error: {:?}", e);
-            }
+    let mut receivers = Vec::new();
 
-            receivers.push(rx);
+    // map-reduce: map phrase
+    for i0 in (0..m).step_by(block_size) {
+        opts.check_cancelled()?;
+        let i1 = (i0 + block_size).min(m);
+        // a 是按行存储的，所以 i0..i1 行本身就是一段连续的切片
+        let rows = Vector::new(&a[i0 * k..i1 * k]);
+        let input = MsgInput::new(i0..i1, rows);
+        let (tx, rx) = oneshot::channel();
+        let msg = Msg::new(input, tx);
+        // 所有消息都进同一个共享队列，不再按 idx % NUM_THREADS 固定分配给某个 worker
+        if let Err(e) = sender.send(msg) {
+            eprintln!("Send error: {:?}", e);
         }
+
+        receivers.push(rx);
     }
+    drop(sender); // 所有工作都已经发出去了，worker 处理完队列里的消息后就会退出
 
     // map-reduce: reduce phrase
     for rx in receivers {
-        let MsgOutput { idx, value } = rx.recv()?;
-        data[idx] = value;
+        let MsgOutput { row, values } = match rx.recv() {
+            Ok(output) => output,
+            Err(e) => {
+                opts.check_cancelled()?;
+                return Err(e.into());
+            }
+        };
+        // worker 端的错误在这里原样返回给调用者
+        let values = values?;
+        // row 这几行在 data 中也是连续的
+        data[row.start * n..row.end * n].clone_from_slice(&values);
+    }
+
+    Ok(())
+}
 
-        // let output= rx.recv()?;
-        // data[output.idx] = output.value;
+// 所有 worker 共享的工作队列：一个 Sender，NUM_THREADS 个 Receiver。
+// 默认用 std 的 mpsc：它是单消费者的（Receiver 不能 clone），所以用 Arc<Mutex<Receiver>> 包一层，worker 取消息时短暂加锁。
+// 开启 crossbeam feature 之后换成 crossbeam-channel：它本身就是多消费者的，Receiver 可以直接 clone，竞争时吞吐量也更好。
+#[cfg(not(feature = "crossbeam"))]
+pub(crate) fn work_queue<M>() -> (Sender<M>, Vec<Receiver<M>>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let rx = Receiver(Arc::new(Mutex::new(rx)));
+    (tx, vec![rx; NUM_THREADS])
+}
+
+#[cfg(feature = "crossbeam")]
+pub(crate) fn work_queue<M>() -> (Sender<M>, Vec<Receiver<M>>) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    (tx, vec![rx; NUM_THREADS])
+}
+
+#[cfg(not(feature = "crossbeam"))]
+pub(crate) struct Receiver<M>(Arc<Mutex<std::sync::mpsc::Receiver<M>>>);
+
+#[cfg(not(feature = "crossbeam"))]
+impl<M> Clone for Receiver<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
     }
+}
 
-    // 矩阵相乘的结果，是一个 a.row * b.col 的矩阵，这个矩阵中的每个元素的下标是 i * b.col + j，其中 i 是行号，j 是列号。
-    // 上述的计算可以这么思考：从最终结果的矩阵中，定位任意一个元素为：data[i * b.col + j]；然后，这个元素是由 a 矩阵的第 i 行和 b 矩阵的第 j 列相乘得到的。
-    // 所以，我们需要遍历 a 矩阵的第 i 行和 b 矩阵的第 j 列，然后，把它们的乘积累加到 data[i * b.col + j] 中。
-    // k 是 a 矩阵的列号，也是 b 矩阵的行号。
-    Ok(Matrix {
-        data,
-        row: a.row,
-        col: b.col,
-    })
+#[cfg(not(feature = "crossbeam"))]
+impl<M> Receiver<M> {
+    // 锁只在取消息的时候持有，计算时已经释放了，所以 worker panic 不会毒化这个锁；保险起见还是忽略 poison
+    pub(crate) fn recv(&self) -> Result<M, std::sync::mpsc::RecvError> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).recv()
+    }
+}
+
+// thread::spawn 的 panic payload 通常是 &str 或 String
+pub(crate) fn panic_message(e: &Box<dyn Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// 中间结果可以是负数的元素类型。用白名单而不是黑名单：不认识的类型（比如自定义的无符号类型）一律不选 Strassen
+fn signed_or_float<T: 'static>() -> bool {
+    [
+        TypeId::of::<i8>(),
+        TypeId::of::<i16>(),
+        TypeId::of::<i32>(),
+        TypeId::of::<i64>(),
+        TypeId::of::<i128>(),
+        TypeId::of::<isize>(),
+        TypeId::of::<f32>(),
+        TypeId::of::<f64>(),
+    ]
+    .contains(&TypeId::of::<T>())
+}
+
+// Strassen：把 n * n 的矩阵切成 4 块，用 7 次（而不是 8 次）子矩阵乘法得到结果，复杂度约为 O(n^2.81)。
+// 最顶层的 7 个子乘法分别放到 7 个线程里，更深的递归在各自线程内串行完成。
+// cancel 在每一层递归开始时检查，取消之后直接返回全 0 的结果，由 multiply_with 负责返回错误。
+fn strassen<T>(
+    a: &[T],
+    b: &[T],
+    n: usize,
+    parallel: bool,
+    cancel: Option<&CancellationToken>,
+) -> Vec<T>
+where
+    T: Mul<Output = T> + Add<Output = T> + Sub<Output = T> + AddAssign + Default + Clone + Send,
+{
+    if cancel.is_some_and(|c| c.is_cancelled()) {
+        return vec![T::default(); n * n];
+    }
+    if n <= STRASSEN_LEAF {
+        return naive_square(a, b, n);
+    }
+
+    let h = n / 2;
+    let [a11, a12, a21, a22] = split_quadrants(a, n);
+    let [b11, b12, b21, b22] = split_quadrants(b, n);
+
+    let inputs = [
+        (add(&a11, &a22), add(&b11, &b22)), // m1 = (a11 + a22)(b11 + b22)
+        (add(&a21, &a22), b11.clone()),     // m2 = (a21 + a22)b11
+        (a11.clone(), sub(&b12, &b22)),     // m3 = a11(b12 - b22)
+        (a22.clone(), sub(&b21, &b11)),     // m4 = a22(b21 - b11)
+        (add(&a11, &a12), b22.clone()),     // m5 = (a11 + a12)b22
+        (sub(&a21, &a11), add(&b11, &b12)), // m6 = (a21 - a11)(b11 + b12)
+        (sub(&a12, &a22), add(&b21, &b22)), // m7 = (a12 - a22)(b21 + b22)
+    ];
+
+    let m = if parallel {
+        thread::scope(|s| {
+            let handles = inputs
+                .into_iter()
+                .map(|(x, y)| s.spawn(move || strassen(&x, &y, h, false, cancel)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Strassen worker panicked!"))
+                .collect::<Vec<_>>()
+        })
+    } else {
+        inputs
+            .iter()
+            .map(|(x, y)| strassen(x, y, h, false, cancel))
+            .collect::<Vec<_>>()
+    };
+
+    let c11 = add(&sub(&add(&m[0], &m[3]), &m[4]), &m[6]);
+    let c12 = add(&m[2], &m[4]);
+    let c21 = add(&m[1], &m[3]);
+    let c22 = add(&add(&sub(&m[0], &m[1]), &m[2]), &m[5]);
+
+    let mut c = vec![T::default(); n * n];
+    for i in 0..h {
+        c[i * n..i * n + h].clone_from_slice(&c11[i * h..(i + 1) * h]);
+        c[i * n + h..(i + 1) * n].clone_from_slice(&c12[i * h..(i + 1) * h]);
+        c[(i + h) * n..(i + h) * n + h].clone_from_slice(&c21[i * h..(i + 1) * h]);
+        c[(i + h) * n + h..(i + h + 1) * n].clone_from_slice(&c22[i * h..(i + 1) * h]);
+    }
+    c
+}
+
+fn naive_square<T>(a: &[T], b: &[T], n: usize) -> Vec<T>
+where
+    T: Mul<Output = T> + AddAssign + Default + Clone,
+{
+    let mut c = vec![T::default(); n * n];
+    for i in 0..n {
+        for k in 0..n {
+            let aik = &a[i * n + k];
+            for j in 0..n {
+                c[i * n + j] += aik.clone() * b[k * n + j].clone();
+            }
+        }
+    }
+    c
 }
 
+// 把 n * n 的矩阵按 [左上, 右上, 左下, 右下] 切成 4 个 (n/2) * (n/2) 的矩阵
+fn split_quadrants<T: Clone>(m: &[T], n: usize) -> [Vec<T>; 4] {
+    let h = n / 2;
+    let quadrant = |row0: usize, col0: usize| {
+        (row0..row0 + h)
+            .flat_map(|i| m[i * n + col0..i * n + col0 + h].iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    [
+        quadrant(0, 0),
+        quadrant(0, h),
+        quadrant(h, 0),
+        quadrant(h, h),
+    ]
+}
+
+fn add<T: Add<Output = T> + Clone>(a: &[T], b: &[T]) -> Vec<T> {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| x.clone() + y.clone())
+        .collect()
+}
+
+fn sub<T: Sub<Output = T> + Clone>(a: &[T], b: &[T]) -> Vec<T> {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| x.clone() - y.clone())
+        .collect()
+}
+
+// 一批连续的行：a 的 row 这几行
 pub struct MsgInput<T> {
-    idx: usize,
-    row: Vector<T>,
-    col: Vector<T>,
+    row: Range<usize>,
+    rows: Vector<T>, // a 的 row 这几行，首尾相接
 }
 
 pub struct MsgOutput<T> {
-    idx: usize,
-    value: T, // why not use Vector<T>? Because the result of dot_product is a scalar, not a vector. // what is scalar? // scalar 是一个数，而不是一个向量。
+    row: Range<usize>,
+    values: Result<Vec<T>>, // 结果矩阵的 row 这几行，每个元素是 dot_product 的结果（标量）；或者计算这几行时的错误
 }
 
-pub struct Msg<T> {
+pub struct Msg<T, O = T> {
     input: MsgInput<T>,
-    sender: oneshot::Sender<MsgOutput<T>>, // tx
+    sender: oneshot::Sender<MsgOutput<O>>, // tx; O 是 kernel 的输出类型，multiply 中就是 T
 }
 
 // 为 Matrix<T> 实现 Mul trait，这样，我们就可以通过 * 运算符，来实现矩阵相乘。
 // 查看下面测试用例 test_matrix_multiply()，可以看到，通过 a * b，就可以实现矩阵相乘。
 impl<T> Mul for Matrix<T>
 where
-    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Copy + Send + 'static,
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
 {
     type Output = Self; //Matrix<T>
 
@@ -157,14 +960,107 @@ where
 // This snippet defines a constructor for the Matrix struct and requires that T implements the Debug trait.
 impl<T: fmt::Debug> Matrix<T> {
     pub fn new(data: impl Into<Vec<T>>, row: usize, col: usize) -> Self {
+        Self::with_layout(data, row, col, Layout::RowMajor)
+    }
+}
+
+impl<T> Matrix<T> {
+    pub fn with_layout(data: impl Into<Vec<T>>, row: usize, col: usize, layout: Layout) -> Self {
         Self {
             data: data.into(),
             row,
             col,
+            layout,
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    // 按行优先的顺序遍历 (row, col, &value)，与 data 实际的排列方式无关
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, usize, &T)> + '_ {
+        (0..self.row)
+            .flat_map(move |i| (0..self.col).map(move |j| (i, j, &self.data[self.offset(i, j)])))
+    }
+
+    // 第 i 行第 j 列的元素在 data 中的下标
+    pub(crate) fn offset(&self, i: usize, j: usize) -> usize {
+        match self.layout {
+            Layout::RowMajor => i * self.col + j,
+            Layout::ColMajor => j * self.row + i,
         }
     }
 }
 
+// map-reduce 的通用版本：把 data 切成 NUM_THREADS 块，每块在一个线程里做局部 reduce（map），最后在当前线程合并各块的结果（reduce）。
+// init 必须是 f 的"单位元"（sum 用 0），因为每个块都会从 init 开始累积。
+impl<T> Matrix<T>
+where
+    T: Clone + Send + Sync,
+{
+    pub fn par_reduce<F>(&self, init: T, f: F) -> T
+    where
+        F: Fn(T, T) -> T + Sync,
+    {
+        par_reduce_slice(&self.data, init, f)
+    }
+
+    pub fn sum(&self) -> T
+    where
+        T: Add<Output = T> + Default,
+    {
+        self.par_reduce(T::default(), |a, b| a + b)
+    }
+
+    // 空矩阵没有最小值/最大值，所以返回 Option
+    // min/max 是幂等的，所以可以用第一个元素作为每个块的 init
+    pub fn min(&self) -> Option<T>
+    where
+        T: PartialOrd,
+    {
+        let first = self.data.first()?.clone();
+        Some(self.par_reduce(first, |a, b| if b < a { b } else { a }))
+    }
+
+    pub fn max(&self) -> Option<T>
+    where
+        T: PartialOrd,
+    {
+        let first = self.data.first()?.clone();
+        Some(self.par_reduce(first, |a, b| if b > a { b } else { a }))
+    }
+}
+
+// Matrix::par_reduce 和 Vector 的 sum 等方法共用
+pub(crate) fn par_reduce_slice<T, F>(data: &[T], init: T, f: F) -> T
+where
+    T: Clone + Send + Sync,
+    F: Fn(T, T) -> T + Sync,
+{
+    if data.is_empty() {
+        return init;
+    }
+
+    let chunk_size = data.len().div_ceil(NUM_THREADS);
+    let f = &f;
+    // thread::scope 允许子线程借用 data，不需要像 multiply 那样把数据 copy 到 'static 的消息里
+    thread::scope(|s| {
+        let handles = data
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let init = init.clone();
+                s.spawn(move || chunk.iter().fold(init, |acc, x| f(acc, x.clone())))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Reduce worker panicked!"))
+            .fold(init, f)
+    })
+}
+
 // why we need to implement Display trait?
 // Because we want to print the matrix in a human-readable format.
 
@@ -177,7 +1073,7 @@ where
         write!(f, "{{")?;
         for i in 0..self.row {
             for j in 0..self.col {
-                write!(f, "{}", self.data[i * self.col + j])?;
+                write!(f, "{}", self.data[self.offset(i, j)])?;
                 if j != self.col - 1 {
                     write!(f, " ")?;
                 }
@@ -192,6 +1088,49 @@ where
     }
 }
 
+// 给报告、notebook 用的格式：Display 的一行 {1 2, 3 4} 没法被文档工具直接使用
+impl<T: fmt::Display> Matrix<T> {
+    // Markdown 表格必须有表头，这里用列号作为表头：
+    // | 0 | 1 |
+    // |---|---|
+    // | 1 | 2 |
+    // | 3 | 4 |
+    pub fn to_markdown(&self) -> String {
+        let mut s = String::new();
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+        s.push_str(&line((0..self.col).map(|j| j.to_string()).collect()));
+        s.push_str(&format!("|{}\n", "---|".repeat(self.col)));
+        for i in 0..self.row {
+            s.push_str(&line(
+                (0..self.col)
+                    .map(|j| self.data[self.offset(i, j)].to_string())
+                    .collect(),
+            ));
+        }
+        s
+    }
+
+    // LaTeX 的 bmatrix（需要 amsmath）：
+    // \begin{bmatrix}
+    // 1 & 2 \\
+    // 3 & 4
+    // \end{bmatrix}
+    pub fn to_latex(&self) -> String {
+        let rows = (0..self.row)
+            .map(|i| {
+                (0..self.col)
+                    .map(|j| self.data[self.offset(i, j)].to_string())
+                    .collect::<Vec<_>>()
+                    .join(" & ")
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "\\begin{{bmatrix}}\n{}\n\\end{{bmatrix}}",
+            rows.join(" \\\\\n")
+        )
+    }
+}
+
 impl<T> fmt::Debug for Matrix<T>
 where
     T: fmt::Display,
@@ -201,14 +1140,67 @@ where
     }
 }
 
+impl<T: Clone> Matrix<T> {
+    // 转置：结果的第 j 行是原矩阵的第 j 列，结果总是 RowMajor 的
+    // 原矩阵按 ColMajor 排列的 data，正好就是转置矩阵按 RowMajor 排列的 data
+    pub fn transpose(&self) -> Matrix<T> {
+        Matrix {
+            data: self.to_layout(Layout::ColMajor).data,
+            row: self.col,
+            col: self.row,
+            layout: Layout::RowMajor,
+        }
+    }
+
+    // 按另一种排列方式复制一份，矩阵本身（每个 (i, j) 上的元素）不变
+    pub fn to_layout(&self, layout: Layout) -> Matrix<T> {
+        let data = match layout {
+            Layout::RowMajor => (0..self.row)
+                .flat_map(|i| (0..self.col).map(move |j| self.data[self.offset(i, j)].clone()))
+                .collect(),
+            Layout::ColMajor => (0..self.col)
+                .flat_map(|j| (0..self.row).map(move |i| self.data[self.offset(i, j)].clone()))
+                .collect(),
+        };
+        Matrix {
+            data,
+            row: self.row,
+            col: self.col,
+            layout,
+        }
+    }
+}
+
+// 共轭转置（conjugate transpose / Hermitian adjoint）：先转置，再对每个元素取共轭，信号处理里的 A^H
+impl<T: Clone + Num + Neg<Output = T>> Matrix<Complex<T>> {
+    pub fn adjoint(&self) -> Matrix<Complex<T>> {
+        let mut m = self.transpose();
+        m.data.iter_mut().for_each(|v| *v = v.conj());
+        m
+    }
+}
+
+impl<T: Default + Clone> Matrix<T> {
+    // 把矩阵放到 n * n 的左上角，其余位置补 T::default()
+    fn pad(&self, n: usize) -> Vec<T> {
+        let mut data = vec![T::default(); n * n];
+        for i in 0..self.row {
+            for j in 0..self.col {
+                data[i * n + j] = self.data[self.offset(i, j)].clone();
+            }
+        }
+        data
+    }
+}
+
 impl<T> MsgInput<T> {
-    pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
-        Self { idx, row, col }
+    pub fn new(row: Range<usize>, rows: Vector<T>) -> Self {
+        Self { row, rows }
     }
 }
 
-impl<T> Msg<T> {
-    pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<T>>) -> Self {
+impl<T, O> Msg<T, O> {
+    pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<O>>) -> Self {
         Self { input, sender }
     }
 }
@@ -216,6 +1208,7 @@ impl<T> Msg<T> {
 #[cfg(test)]
 mod tests {
     use super::*; // use super::*; 表示引入当前模块的父模块中的所有内容。super 表示父模块，* 表示所有内容。
+    use anyhow::anyhow; // anyhow::anyhow 是个宏，用来创建一个 anyhow::Error 类型的错误。
 
     #[test]
     fn test_matrix_multiply() -> Result<()> {
@@ -248,6 +1241,515 @@ mod tests {
         assert!(c.is_err()); // assert!(c.is_err()); 表示 c 是一个错误。
     }
 
+    #[test]
+    fn test_matrix_reduce() {
+        let a = Matrix::new([3, -1, 7, 2, 9, 4, 0, 5, -6], 3, 3);
+        assert_eq!(a.sum(), 23);
+        assert_eq!(a.min(), Some(-6));
+        assert_eq!(a.max(), Some(9));
+        assert_eq!(
+            Matrix::new([1, 2, 3, 4], 2, 2).par_reduce(1, |x, y| x * y),
+            24
+        );
+
+        let empty: Matrix<i32> = Matrix::new(Vec::<i32>::new(), 0, 0);
+        assert_eq!(empty.sum(), 0);
+        assert_eq!(empty.min(), None);
+    }
+
+    #[test]
+    fn test_matrix_multiply_block_size() -> Result<()> {
+        // 7 * 5 和 5 * 9 的矩阵，block_size = 3 时最后一批行、最后一块列都不是完整的 3
+        let a = Matrix::new((0..35).collect::<Vec<i64>>(), 7, 5);
+        let b = Matrix::new((0..45).map(|v| v - 20).collect::<Vec<i64>>(), 5, 9);
+        let expected = multiply(&a, &b)?;
+        for block_size in [1, 2, 3, 64] {
+            let opts = MultiplyOptions {
+                algorithm: Algorithm::Naive,
+                block_size,
+                sequential_threshold: 0,
+                ..Default::default()
+            };
+            assert_eq!(multiply_with(&a, &b, &opts)?.data, expected.data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+        for (algorithm, sequential_threshold) in [
+            (Algorithm::Naive, 0),
+            (Algorithm::Naive, usize::MAX),
+            (Algorithm::Strassen, 0),
+        ] {
+            let opts = MultiplyOptions {
+                algorithm,
+                sequential_threshold,
+                cancel: Some(token.clone()),
+                ..Default::default()
+            };
+            let err = multiply_with(&a, &b, &opts).unwrap_err();
+            assert_eq!(err.to_string(), "Matrix multiply cancelled");
+            assert_eq!(err.downcast_ref(), Some(&Error::Cancelled));
+        }
+    }
+
+    #[test]
+    fn test_matrix_multiply_progress() -> Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let last = Arc::new(AtomicUsize::new(0));
+        let (c, l) = (calls.clone(), last.clone());
+        let opts = MultiplyOptions {
+            algorithm: Algorithm::Naive,
+            block_size: 2,
+            sequential_threshold: 0,
+            progress: Some(Arc::new(move |completed, total| {
+                assert_eq!(total, 35);
+                c.fetch_add(1, Ordering::Relaxed);
+                l.fetch_max(completed, Ordering::Relaxed);
+            })),
+            ..Default::default()
+        };
+        let a = Matrix::new((0..35).collect::<Vec<i64>>(), 7, 5);
+        let b = Matrix::new((0..25).collect::<Vec<i64>>(), 5, 5);
+        multiply_with(&a, &b, &opts)?;
+        // 7 行按每批 2 行分成 4 批
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+        assert_eq!(last.load(Ordering::Relaxed), 35);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_worker_panic() {
+        fn bad_kernel(_: &[i32], _: &[i32]) -> Result<i32> {
+            panic!("boom");
+        }
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+        let opts = MultiplyOptions {
+            sequential_threshold: 0,
+            ..Default::default()
+        };
+        let err = par_dot_product(&a, &b, &opts, bad_kernel).unwrap_err();
+        assert_eq!(err.to_string(), "Matrix worker panicked: boom");
+    }
+
+    #[test]
+    fn test_matrix_multiply_worker_error() {
+        fn bad_kernel(r: &[i32], _: &[i32]) -> Result<i32> {
+            if r[0] == 3 {
+                return Err(anyhow!("bad row starting with {}", r[0]));
+            }
+            Ok(0)
+        }
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+        let opts = MultiplyOptions {
+            block_size: 1,
+            sequential_threshold: 0,
+            ..Default::default()
+        };
+        let err = par_dot_product(&a, &b, &opts, bad_kernel).unwrap_err();
+        assert_eq!(err.to_string(), "bad row starting with 3");
+    }
+
+    #[test]
+    fn test_multiply_options_debug() {
+        let opts = MultiplyOptions {
+            sequential_threshold: 42,
+            progress: Some(Arc::new(|_, _| {})),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", opts);
+        assert!(debug.contains("sequential_threshold: 42"));
+        assert!(debug.contains("progress: Some(\"Fn(usize, usize)\")"));
+    }
+
+    #[test]
+    fn test_matrix_multiply_sequential_fallback() -> Result<()> {
+        let a = Matrix::new((0..35).collect::<Vec<i64>>(), 7, 5);
+        let b = Matrix::new((0..45).map(|v| v - 20).collect::<Vec<i64>>(), 5, 9);
+        let results = [0, usize::MAX]
+            .into_iter()
+            .map(|sequential_threshold| {
+                let opts = MultiplyOptions {
+                    algorithm: Algorithm::Naive,
+                    sequential_threshold,
+                    ..Default::default()
+                };
+                multiply_with(&a, &b, &opts).map(|c| c.data)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(results[0], results[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_strassen() -> Result<()> {
+        let opts = MultiplyOptions {
+            algorithm: Algorithm::Strassen,
+            ..Default::default()
+        };
+
+        // 100 * 100 会被补成 128 * 128，刚好递归一层
+        let n = 100;
+        let a = Matrix::new(
+            (0..n * n).map(|v| (v % 7) as i64 - 3).collect::<Vec<_>>(),
+            n,
+            n,
+        );
+        let b = Matrix::new(
+            (0..n * n).map(|v| (v % 5) as i64 - 2).collect::<Vec<_>>(),
+            n,
+            n,
+        );
+        assert_eq!(multiply_with(&a, &b, &opts)?.data, multiply(&a, &b)?.data);
+
+        // 非方阵同样可以通过补 0 使用 Strassen
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+        let c = multiply_with(&a, &b, &opts)?;
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matrix_multiply_async() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+        let c = multiply_async(a, b).await?;
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+        assert!(multiply_async(a, b).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_into() -> Result<()> {
+        // 马尔可夫链：state = state * transition，反复复用两个缓冲区
+        let transition = Matrix::new([0.5f64, 0.5, 0.25, 0.75], 2, 2);
+        let mut state = Matrix::new([1.0, 0.0], 1, 2);
+        let mut next = Matrix::new([0.0, 0.0], 1, 2);
+        for _ in 0..50 {
+            multiply_into(&state, &transition, &mut next)?;
+            std::mem::swap(&mut state, &mut next);
+        }
+        assert!((state.data[0] - 1.0 / 3.0).abs() < 1e-9);
+        assert!((state.data[1] - 2.0 / 3.0).abs() < 1e-9);
+
+        let mut wrong = Matrix::new([0.0; 4], 2, 2);
+        let err = multiply_into(&state, &transition, &mut wrong).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Output matrix dimensions do not match, expected 1x2, got 2x2"
+        );
+
+        // a.col != b.row 时 ColMajor 的 out 不能被改成 RowMajor
+        let mut col_major = Matrix::with_layout([1.0, 2.0], 1, 2, Layout::ColMajor);
+        assert!(multiply_into(&state, &state, &mut col_major).is_err());
+        assert_eq!(col_major.layout, Layout::ColMajor);
+        assert_eq!(col_major.data, vec![1.0, 2.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_acc() -> Result<()> {
+        let a = Matrix::new([i32::MAX, i32::MAX, 1, 2], 2, 2);
+        let b = Matrix::new([2, 0, 2, 1], 2, 2);
+        let c: Matrix<i64> = multiply_acc(&a, &b)?;
+        assert_eq!(c.data, vec![4 * i32::MAX as i64, i32::MAX as i64, 6, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_transpose() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let t = a.transpose();
+        assert_eq!((t.row, t.col), (3, 2));
+        assert_eq!(format!("{}", t), "{1 4, 2 5, 3 6}");
+    }
+
+    #[test]
+    fn test_matrix_col_major() -> Result<()> {
+        // 同一个 2x3 矩阵 {1 2 3, 4 5 6} 的两种排列方式
+        let a = Matrix::with_layout([1, 4, 2, 5, 3, 6], 2, 3, Layout::ColMajor);
+        assert_eq!(format!("{}", a), "{1 2 3, 4 5 6}");
+        assert_eq!(a.to_layout(Layout::RowMajor).data, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(a.transpose().data, vec![1, 4, 2, 5, 3, 6]);
+
+        let b = Matrix::with_layout([1, 3, 5, 2, 4, 6], 3, 2, Layout::ColMajor);
+        let c = multiply(&a, &b)?;
+        assert_eq!(c.layout(), Layout::RowMajor);
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+
+        let opts = MultiplyOptions {
+            algorithm: Algorithm::Strassen,
+            ..Default::default()
+        };
+        assert_eq!(multiply_with(&a, &b, &opts)?.data, vec![22, 28, 49, 64]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_checked() -> Result<()> {
+        let a = Matrix::new([1i8, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1i8, 2, 3, 4, 5, 6], 3, 2);
+        let c = multiply_checked(&a, &b)?;
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+
+        // 第二行第二列：4 * 20 + 5 * 20 = 180 > i8::MAX
+        let b = Matrix::new([1i8, 2, 3, 20, 5, 20], 3, 2);
+        let err = multiply_checked(&a, &b).unwrap_err();
+        assert_eq!(err.to_string(), "Integer overflow in output cell (1, 1)");
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::Overflow { row: 1, col: 1 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_bigint() -> Result<()> {
+        use num_bigint::BigInt;
+        // BigInt 不是 Copy 的；1e20 已经超出了 i64 的范围
+        let big = BigInt::from(10).pow(20);
+        let a = Matrix::new([1, 2, 3, 4].map(|v| BigInt::from(v) * &big).to_vec(), 2, 2);
+        let b = Matrix::new([1, 2, 3, 4].map(BigInt::from).to_vec(), 2, 2);
+        let expected = [7, 10, 15, 22].map(|v| BigInt::from(v) * &big).to_vec();
+        assert_eq!(multiply(&a, &b)?.data, expected);
+
+        let opts = MultiplyOptions {
+            algorithm: Algorithm::Strassen,
+            ..Default::default()
+        };
+        assert_eq!(multiply_with(&a, &b, &opts)?.data, expected);
+        assert_eq!(multiply_checked(&a, &b)?.data, expected);
+        assert_eq!(a.sum(), BigInt::from(10) * &big);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_complex() -> Result<()> {
+        let c = |re: f64, im: f64| Complex::new(re, im);
+        let a = Matrix::new([c(1.0, 1.0), c(0.0, 2.0), c(3.0, 0.0), c(1.0, -1.0)], 2, 2);
+        let b = Matrix::new([c(2.0, 0.0), c(0.0, 1.0), c(1.0, 1.0), c(-1.0, 0.0)], 2, 2);
+        let expected = vec![c(0.0, 4.0), c(-1.0, -1.0), c(8.0, 0.0), c(-1.0, 4.0)];
+        assert_eq!(multiply(&a, &b)?.data, expected);
+        let opts = MultiplyOptions {
+            algorithm: Algorithm::Strassen,
+            sequential_threshold: 0,
+            ..Default::default()
+        };
+        assert_eq!(multiply_with(&a, &b, &opts)?.data, expected);
+
+        let h = a.adjoint();
+        assert_eq!((h.row, h.col), (2, 2));
+        assert_eq!(
+            h.data,
+            vec![c(1.0, -1.0), c(3.0, 0.0), c(0.0, -2.0), c(1.0, 1.0)]
+        );
+        // (AB)^H = B^H A^H
+        let ab_h = multiply(&a, &b)?.adjoint();
+        assert_eq!(multiply(&b.adjoint(), &h)?.data, ab_h.data);
+
+        let r = Matrix::new([c(1.0, 2.0), c(3.0, -4.0), c(5.0, 6.0)], 1, 3).adjoint();
+        assert_eq!((r.row, r.col), (3, 1));
+        assert_eq!(format!("{}", r), "{1-2i, 3+4i, 5-6i}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_batch() -> Result<()> {
+        let a1 = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b1 = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+        let a2 = Matrix::new([1, 2, 3, 4], 2, 2);
+        // 150 行，会被切成多批
+        let a3 = Matrix::new((0..450).map(|v| v % 13 - 6).collect::<Vec<i64>>(), 150, 3);
+        let b3 = Matrix::with_layout((0..12).collect::<Vec<i64>>(), 3, 4, Layout::ColMajor);
+        let empty = Matrix::new(Vec::<i32>::new(), 2, 0);
+        let empty_b = Matrix::new(Vec::<i32>::new(), 0, 3);
+
+        let results = multiply_batch(&[(&a1, &b1), (&a1, &a2), (&a2, &a2), (&empty, &empty_b)]);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().data, vec![22, 28, 49, 64]);
+        assert!(matches!(
+            results[1].as_ref().unwrap_err().downcast_ref(),
+            Some(Error::DimensionMismatch { .. })
+        ));
+        assert_eq!(results[2].as_ref().unwrap().data, vec![7, 10, 15, 22]);
+        assert_eq!(results[3].as_ref().unwrap().data, vec![0; 6]);
+
+        let results = multiply_batch(&[(&a3, &b3)]);
+        assert_eq!(results[0].as_ref().unwrap().data, multiply(&a3, &b3)?.data);
+        assert!(multiply_batch::<i32>(&[]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_auto_unsigned() -> Result<()> {
+        // 达到 STRASSEN_THRESHOLD 的无符号方阵：Strassen 的 A11 - A22 会下溢，Auto 必须选 Naive
+        let n = STRASSEN_THRESHOLD;
+        let a = Matrix::new((0..n * n).map(|v| (v % 3) as u32).collect::<Vec<_>>(), n, n);
+        let b = Matrix::new((0..n * n).map(|v| (v % 5) as u32).collect::<Vec<_>>(), n, n);
+        let c = multiply_with(&a, &b, &MultiplyOptions::default())?;
+        // 抽查几个单元，和直接算的点积比较
+        for (i, j) in [(0, 0), (1, 7), (n - 1, n - 1), (300, 11)] {
+            let expected = (0..n)
+                .map(|k| a.data[i * n + k] * b.data[k * n + j])
+                .sum::<u32>();
+            assert_eq!(c.data[i * n + j], expected);
+        }
+        assert!(signed_or_float::<f64>() && signed_or_float::<i32>());
+        assert!(!signed_or_float::<u64>() && !signed_or_float::<usize>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_batch_panic() {
+        // 乘到 -1 就 panic 的元素类型，模拟 worker 在计算中途 panic
+        #[derive(Debug, Clone, Copy, Default, PartialEq)]
+        struct Boom(i32);
+        impl Mul for Boom {
+            type Output = Boom;
+            fn mul(self, rhs: Boom) -> Boom {
+                if self.0 == -1 || rhs.0 == -1 {
+                    panic!("boom");
+                }
+                Boom(self.0 * rhs.0)
+            }
+        }
+        impl Add for Boom {
+            type Output = Boom;
+            fn add(self, rhs: Boom) -> Boom {
+                Boom(self.0 + rhs.0)
+            }
+        }
+        impl AddAssign for Boom {
+            fn add_assign(&mut self, rhs: Boom) {
+                self.0 += rhs.0;
+            }
+        }
+
+        let a = Matrix::new([Boom(1), Boom(2), Boom(3), Boom(4)], 2, 2);
+        let bad_shape = Matrix::new([Boom(1), Boom(2)], 1, 2);
+        let bomb = Matrix::new([Boom(-1), Boom(1), Boom(1), Boom(1)], 2, 2);
+        let results = multiply_batch(&[(&a, &bad_shape), (&bomb, &a), (&a, &a)]);
+        assert!(matches!(
+            results[0].as_ref().err().unwrap().downcast_ref(),
+            Some(Error::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            results[1].as_ref().err().unwrap().downcast_ref(),
+            Some(Error::WorkerPanicked { message, .. }) if message == "boom"
+        ));
+        assert_eq!(
+            results[2].as_ref().ok().unwrap().data,
+            [Boom(7), Boom(10), Boom(15), Boom(22)]
+        );
+    }
+
+    #[test]
+    fn test_matrix_multiply_gpu_backend() -> Result<()> {
+        // 没有 GPU（或者没开 gpu feature）时回退到 CPU，结果应该一样
+        let a = Matrix::new((0..35).map(|v| v as f32 * 0.5).collect::<Vec<_>>(), 7, 5);
+        let b = Matrix::new((0..45).map(|v| v as f32 - 20.0).collect::<Vec<_>>(), 5, 9);
+        let opts = MultiplyOptions {
+            backend: Backend::Gpu,
+            ..Default::default()
+        };
+        assert_eq!(multiply_with(&a, &b, &opts)?.data, multiply(&a, &b)?.data);
+
+        // 非 f32 的矩阵总是走 CPU
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        assert_eq!(multiply_with(&a, &a, &opts)?.data, vec![7, 10, 15, 22]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_to_markdown_latex() {
+        let a = Matrix::with_layout([1, 4, 2, 5, 3, 6], 2, 3, Layout::ColMajor);
+        assert_eq!(
+            a.to_markdown(),
+            "| 0 | 1 | 2 |\n|---|---|---|\n| 1 | 2 | 3 |\n| 4 | 5 | 6 |\n"
+        );
+        assert_eq!(
+            a.to_latex(),
+            "\\begin{bmatrix}\n1 & 2 & 3 \\\\\n4 & 5 & 6\n\\end{bmatrix}"
+        );
+    }
+
+    #[test]
+    fn test_matrix_iter_indexed() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let c = multiply(&a, &a.transpose())?;
+        let above = c
+            .iter_indexed()
+            .filter(|&(_, _, &v)| v > 30)
+            .map(|(i, j, &v)| (i, j, v))
+            .collect::<Vec<_>>();
+        assert_eq!(above, vec![(0, 1, 32), (1, 0, 32), (1, 1, 77)]);
+
+        let c = Matrix::with_layout([1, 3, 2, 4], 2, 2, Layout::ColMajor);
+        assert_eq!(
+            c.iter_indexed().collect::<Vec<_>>(),
+            vec![(0, 0, &1), (0, 1, &2), (1, 0, &3), (1, 1, &4)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_seq() -> Result<()> {
+        let a = Matrix::new((0..35).collect::<Vec<i64>>(), 7, 5);
+        let b = Matrix::with_layout(
+            (0..45).map(|v| v - 20).collect::<Vec<i64>>(),
+            5,
+            9,
+            Layout::ColMajor,
+        );
+        assert_eq!(multiply_seq(&a, &b)?.data, multiply(&a, &b)?.data);
+        assert!(multiply_seq(&a, &a).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_deterministic() -> Result<()> {
+        let (m, k, n) = (37, 300, 23);
+        let a = Matrix::new(
+            (0..m * k)
+                .map(|v| (v as f64).sin() * 1e3)
+                .collect::<Vec<_>>(),
+            m,
+            k,
+        );
+        let b = Matrix::new(
+            (0..k * n)
+                .map(|v| (v as f64).cos() / 7.0)
+                .collect::<Vec<_>>(),
+            k,
+            n,
+        );
+        let expected = multiply_seq(&a, &b)?;
+        for (block_size, sequential_threshold) in [(1, 0), (5, 0), (64, 0), (64, usize::MAX)] {
+            let opts = MultiplyOptions {
+                block_size,
+                sequential_threshold,
+                deterministic: true,
+                backend: Backend::Gpu,
+                ..Default::default()
+            };
+            let c = multiply_with(&a, &b, &opts)?;
+            let bits = |m: &Matrix<f64>| m.data.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&c), bits(&expected));
+        }
+        Ok(())
+    }
+
     #[test]
     #[should_panic]
     fn test_a_can_not_multiply_b_panic() {
@@ -1,17 +1,51 @@
 use std::{
     collections::HashMap,
     fmt,
+    ops::Deref,
+    path::Path,
     sync::{
         atomic::{AtomicI64, Ordering},
-        Arc,
+        Arc, RwLock,
     },
 };
 
 use anyhow::{anyhow, Result};
 
+use super::{
+    backend::{MetricsBackend, Snapshot},
+    key::Key,
+    metadata::{Descriptions, Metadata},
+    overflow::{Op, OverflowPolicy},
+    persist, prometheus,
+    table::FormatOptions,
+    timer::{as_micros, Timer},
+};
+
 #[derive(Debug)]
 pub struct AmapMetrics {
     data: Arc<HashMap<&'static str, AtomicI64>>, // 因为 Arc 实现了 send 和 sync，所以可以跨线程共享
+    // 运行时通过 register 加进来的 key。new 时给出的 key 仍然只查 data，不需要拿锁；
+    // 只有查不到的 key 才会来这里拿读锁
+    registered: Arc<RwLock<HashMap<&'static str, Arc<AtomicI64>>>>,
+    overflow: OverflowPolicy,
+    meta: Descriptions,
+}
+
+// counter() 的返回值：固定的 key 直接借用 data 里的 atomic，运行时注册的 key 拿一份 Arc，不用一直持有读锁
+enum Counter<'a> {
+    Fixed(&'a AtomicI64),
+    Registered(Arc<AtomicI64>),
+}
+
+impl Deref for Counter<'_> {
+    type Target = AtomicI64;
+
+    fn deref(&self) -> &AtomicI64 {
+        match self {
+            Counter::Fixed(c) => c,
+            Counter::Registered(c) => c,
+        }
+    }
 }
 // Suitable for scenarios where you have a fixed set of keys known at compile time and need to perform frequent concurrent updates to the values.
 // Example: A metrics system where the keys are predefined metric names, and the values are counters that are incremented by multiple threads.
@@ -32,7 +66,29 @@ impl AmapMetrics {
             .collect();
         AmapMetrics {
             data: Arc::new(map),
+            registered: Default::default(),
+            overflow: OverflowPolicy::default(),
+            meta: Descriptions::default(),
+        }
+    }
+
+    // 默认溢出时 wrap；需要 saturate 或者报错时在创建的时候指定
+    pub fn with_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    // 运行时新增一个 key，所有 clone 出来的 AmapMetrics 都能看到；key 已经存在时返回 false
+    pub fn register(&self, name: &'static str) -> bool {
+        if self.data.contains_key(name) {
+            return false;
         }
+        let mut registered = self.registered.write().unwrap_or_else(|e| e.into_inner());
+        if registered.contains_key(name) {
+            return false;
+        }
+        registered.insert(name, Arc::new(AtomicI64::new(0)));
+        true
     }
 
     // AsRef is a trait in Rust's standard library that provides a way to convert a value to a reference of another type.
@@ -42,29 +98,407 @@ impl AmapMetrics {
     // how many other types can be converted to &str?
     // The AsRef trait is implemented for many types in Rust, including String, &str, Path, and OsStr.
     pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        self.inc_by(key, 1)
+    }
+
+    // 与 inc 相反，用于 "当前打开的连接数" 这类可以减少的 gauge
+    pub fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        self.dec_by(key, 1)
+    }
+
+    // 一次加上任意的增量，比如收到的字节数、一批的大小；比循环调用 inc 少很多次原子操作
+    pub fn inc_by(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
         let key = key.as_ref();
-        let counter = self
-            .data
-            .get(key)
-            .ok_or_else(|| anyhow!("key {} not found", key))?;
-        counter.fetch_add(1, Ordering::Relaxed); // fetch_add 是读，load 是写
+        // Wrap 策略下就是一次 fetch_add
+        self.overflow
+            .apply_atomic(key, &*self.counter(key)?, delta, Op::Add)
+    }
+
+    pub fn dec_by(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
+        let key = key.as_ref();
+        self.overflow
+            .apply_atomic(key, &*self.counter(key)?, delta, Op::Sub)
+    }
+
+    // 带 label 的 key 也必须在 new 或者 register 的时候注册，写法与 Key 的 Display 一致，比如 r#"req{method="GET",page="4"}"#
+    pub fn inc_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Result<()> {
+        self.inc(Key::new(name, labels).to_string())
+    }
+
+    // 记录峰值（最大队列长度、最大延迟）：fetch_max 直接在 atomic 上比较并替换，不需要额外的锁
+    pub fn update_max(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        self.counter(key.as_ref())?
+            .fetch_max(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // 用 update_min 记录最小值时，key 的初始值应该先 set 成 i64::MAX
+    pub fn update_min(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        self.counter(key.as_ref())?
+            .fetch_min(value, Ordering::Relaxed);
         Ok(())
     }
+
+    // 读一个计数器不需要 snapshot 整个 map；没有注册过的 key 返回 None
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.counter(key.as_ref())
+            .ok()
+            .map(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        self.counter(key.as_ref())?.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // 返回一个计时器，drop 时把经过的微秒数加到 key 上；key 没有注册过时直接返回错误，而不是在 drop 时悄悄丢掉
+    pub fn timer(&self, key: impl AsRef<str>) -> Result<Timer> {
+        let key = key.as_ref().to_string();
+        self.counter(&key)?;
+        let metrics = self.clone();
+        Ok(Timer::new(move |elapsed| {
+            let _ = metrics.inc_by(&key, as_micros(elapsed));
+        }))
+    }
+
+    // 把 other 的每个值加到同名的 key 上，比如 fork-join 的每个 worker 各自计数，最后合并到一起。
+    // other 里有、这里没有的 key 会先 register
+    pub fn merge(&self, other: &AmapMetrics) -> Result<()> {
+        for (key, value) in other.iter() {
+            self.register(key);
+            self.inc_by(key, value)?;
+        }
+        Ok(())
+    }
+
+    // 所有计数器清零；key 只能增加、不能删除，所以没有 clear
+    pub fn reset(&self) {
+        self.for_each(|_, value| value.store(0, Ordering::Relaxed));
+    }
+
+    // 读出当前值并清零：每个 key 是一次 swap，所以 drain 和并发的 inc 之间不会丢失、也不会重复计数。
+    // 给推送增量的 exporter 用，比 snapshot 之后再 reset 安全
+    pub fn drain(&self) -> Snapshot {
+        let mut drained = Snapshot::with_capacity(self.data.len());
+        self.for_each(|key, value| {
+            drained.insert(key.to_string(), value.swap(0, Ordering::Relaxed));
+        });
+        drained
+    }
+
+    // 逐个 load 每个 atomic：不同 key 之间不是同一时刻的值，但每个值本身都是完整的
+    pub fn snapshot(&self) -> HashMap<&'static str, i64> {
+        self.values().into_iter().collect()
+    }
+
+    // 写成 JSON 文件，重启之后用 load 恢复
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        persist::save(path.as_ref(), &MetricsBackend::snapshot(self))
+    }
+
+    // 把文件里的值覆盖到对应的 key 上；文件里有、但这里没有注册的 key 会被忽略，
+    // 因为两次部署之间 key 的集合可能变了
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<()> {
+        for (key, value) in persist::load(path.as_ref())? {
+            if let Ok(counter) = self.counter(&key) {
+                counter.store(value, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    // 遍历所有 key（包括 register 的）和当前值，可以直接 filter / sum，不需要先 snapshot 成 HashMap。
+    // 值是先 load 出来的，遍历的过程中不持有任何锁
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, i64)> {
+        self.values().into_iter()
+    }
+
+    // 给 Prometheus 抓取用的文本格式，Display 的输出是给人看的
+    pub fn prometheus_encode(&self) -> String {
+        prometheus::encode_with(
+            self.values()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+            |name| self.meta.get(name),
+        )
+    }
+
+    // 登记 name 的说明和单位，所有带 label 的 key 共用；可以在 register 之前或之后调用
+    pub fn describe(&self, name: &str, help: &str, unit: Option<&str>) {
+        self.meta.describe(name, help, unit);
+    }
+
+    // key 必须在 new 或者 register 的时候就给出，这里只是查找，不会插入
+    fn counter(&self, key: &str) -> Result<Counter<'_>> {
+        if let Some(counter) = self.data.get(key) {
+            return Ok(Counter::Fixed(counter));
+        }
+        self.registered
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .map(|counter| Counter::Registered(counter.clone()))
+            .ok_or_else(|| anyhow!("key {} not found", key))
+    }
+
+    fn for_each(&self, mut f: impl FnMut(&'static str, &AtomicI64)) {
+        for (&key, value) in self.data.iter() {
+            f(key, value);
+        }
+        let registered = self.registered.read().unwrap_or_else(|e| e.into_inner());
+        for (&key, value) in registered.iter() {
+            f(key, value);
+        }
+    }
+
+    fn values(&self) -> Vec<(&'static str, i64)> {
+        let mut values = Vec::with_capacity(self.data.len());
+        self.for_each(|key, value| values.push((key, value.load(Ordering::Relaxed))));
+        values
+    }
+}
+
+impl MetricsBackend for AmapMetrics {
+    fn inc(&self, key: &str) -> Result<()> {
+        AmapMetrics::inc(self, key)
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        AmapMetrics::dec(self, key)
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        AmapMetrics::get(self, key)
+    }
+
+    fn snapshot(&self) -> HashMap<String, i64> {
+        MetricsBackend::iter(self).collect()
+    }
+
+    fn metadata(&self, key: &str) -> Option<Metadata> {
+        self.meta.get(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_> {
+        Box::new(AmapMetrics::iter(self).map(|(key, value)| (key.to_string(), value)))
+    }
 }
 
 impl Clone for AmapMetrics {
     fn clone(&self) -> Self {
         AmapMetrics {
             data: Arc::clone(&self.data),
+            registered: Arc::clone(&self.registered),
+            overflow: self.overflow,
+            meta: self.meta.clone(),
         }
     }
 }
 
 impl fmt::Display for AmapMetrics {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (key, value) in self.data.iter() {
-            writeln!(f, "{}: {}", key, value.load(Ordering::Relaxed))?; // fetch_add 是读，load 是写
+        if f.alternate() {
+            return f.write_str(&self.format_with(&FormatOptions::default()));
+        }
+        for (key, value) in self.values() {
+            writeln!(f, "{}: {}", key, value)?; // fetch_add 是读，load 是写
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_amap_register() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req"]);
+        let other = metrics.clone();
+        assert!(metrics.inc("late").is_err());
+        assert!(metrics.register("late"));
+        assert!(!metrics.register("late"));
+        assert!(!metrics.register("req"));
+        // clone 出来的 handle 共享同一个注册表
+        other.inc_by("late", 3)?;
+        metrics.inc("req")?;
+        assert_eq!(metrics.get("late"), Some(3));
+        assert_eq!(metrics.snapshot(), HashMap::from([("req", 1), ("late", 3)]));
+        metrics.reset();
+        assert_eq!(other.get("late"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_poisoned_lock() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req"]);
+        metrics.register("late");
+        // 某个线程拿着 registered 的写锁 panic，锁被 poison
+        let registered = Arc::clone(&metrics.registered);
+        let result = thread::spawn(move || {
+            let _guard = registered.write().unwrap();
+            panic!("worker panicked while holding the lock");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(metrics.registered.is_poisoned());
+
+        // metrics 仍然可以正常使用，不会因为一次 panic 永久失效
+        metrics.inc("late")?;
+        assert!(metrics.register("later"));
+        assert_eq!(metrics.get("late"), Some(1));
+        assert_eq!(metrics.to_string().lines().count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_drain() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req", "late"]);
+        metrics.inc("req")?;
+        metrics.inc_by("late", 3)?;
+        assert_eq!(
+            metrics.drain(),
+            Snapshot::from([("req".to_string(), 1), ("late".to_string(), 3)])
+        );
+        assert_eq!(metrics.get("late"), Some(0));
+        // 清零之后 key 仍然注册着，可以继续写
+        metrics.inc("late")?;
+        assert_eq!(metrics.drain()["late"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_merge() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req"]);
+        let worker = AmapMetrics::new(&["req", "late"]);
+        worker.inc_by("req", 2)?;
+        worker.inc("late")?;
+        metrics.inc("req")?;
+        metrics.merge(&worker)?;
+        assert_eq!(metrics.get("req"), Some(3));
+        // 只在 worker 里注册过的 key 会注册到 metrics 里
+        assert_eq!(metrics.get("late"), Some(1));
+        assert_eq!(worker.get("req"), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_max_min() -> Result<()> {
+        let metrics = AmapMetrics::new(&["queue.max", "latency.min"]);
+        metrics.set("latency.min", i64::MAX)?;
+        let handles = (0..4)
+            .map(|t| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        metrics.update_max("queue.max", i * 4 + t)?;
+                        metrics.update_min("latency.min", 5000 - i * 4 - t)?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert_eq!(metrics.get("queue.max"), Some(3999));
+        assert_eq!(metrics.get("latency.min"), Some(1001));
+        assert!(metrics.update_max("unknown", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_inc_dec() -> Result<()> {
+        let metrics = AmapMetrics::new(&["conn"]);
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        metrics.inc("conn")?;
+                    }
+                    for _ in 0..40 {
+                        metrics.dec("conn")?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert_eq!(metrics.to_string(), "conn: 240\n");
+        assert!(metrics.dec("unknown").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_inc_by() -> Result<()> {
+        let metrics = AmapMetrics::new(&["conn"]);
+        metrics.inc_by("conn", 1240)?;
+        metrics.dec_by("conn", 240)?;
+        assert_eq!(metrics.to_string(), "conn: 1000\n");
+        assert!(metrics.inc_by("unknown", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_get_set() -> Result<()> {
+        let metrics = AmapMetrics::new(&["conn"]);
+        metrics.set("conn", 7)?;
+        assert_eq!(metrics.get("conn"), Some(7));
+        assert_eq!(metrics.to_string(), "conn: 7\n");
+        assert_eq!(metrics.get("unknown"), None);
+        assert!(metrics.set("unknown", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_snapshot() -> Result<()> {
+        let metrics = AmapMetrics::new(&["conn", "req"]);
+        metrics.inc_by("conn", 1000)?;
+        assert_eq!(
+            metrics.snapshot(),
+            HashMap::from([("conn", 1000), ("req", 0)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_iter() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req", "late"]);
+        metrics.inc("req")?;
+        metrics.inc_by("late", 3)?;
+        assert_eq!(metrics.iter().filter(|&(_, v)| v > 1).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_reset() -> Result<()> {
+        let metrics = AmapMetrics::new(&["conn"]);
+        metrics.inc("conn")?;
+        // key 集合是固定的，reset 只清零
+        metrics.reset();
+        assert_eq!(metrics.get("conn"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_timer() {
+        let metrics = AmapMetrics::new(&["req.us"]);
+        assert!(metrics.timer("req.us").is_ok());
+        assert!(metrics.timer("unknown").is_err());
+    }
+
+    #[test]
+    fn test_amap_labels() -> Result<()> {
+        let metrics = AmapMetrics::new(&[r#"req{method="GET",page="4"}"#]);
+        metrics.inc_with_labels("req", &[("page", "4"), ("method", "GET")])?;
+        assert_eq!(metrics.to_string(), "req{method=\"GET\",page=\"4\"}: 1\n");
+        assert!(metrics
+            .inc_with_labels("req", &[("page", "5"), ("method", "GET")])
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+// 标准库没有 AtomicF64：把 f64 按 to_bits 存进 AtomicU64，load/store 直接转换，
+// fetch_add 这类读-改-写的操作用 CAS 循环。读写都不需要锁。
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Default)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub fn new(value: f64) -> Self {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    pub fn load(&self, order: Ordering) -> f64 {
+        f64::from_bits(self.0.load(order))
+    }
+
+    pub fn store(&self, value: f64, order: Ordering) {
+        self.0.store(value.to_bits(), order);
+    }
+
+    pub fn swap(&self, value: f64, order: Ordering) -> f64 {
+        f64::from_bits(self.0.swap(value.to_bits(), order))
+    }
+
+    // 返回加之前的值；并发写很多的时候 CAS 会重试，但不会丢掉任何一次 add
+    pub fn fetch_add(&self, delta: f64, order: Ordering) -> f64 {
+        self.fetch_update(order, |v| v + delta)
+    }
+
+    // f64::max 会忽略 NaN，所以 NaN 不会把最大值 "污染" 掉
+    pub fn fetch_max(&self, value: f64, order: Ordering) -> f64 {
+        self.fetch_update(order, |v| v.max(value))
+    }
+
+    pub fn fetch_min(&self, value: f64, order: Ordering) -> f64 {
+        self.fetch_update(order, |v| v.min(value))
+    }
+
+    fn fetch_update(&self, order: Ordering, f: impl Fn(f64) -> f64) -> f64 {
+        // 闭包总是返回 Some，所以 fetch_update 不会失败
+        let bits = self
+            .0
+            .fetch_update(order, Ordering::Relaxed, |bits| {
+                Some(f(f64::from_bits(bits)).to_bits())
+            })
+            .unwrap_or_else(|bits| bits);
+        f64::from_bits(bits)
+    }
+}
+
+impl fmt::Debug for AtomicF64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_atomic_f64() {
+        let value = Arc::new(AtomicF64::new(0.0));
+        let handles = (0..4)
+            .map(|_| {
+                let value = value.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        value.fetch_add(0.5, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("worker panicked");
+        }
+        assert_eq!(value.load(Ordering::Relaxed), 2000.0);
+        assert_eq!(value.fetch_max(3e6, Ordering::Relaxed), 2000.0);
+        assert_eq!(value.swap(1.5, Ordering::Relaxed), 3e6);
+        assert_eq!(value.fetch_min(f64::NAN, Ordering::Relaxed), 1.5);
+        assert_eq!(format!("{:?}", value), "1.5");
+    }
+}
@@ -0,0 +1,144 @@
+// 所有 metrics backend 共同的接口：应用代码和 worker 只写一次，就可以换不同的 backend 做 benchmark。
+// key 统一用 &str；各个 backend 自己的 inherent 方法仍然可以接受 String、Key 之类的参数。
+use anyhow::Result;
+use std::collections::HashMap;
+
+use super::{
+    inflight::InFlight,
+    metadata::Metadata,
+    scoped::Scoped,
+    table::{self, FormatOptions},
+};
+
+// 某一时刻所有 key 的值
+pub type Snapshot = HashMap<String, i64>;
+
+// Snapshot 只是一个 HashMap 的别名，不能直接加方法，所以用一个 trait 给它加上 diff
+pub trait SnapshotDiff {
+    // 和更早的 earlier 相比每个 key 的增量，给 statsd 这类推送增量的 exporter 用。
+    // 新出现的 key 从 0 算起，消失的 key 按减到 0 算；没有变化的 key 不出现在结果里
+    fn diff(&self, earlier: &Snapshot) -> Snapshot;
+}
+
+impl SnapshotDiff for Snapshot {
+    fn diff(&self, earlier: &Snapshot) -> Snapshot {
+        let changed = self.iter().map(|(key, &value)| {
+            let before = earlier.get(key).copied().unwrap_or(0);
+            (key.clone(), value.wrapping_sub(before))
+        });
+        let removed = earlier
+            .iter()
+            .filter(|(key, _)| !self.contains_key(*key))
+            .map(|(key, &value)| (key.clone(), value.wrapping_neg()));
+        changed
+            .chain(removed)
+            .filter(|&(_, delta)| delta != 0)
+            .collect()
+    }
+}
+
+pub trait MetricsBackend {
+    fn inc(&self, key: &str) -> Result<()>;
+    fn dec(&self, key: &str) -> Result<()>;
+    fn get(&self, key: &str) -> Option<i64>;
+    fn snapshot(&self) -> Snapshot;
+    // 遍历时每个值都是 copy 出来的，不会一直持有锁
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_>;
+
+    // 按 opts 排成一张对齐的表；Display 的 `{:#}` 就是用默认的 FormatOptions 调用它
+    fn format_with(&self, opts: &FormatOptions) -> String {
+        table::render(self.iter(), opts, |key| {
+            self.metadata(key).and_then(|m| m.unit)
+        })
+    }
+
+    // key 所属的 name 登记过的说明和单位，key 可以带 label
+    fn metadata(&self, _key: &str) -> Option<Metadata> {
+        None
+    }
+
+    // 创建时 inc(key)，返回的 guard drop 时 dec(key)
+    fn in_flight(&self, key: &str) -> Result<InFlight<Self>>
+    where
+        Self: Clone + Sized,
+    {
+        InFlight::new(self.clone(), key)
+    }
+
+    // 共享同一个 backend、自动给 key 加上 prefix 的 handle
+    fn scoped(&self, prefix: &str) -> Scoped<Self>
+    where
+        Self: Clone + Sized,
+    {
+        Scoped::new(self.clone(), prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics};
+    use std::thread;
+
+    // 同一个 worker 跑在不同的 backend 上
+    fn run_workers<M: MetricsBackend + Clone + Send + 'static>(metrics: M) -> Result<()> {
+        let handles = (0..4)
+            .map(|i| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        metrics.inc("req")?;
+                    }
+                    metrics.dec(&format!("worker.{}", i % 2))
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_backend() -> Result<()> {
+        let expected = HashMap::from([
+            ("req".to_string(), 200),
+            ("worker.0".to_string(), -2),
+            ("worker.1".to_string(), -2),
+        ]);
+
+        let amap = AmapMetrics::new(&["req", "worker.0", "worker.1"]);
+        run_workers(amap.clone())?;
+        assert_eq!(MetricsBackend::snapshot(&amap), expected);
+        assert_eq!(MetricsBackend::get(&amap, "req"), Some(200));
+
+        let cmap = CmapMetrics::new();
+        run_workers(cmap.clone())?;
+        assert_eq!(MetricsBackend::snapshot(&cmap), expected);
+        assert_eq!(cmap.iter().collect::<HashMap<_, _>>(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let earlier = Snapshot::from([
+            ("req".to_string(), 100),
+            ("idle".to_string(), 3),
+            ("gone".to_string(), 5),
+        ]);
+        let current = Snapshot::from([
+            ("req".to_string(), 150),
+            ("idle".to_string(), 3),
+            ("new".to_string(), 10),
+        ]);
+        assert_eq!(
+            current.diff(&earlier),
+            Snapshot::from([
+                ("req".to_string(), 50),
+                ("new".to_string(), 10),
+                ("gone".to_string(), -5),
+            ])
+        );
+        assert!(current.diff(&current).is_empty());
+    }
+}
@@ -3,13 +3,31 @@
 // functionality: inc/dec/snapshot
 use anyhow::Result;
 use std::{
-    // collections::HashMap, // 用 dashmap 代替 HashMap
+    collections::HashMap, // DashMap 存数据，HashMap 只用来返回 snapshot
     fmt,
+    path::Path,
     // sync::{Arc, RwLock}, // 用 RwLock 替换 Mutex，后者不区分 read 和 write，前者区分 read 和 write
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use dashmap::DashMap;
+use rand::Rng;
+
+use super::{
+    backend::{MetricsBackend, Snapshot},
+    key::Key,
+    metadata::{Descriptions, Metadata},
+    overflow::{Op, OverflowPolicy},
+    persist, prometheus,
+    table::FormatOptions,
+    timer::{as_micros, Timer},
+};
 
 // 本例中，
 // 如果你的代码中的数据是 HashMap，又是在多线程中共享，那么你可以考虑使用 DashMap 来替换 HashMap。
@@ -65,6 +83,57 @@ use dashmap::DashMap;
 #[derive(Debug, Clone)]
 pub struct CmapMetrics {
     data: Arc<DashMap<String, i64>>,
+    // 设置了 TTL 的 key：超过 ttl 没有被写过就删掉。大部分 key 没有 TTL，所以单独放一个 map
+    expiry: Arc<DashMap<String, Expiry>>,
+    watchers: Arc<DashMap<String, Vec<Arc<Watch>>>>,
+    overflow: OverflowPolicy,
+    // key 的个数上限，防止 label 爆炸（比如每个客户端 ip 一个 key）让 map 无限增长
+    limit: Option<KeyLimit>,
+    // 只在 EvictionPolicy::Lru 时使用：每个 key 最后一次写入的时间
+    last_used: Arc<DashMap<String, Instant>>,
+    // 因为超过上限而被拒绝或者被淘汰的 key 的次数
+    dropped: Arc<AtomicU64>,
+    meta: Descriptions,
+}
+
+// key 的个数达到上限之后，新的 key 怎么处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    // 丢掉新 key 的写入，已有的 key 不受影响
+    RejectNew,
+    // 删掉最久没有写过的 key，给新 key 腾位置
+    Lru,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KeyLimit {
+    max: usize,
+    policy: EvictionPolicy,
+}
+
+// 值从下方越过 threshold 时调用一次 callback；rearm 为 None 时只触发一次，
+// 否则要等值回落到 rearm 以下才会再次触发（hysteresis，避免在阈值附近来回抖动时反复报警）
+struct Watch {
+    threshold: i64,
+    rearm: Option<i64>,
+    armed: AtomicBool,
+    callback: Box<dyn Fn(i64) + Send + Sync>,
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("threshold", &self.threshold)
+            .field("rearm", &self.rearm)
+            .field("armed", &self.armed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Expiry {
+    ttl: Duration,
+    deadline: Instant,
 }
 // Suitable for scenarios where the set of keys is dynamic and can change at runtime.
 // Example: A cache where the keys are dynamically generated strings, and the values are accessed and modified by multiple threads.
@@ -73,9 +142,33 @@ impl CmapMetrics {
     pub fn new() -> CmapMetrics {
         CmapMetrics {
             data: Arc::new(DashMap::new()),
+            expiry: Arc::new(DashMap::new()),
+            watchers: Arc::new(DashMap::new()),
+            overflow: OverflowPolicy::default(),
+            limit: None,
+            last_used: Arc::new(DashMap::new()),
+            dropped: Arc::new(AtomicU64::new(0)),
+            meta: Descriptions::default(),
         }
     }
 
+    // 最多保留 max 个 key。多个线程同时写新 key 时，key 的个数可能短暂地超过 max 几个
+    pub fn with_max_keys(mut self, max: usize, policy: EvictionPolicy) -> Self {
+        self.limit = Some(KeyLimit { max, policy });
+        self
+    }
+
+    // 因为超过 key 的上限被拒绝（RejectNew）或者被淘汰（Lru）的次数
+    pub fn dropped_keys(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    // 默认溢出时 wrap；需要 saturate 或者报错时在创建的时候指定
+    pub fn with_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
     // data.entry
     // data is a HashMap<String, i64>. // literally, data is a Mutex<HashMap<String, i64>> which implements Deref trait.
     // data.entry(key) accesses the entry for the given key in the HashMap.
@@ -89,17 +182,333 @@ impl CmapMetrics {
         // let mut data = self.data.lock().map_err(|e| anyhow!(e.to_string()))?; // MutexGuard<HashMap<String, i64>>
         // let mut data = self.data.write().map_err(|e| anyhow!(e.to_string()))?; // RwLock 区分 read 和 write
         // let counter = data.entry(key.into()).or_insert(0);
-        let mut counter = self.data.entry(key.into()).or_insert(0); // 所有跟 data 和 锁 相关的操作都被封装到了 DashMap 里面
-        *counter += 1;
+        self.inc_by(key, 1)
+    }
+
+    // 与 inc 相反；key 不存在时从 0 开始减，所以 gauge 可以先 dec 再 inc
+    pub fn dec(&self, key: impl Into<String>) -> Result<()> {
+        self.dec_by(key, 1)
+    }
+
+    // 一次加上任意的增量，比如收到的字节数、一批的大小
+    pub fn inc_by(&self, key: impl Into<String>, delta: i64) -> Result<()> {
+        let key = key.into();
+        if !self.admit(&key) {
+            return Ok(());
+        }
+        let mut counter = self.data.entry(key).or_insert(0); // 所有跟 data 和 锁 相关的操作都被封装到了 DashMap 里面
+        *counter = self
+            .overflow
+            .apply(counter.key(), *counter, delta, Op::Add)?;
+        // 先释放 data 的锁再去碰 expiry，两个 map 的锁永远不会交叉持有
+        let (key, value) = (counter.key().clone(), *counter);
+        drop(counter);
+        self.touch(&key);
+        self.notify(&key, value);
         Ok(())
     }
 
-    // pub fn dec(&self, key: impl Into<String>) -> Result<()>  {
-    //     let mut data = self.data.lock().map_err(|e| anyhow!(e.to_string()))?;
-    //     let count = data.entry(key.into()).or_insert(0);
-    //     *count -= 1;
-    //     Ok(())
-    // }
+    pub fn dec_by(&self, key: impl Into<String>, delta: i64) -> Result<()> {
+        let key = key.into();
+        if !self.admit(&key) {
+            return Ok(());
+        }
+        let mut counter = self.data.entry(key).or_insert(0);
+        *counter = self
+            .overflow
+            .apply(counter.key(), *counter, delta, Op::Sub)?;
+        let (key, value) = (counter.key().clone(), *counter);
+        drop(counter);
+        self.touch(&key);
+        self.notify(&key, value);
+        Ok(())
+    }
+
+    // 极热的路径上每次都 inc 开销太大：平均每 rate 次才真正写一次，每次加 rate，
+    // 所以存下来的仍然是总次数的无偏估计。没有被采样到的调用不会分配 String，也不碰 DashMap；
+    // rand::thread_rng 是 thread-local 的，不需要加锁
+    pub fn inc_sampled(&self, key: impl Into<String>, rate: u32) -> Result<()> {
+        if rate <= 1 {
+            return self.inc(key);
+        }
+        if rand::thread_rng().gen_ratio(1, rate) {
+            self.inc_by(key, rate as i64)?;
+        }
+        Ok(())
+    }
+
+    // inc_with_labels("req", &[("page", "4"), ("method", "GET")]) 等价于 inc(r#"req{method="GET",page="4"}"#)
+    pub fn inc_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Result<()> {
+        self.inc(Key::new(name, labels))
+    }
+
+    // 只读一个 key：DashMap 只锁住它所在的 shard，读完立刻释放
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        let key = key.as_ref();
+        // 过期但 sweeper 还没来得及删的 key，在读的时候顺手删掉
+        if self.expire(key, Instant::now()) {
+            return None;
+        }
+        self.data.get(key).map(|v| *v)
+    }
+
+    // 直接覆盖，key 不存在时插入
+    pub fn set(&self, key: impl Into<String>, value: i64) -> Result<()> {
+        let key = key.into();
+        if !self.admit(&key) {
+            return Ok(());
+        }
+        self.data.insert(key.clone(), value);
+        self.touch(&key);
+        self.notify(&key, value);
+        Ok(())
+    }
+
+    // 删除不再需要的 key（比如某个连接断开后它自己的计数器），返回删除前的值
+    pub fn remove(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.expiry.remove(key.as_ref());
+        self.last_used.remove(key.as_ref());
+        self.data.remove(key.as_ref()).map(|(_, v)| v)
+    }
+
+    // 给 key 设置 TTL：超过 ttl 没有 inc/dec/set 就被删除，每次写入都会重新计时。
+    // key 过期删除之后 TTL 也一起删除，同一个 key 再出现时需要重新 set_ttl
+    pub fn set_ttl(&self, key: impl Into<String>, ttl: Duration) {
+        let key = key.into();
+        if !self.admit(&key) {
+            return;
+        }
+        self.data.entry(key.clone()).or_insert(0);
+        self.expiry.insert(
+            key,
+            Expiry {
+                ttl,
+                deadline: Instant::now() + ttl,
+            },
+        );
+    }
+
+    // 删除所有已经过期的 key，返回删除的个数
+    pub fn sweep(&self) -> usize {
+        if self.expiry.is_empty() {
+            return 0;
+        }
+        let now = Instant::now();
+        // 先收集再删除：遍历 expiry 的时候不能同时 remove 它的 entry
+        let expired = self
+            .expiry
+            .iter()
+            .filter(|entry| entry.deadline <= now)
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        expired.iter().filter(|key| self.expire(key, now)).count()
+    }
+
+    // 后台线程每隔 interval 调用一次 sweep。返回的 Sweeper 被 stop 或者 drop 时线程停下来，
+    // 和 MetricsReporter 一样用 recv_timeout 等待，不需要等完整个 interval
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        let metrics = self.clone();
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                metrics.sweep();
+            }
+        });
+        Sweeper {
+            shutdown: Some(tx),
+            handle: Some(handle),
+        }
+    }
+
+    // key 的值达到 threshold 时调用一次 callback(value)，之后不再触发。
+    // callback 在写入的线程里同步执行，此时没有持有任何锁，可以在里面继续读写 metrics
+    pub fn watch<F>(&self, key: impl Into<String>, threshold: i64, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.add_watch(key.into(), threshold, None, callback);
+    }
+
+    // 与 watch 相同，但值回落到 rearm_below 以下之后会重新武装，下次越过 threshold 时再触发
+    pub fn watch_with_hysteresis<F>(
+        &self,
+        key: impl Into<String>,
+        threshold: i64,
+        rearm_below: i64,
+        callback: F,
+    ) where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.add_watch(key.into(), threshold, Some(rearm_below), callback);
+    }
+
+    fn add_watch<F>(&self, key: String, threshold: i64, rearm: Option<i64>, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        // 注册时已经超过阈值的，不补发，等下一次从下方越过
+        let armed = self.data.get(&key).is_none_or(|v| *v < threshold);
+        self.watchers.entry(key).or_default().push(Arc::new(Watch {
+            threshold,
+            rearm,
+            armed: AtomicBool::new(armed),
+            callback: Box::new(callback),
+        }));
+    }
+
+    fn notify(&self, key: &str, value: i64) {
+        if self.watchers.is_empty() {
+            return;
+        }
+        // 先把 watch 拷贝出来再释放 watchers 的锁，callback 里调用 watch 也不会死锁
+        let Some(watches) = self.watchers.get(key).map(|w| w.clone()) else {
+            return;
+        };
+        for watch in watches {
+            if value >= watch.threshold {
+                // swap 保证多个线程同时越过阈值时只有一个会调用 callback
+                if watch.armed.swap(false, Ordering::AcqRel) {
+                    (watch.callback)(value);
+                }
+            } else if watch.rearm.is_some_and(|rearm| value < rearm) {
+                watch.armed.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    // 写入时刷新 deadline；没有任何 key 设置 TTL 时只多一次 is_empty 判断
+    fn touch(&self, key: &str) {
+        if self.expiry.is_empty() {
+            return;
+        }
+        if let Some(mut e) = self.expiry.get_mut(key) {
+            e.deadline = Instant::now() + e.ttl;
+        }
+    }
+
+    // 写入之前检查 key 的个数上限，返回 false 时这次写入被丢掉。
+    // 不能在持有 data 的 entry 锁的时候调用：data.len() 要拿每个 shard 的读锁
+    fn admit(&self, key: &str) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+        let lru = limit.policy == EvictionPolicy::Lru;
+        if self.data.contains_key(key) {
+            if lru {
+                self.last_used.insert(key.to_string(), Instant::now());
+            }
+            return true;
+        }
+        if self.data.len() >= limit.max {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            if !lru {
+                return false;
+            }
+            // O(n) 地找最久没写过的 key；只在 map 已经满了、又来了新 key 时才会走到这里
+            let oldest = self
+                .last_used
+                .iter()
+                .min_by_key(|entry| *entry.value())
+                .map(|entry| entry.key().clone());
+            if let Some(oldest) = oldest {
+                self.remove(&oldest);
+            }
+        }
+        if lru {
+            self.last_used.insert(key.to_string(), Instant::now());
+        }
+        true
+    }
+
+    // remove_if 在 expiry 的锁里再检查一次，避免删掉一个刚刚被 touch 过的 key
+    fn expire(&self, key: &str, now: Instant) -> bool {
+        match self.expiry.remove_if(key, |_, e| e.deadline <= now) {
+            Some((key, _)) => {
+                self.data.remove(&key);
+                self.last_used.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 把 other 的每个值加到同名的 key 上，key 不存在时新建。
+    // 先 collect 再写：merge 自己（或者共享同一个 data 的 clone）时不会在持有读锁的时候写
+    pub fn merge(&self, other: &CmapMetrics) -> Result<()> {
+        for (key, value) in other.iter().collect::<Vec<_>>() {
+            self.inc_by(key, value)?;
+        }
+        Ok(())
+    }
+
+    // 读出当前值并清零，key 保留。每个 key 在它所在 shard 的写锁里读出并清零，
+    // 并发的 inc 要么算在这次 drain 里，要么留给下一次，不会丢也不会重复
+    pub fn drain(&self) -> Snapshot {
+        self.sweep();
+        self.data
+            .iter_mut()
+            .map(|mut entry| (entry.key().clone(), std::mem::take(entry.value_mut())))
+            .collect()
+    }
+
+    // reset 保留所有 key、把值清零；clear 把 key 也删掉
+    pub fn reset(&self) {
+        self.data.iter_mut().for_each(|mut entry| *entry = 0);
+    }
+
+    pub fn clear(&self) {
+        self.data.clear();
+        self.expiry.clear();
+        self.last_used.clear();
+    }
+
+    // 返回一个计时器，drop 时把经过的微秒数加到 key 上
+    pub fn timer(&self, key: impl Into<String>) -> Timer {
+        let key = key.into();
+        let metrics = self.clone();
+        Timer::new(move |elapsed| {
+            let _ = metrics.inc_by(key, as_micros(elapsed));
+        })
+    }
+
+    // 写成 JSON 文件，重启之后用 load 恢复
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        persist::save(path.as_ref(), &MetricsBackend::snapshot(self))
+    }
+
+    // 把文件里的值逐个 set 进来，文件里没有的 key 保持不变
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<()> {
+        for (key, value) in persist::load(path.as_ref())? {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    // 遍历所有 key 和当前值，key 和值都是 copy 出来的。
+    // 遍历到某个 shard 时会持有这个 shard 的读锁，所以不要在遍历的过程中写同一个 CmapMetrics，
+    // 需要边遍历边写的时候先 collect
+    pub fn iter(&self) -> impl Iterator<Item = (String, i64)> + '_ {
+        self.sweep();
+        self.data
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+    }
+
+    // 给 Prometheus 抓取用的文本格式，Display 的输出是给人看的
+    pub fn prometheus_encode(&self) -> String {
+        self.sweep();
+        prometheus::encode_with(
+            self.data
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value())),
+            |name| self.meta.get(name),
+        )
+    }
+
+    // 登记 name 的说明和单位，所有带 label 的 key 共用
+    pub fn describe(&self, name: &str, help: &str, unit: Option<&str>) {
+        self.meta.describe(name, help, unit);
+    }
 
     // 1
     // The map_err method is used to transform the error type, not to propagate it.
@@ -131,6 +540,32 @@ impl CmapMetrics {
     // }
 }
 
+impl MetricsBackend for CmapMetrics {
+    fn inc(&self, key: &str) -> Result<()> {
+        CmapMetrics::inc(self, key)
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        CmapMetrics::dec(self, key)
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        CmapMetrics::get(self, key)
+    }
+
+    fn snapshot(&self) -> HashMap<String, i64> {
+        MetricsBackend::iter(self).collect()
+    }
+
+    fn metadata(&self, key: &str) -> Option<Metadata> {
+        self.meta.get(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_> {
+        Box::new(CmapMetrics::iter(self))
+    }
+}
+
 impl Default for CmapMetrics {
     fn default() -> Self {
         Self::new()
@@ -139,9 +574,388 @@ impl Default for CmapMetrics {
 // 与 metrics.snapshot 不同，前者用到 .clone()，后者没有用到
 impl fmt::Display for CmapMetrics {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f.write_str(&self.format_with(&FormatOptions::default()));
+        }
+        self.sweep();
         for entry in self.data.iter() {
             writeln!(f, "{}: {}", entry.key(), entry.value())?;
         }
         Ok(())
     }
 }
+
+// spawn_sweeper 返回的句柄；线程里持有一个 CmapMetrics 的 clone，所以要靠它来停
+pub struct Sweeper {
+    shutdown: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Sweeper {
+    // 停止 sweep 线程并等待它退出
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            if let Err(e) = handle.join() {
+                if !thread::panicking() {
+                    std::panic::resume_unwind(e);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_cmap_inc_dec() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        metrics.inc("conn")?;
+                    }
+                    for _ in 0..40 {
+                        metrics.dec("conn")?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        metrics.dec("idle")?;
+        assert_eq!(*metrics.data.get("conn").unwrap(), 240);
+        assert_eq!(*metrics.data.get("idle").unwrap(), -1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_inc_by() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_by("bytes", 4096)?;
+        metrics.dec_by("bytes", 96)?;
+        assert_eq!(metrics.get("bytes"), Some(4000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_get_set() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_by("bytes", 4000)?;
+        metrics.set("bytes", 0)?;
+        metrics.set("new", 5)?;
+        assert_eq!(metrics.get("bytes"), Some(0));
+        assert_eq!(metrics.get("new"), Some(5));
+        assert_eq!(metrics.get("unknown"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_labels() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        // label 的顺序不影响 key
+        metrics.inc_with_labels("req", &[("page", "4"), ("method", "GET")])?;
+        metrics.inc_with_labels("req", &[("method", "GET"), ("page", "4")])?;
+        assert_eq!(
+            metrics.get(Key::new("req", &[("page", "4"), ("method", "GET")]).to_string()),
+            Some(2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_prometheus() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_with_labels("req", &[("page", "4"), ("method", "GET")])?;
+        metrics.inc_with_labels("req", &[("method", "GET"), ("page", "4")])?;
+        assert!(metrics
+            .prometheus_encode()
+            .contains("# TYPE req gauge\nreq{method=\"GET\",page=\"4\"} 2\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_iter() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("conn")?;
+        metrics.inc_with_labels("req", &[("page", "1")])?;
+        metrics.inc_with_labels("req", &[("page", "2")])?;
+        let req = metrics
+            .iter()
+            .filter(|(key, _)| Key::parse(key).name() == "req")
+            .map(|(_, value)| value)
+            .sum::<i64>();
+        assert_eq!(req, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_remove() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.dec("idle")?;
+        assert_eq!(metrics.remove("idle"), Some(-1));
+        assert_eq!(metrics.remove("idle"), None);
+        assert_eq!(metrics.get("idle"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_reset_clear() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_by("conn", 240)?;
+        // reset 只清零，key 还在；clear 连 key 一起删掉
+        metrics.reset();
+        assert_eq!(metrics.get("conn"), Some(0));
+        metrics.clear();
+        assert_eq!(metrics.get("conn"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_timer() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let handle = |metrics: &CmapMetrics, fail: bool| -> Result<()> {
+            let _timer = metrics.timer("req.us");
+            thread::sleep(Duration::from_millis(2));
+            if fail {
+                anyhow::bail!("early return");
+            }
+            Ok(())
+        };
+        handle(&metrics, false)?;
+        // 提前返回时同样会被记录
+        assert!(handle(&metrics, true).is_err());
+        assert!(metrics.get("req.us").unwrap() >= 4000);
+
+        let timer = metrics.timer("stopped.us");
+        let elapsed = timer.stop();
+        assert_eq!(metrics.get("stopped.us"), Some(as_micros(elapsed)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_watch() -> Result<()> {
+        use std::sync::atomic::AtomicUsize;
+
+        let metrics = CmapMetrics::new();
+        let once = Arc::new(AtomicUsize::new(0));
+        let fired = once.clone();
+        metrics.watch("queue", 10, move |v| {
+            assert!(v >= 10);
+            fired.fetch_add(1, Ordering::Relaxed);
+        });
+        let alerts = Arc::new(AtomicUsize::new(0));
+        let fired = alerts.clone();
+        let m = metrics.clone();
+        metrics.watch_with_hysteresis("queue", 10, 5, move |_| {
+            fired.fetch_add(1, Ordering::Relaxed);
+            // callback 里可以继续写 metrics
+            m.inc("queue.alerts").unwrap();
+        });
+
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || metrics.inc_by("queue", 4))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert_eq!(once.load(Ordering::Relaxed), 1);
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+
+        // 回落到 9：还在 hysteresis 区间里，再越过 10 不会触发
+        metrics.set("queue", 9)?;
+        metrics.inc("queue")?;
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+        // 回落到 5 以下重新武装
+        metrics.set("queue", 4)?;
+        metrics.set("queue", 12)?;
+        assert_eq!(alerts.load(Ordering::Relaxed), 2);
+        assert_eq!(once.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.get("queue.alerts"), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_inc_sampled() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..25_000 {
+                        metrics.inc_sampled("hot", 10)?;
+                    }
+                    metrics.inc_sampled("cold", 1)
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        // 100000 次、1/10 采样：标准差大约是 950，允许 5%
+        let hot = metrics.get("hot").unwrap();
+        assert!((95_000..=105_000).contains(&hot), "hot = {}", hot);
+        assert_eq!(hot % 10, 0);
+        assert_eq!(metrics.get("cold"), Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_max_keys() -> Result<()> {
+        let metrics = CmapMetrics::new().with_max_keys(2, EvictionPolicy::RejectNew);
+        metrics.inc("a")?;
+        metrics.inc("b")?;
+        metrics.inc("c")?;
+        metrics.set("d", 1)?;
+        // 已有的 key 不受影响
+        metrics.inc("a")?;
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("c"), None);
+        assert_eq!(metrics.dropped_keys(), 2);
+
+        let metrics = CmapMetrics::new().with_max_keys(2, EvictionPolicy::Lru);
+        metrics.inc("a")?;
+        thread::sleep(Duration::from_millis(1));
+        metrics.inc("b")?;
+        thread::sleep(Duration::from_millis(1));
+        metrics.inc("a")?;
+        thread::sleep(Duration::from_millis(1));
+        // b 最久没有写过，被淘汰
+        metrics.inc("c")?;
+        assert_eq!(metrics.get("b"), None);
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("c"), Some(1));
+        assert_eq!(metrics.dropped_keys(), 1);
+        assert_eq!(metrics.snapshot().len(), 2);
+
+        let handles = (0..4)
+            .map(|t| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        metrics.inc(format!("client.{}.{}", t, i))?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert!(metrics.snapshot().len() <= 2 + 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_drain() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        metrics.inc("req")?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        // 一边写一边 drain，所有 drain 出来的加上最后剩下的正好是总数
+        let mut drained = 0;
+        while !handles.iter().all(|h| h.is_finished()) {
+            drained += metrics.drain().get("req").copied().unwrap_or(0);
+        }
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        drained += metrics.drain()["req"];
+        assert_eq!(drained, 40_000);
+        assert_eq!(metrics.get("req"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_merge() -> Result<()> {
+        // fork-join：每个 worker 有自己的 metrics，最后合并
+        let handles = (0..4)
+            .map(|i| {
+                thread::spawn(move || {
+                    let metrics = CmapMetrics::new();
+                    for _ in 0..10 {
+                        metrics.inc("req")?;
+                    }
+                    metrics.inc(format!("worker.{}", i))?;
+                    Ok::<_, anyhow::Error>(metrics)
+                })
+            })
+            .collect::<Vec<_>>();
+        let total = CmapMetrics::new();
+        for handle in handles {
+            total.merge(&handle.join().expect("metrics worker panicked")?)?;
+        }
+        assert_eq!(total.get("req"), Some(40));
+        assert_eq!(total.get("worker.3"), Some(1));
+        assert_eq!(total.snapshot().len(), 5);
+
+        total.merge(&total.clone())?;
+        assert_eq!(total.get("req"), Some(80));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_ttl() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("total")?;
+        metrics.set_ttl("session.1", Duration::from_millis(50));
+        metrics.set_ttl("session.2", Duration::from_millis(50));
+        metrics.inc("session.1")?;
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(20));
+            // 一直在写的 key 不会过期
+            metrics.inc("session.2")?;
+        }
+        // get 时发现过期，直接删除
+        assert_eq!(metrics.get("session.1"), None);
+        assert_eq!(metrics.get("session.2"), Some(3));
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(metrics.sweep(), 1);
+        assert_eq!(metrics.snapshot().len(), 1);
+        assert_eq!(metrics.get("total"), Some(1));
+
+        metrics.set_ttl("session.3", Duration::from_millis(10));
+        let sweeper = metrics.spawn_sweeper(Duration::from_millis(5));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!metrics.data.contains_key("session.3"));
+        sweeper.stop();
+
+        // drop 也会停止线程，之后不再 sweep
+        metrics.set_ttl("session.4", Duration::from_millis(10));
+        drop(metrics.spawn_sweeper(Duration::from_millis(5)));
+        thread::sleep(Duration::from_millis(50));
+        assert!(metrics.data.contains_key("session.4"));
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+// 每次 append 在 CSV 文件末尾加一行：第一列是时间戳（毫秒），后面每个 key 一列。
+// 本地做实验时直接用表格软件或者 pandas 画图，不需要跑 Prometheus。
+// 新的 key 出现时加一列：只重写第一行的表头，之前的行少了后面几列，读出来就是空值。
+// 和 MetricsReporter 组合：
+//   let mut csv = CsvAppender::new("metrics.csv")?;
+//   MetricsReporter::start(metrics, interval, move |s| { let _ = csv.append(s); })
+use anyhow::{Context, Result};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::backend::Snapshot;
+
+#[derive(Debug)]
+pub struct CsvAppender {
+    path: PathBuf,
+    file: File,
+    // 不包括第一列的 timestamp
+    columns: Vec<String>,
+}
+
+impl CsvAppender {
+    // 文件已经存在时接着往后写，沿用它的表头
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut columns = Vec::new();
+        if let Ok(file) = File::open(&path) {
+            if let Some(header) = BufReader::new(file).lines().next() {
+                // 去掉第一列的 timestamp
+                columns = parse_row(&header?).into_iter().skip(1).collect();
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        Ok(CsvAppender {
+            path,
+            file,
+            columns,
+        })
+    }
+
+    pub fn append(&mut self, snapshot: &Snapshot) -> Result<()> {
+        self.append_at(snapshot, SystemTime::now())
+    }
+
+    fn append_at(&mut self, snapshot: &Snapshot, at: SystemTime) -> Result<()> {
+        let known = self.columns.iter().collect::<HashSet<_>>();
+        let mut added = snapshot
+            .keys()
+            .filter(|key| !known.contains(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !added.is_empty() || self.columns.is_empty() {
+            added.sort();
+            self.columns.extend(added);
+            self.write_header()?;
+        }
+
+        let millis = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut row = vec![millis.to_string()];
+        // snapshot 里没有的 key（比如被删掉了）留空
+        row.extend(
+            self.columns
+                .iter()
+                .map(|key| snapshot.get(key).map_or(String::new(), |v| v.to_string())),
+        );
+        writeln!(self.file, "{}", row.join(","))?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    // 表头变了：把整个文件重写一遍，第一行换成新的表头。只在出现新 key 的时候发生
+    fn write_header(&mut self) -> Result<()> {
+        let header = std::iter::once("timestamp")
+            .chain(self.columns.iter().map(String::as_str))
+            .map(escape)
+            .collect::<Vec<_>>()
+            .join(",");
+        let old = fs::read_to_string(&self.path).unwrap_or_default();
+        let rows = old.split_once('\n').map_or("", |(_, rows)| rows);
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n{}", header, rows))?;
+        fs::rename(&tmp, &self.path)?;
+        // rename 之后原来的 fd 指向的是旧文件，重新打开
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+// 带 label 的 key 里有引号和逗号：req{method="GET",page="1"}，整个字段用引号括起来，里面的引号写两遍
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn parse_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, time::Duration};
+
+    #[test]
+    fn test_csv_appender() -> Result<()> {
+        let path = env::temp_dir().join(format!("metrics-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        let mut csv = CsvAppender::new(&path)?;
+        csv.append_at(&Snapshot::from([("req".to_string(), 1)]), at(1))?;
+        let label = r#"req{method="GET",page="1"}"#.to_string();
+        csv.append_at(
+            &Snapshot::from([("req".to_string(), 5), (label.clone(), 2)]),
+            at(2),
+        )?;
+        drop(csv);
+
+        // 重新打开时沿用原来的表头
+        let mut csv = CsvAppender::new(&path)?;
+        csv.append_at(&Snapshot::from([(label.clone(), 3)]), at(3))?;
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "timestamp,req,\"req{method=\"\"GET\"\",page=\"\"1\"\"}\"\n\
+             1000,1\n\
+             2000,5,2\n\
+             3000,,3\n"
+        );
+        assert_eq!(parse_row(&escape(&label)), vec![label],);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+// f64 的 gauge：比例、温度、队列使用率这类不是整数的值。
+// CmapGauges 与 CmapMetrics 一样用 DashMap，key 可以在运行时随时出现；
+// AmapGauges 与 AmapMetrics 一样 key 在 new 的时候固定，值是 AtomicF64，set/add 都不需要锁。
+// 值是直接 set 的当前状态，而不是累加的计数。
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{atomic::Ordering, Arc},
+};
+
+use dashmap::DashMap;
+
+use super::{atomic::AtomicF64, key::Key, prometheus};
+
+#[derive(Debug, Clone, Default)]
+pub struct CmapGauges {
+    data: Arc<DashMap<String, f64>>,
+}
+
+impl CmapGauges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, key: impl Into<String>, value: f64) {
+        self.data.insert(key.into(), value);
+    }
+
+    pub fn set_with_labels(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.set(Key::new(name, labels), value);
+    }
+
+    // 在当前值上加一个增量（可以是负数）；key 不存在时从 0.0 开始
+    pub fn add(&self, key: impl Into<String>, delta: f64) {
+        *self.data.entry(key.into()).or_insert(0.0) += delta;
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<f64> {
+        self.data.get(key.as_ref()).map(|v| *v)
+    }
+
+    pub fn remove(&self, key: impl AsRef<str>) -> Option<f64> {
+        self.data.remove(key.as_ref()).map(|(_, v)| v)
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, f64> {
+        self.data
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    pub fn prometheus_encode(&self) -> String {
+        prometheus::encode(self.snapshot())
+    }
+}
+
+impl fmt::Display for CmapGauges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in self.data.iter() {
+            writeln!(f, "{}: {}", entry.key(), entry.value())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AmapGauges {
+    data: Arc<HashMap<&'static str, AtomicF64>>,
+}
+
+impl AmapGauges {
+    pub fn new(names: &[&'static str]) -> Self {
+        let map = names
+            .iter()
+            .map(|&name| (name, AtomicF64::new(0.0)))
+            .collect();
+        AmapGauges {
+            data: Arc::new(map),
+        }
+    }
+
+    pub fn set(&self, key: impl AsRef<str>, value: f64) -> Result<()> {
+        self.gauge(key.as_ref())?.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // 多个线程同时 add 时用 CAS 重试，不会丢失
+    pub fn add(&self, key: impl AsRef<str>, delta: f64) -> Result<()> {
+        self.gauge(key.as_ref())?
+            .fetch_add(delta, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<f64> {
+        self.data
+            .get(key.as_ref())
+            .map(|v| v.load(Ordering::Relaxed))
+    }
+
+    pub fn snapshot(&self) -> HashMap<&'static str, f64> {
+        self.data
+            .iter()
+            .map(|(&key, value)| (key, value.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub fn prometheus_encode(&self) -> String {
+        prometheus::encode(
+            self.snapshot()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        )
+    }
+
+    fn gauge(&self, key: &str) -> Result<&AtomicF64> {
+        self.data
+            .get(key)
+            .ok_or_else(|| anyhow!("key {} not found", key))
+    }
+}
+
+impl fmt::Display for AmapGauges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.data.iter() {
+            writeln!(f, "{}: {}", key, value.load(Ordering::Relaxed))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmap_gauges() {
+        let gauges = CmapGauges::new();
+        gauges.set("cpu.temp", 61.5);
+        gauges.add("queue.utilization", 0.25);
+        gauges.add("queue.utilization", 0.5);
+        gauges.set_with_labels("hit.ratio", &[("cache", "l1")], 0.875);
+        assert_eq!(gauges.get("cpu.temp"), Some(61.5));
+        assert_eq!(gauges.get("queue.utilization"), Some(0.75));
+        assert_eq!(gauges.remove("cpu.temp"), Some(61.5));
+        assert_eq!(gauges.get("cpu.temp"), None);
+        assert_eq!(
+            gauges.prometheus_encode(),
+            "# TYPE hit_ratio gauge\nhit_ratio{cache=\"l1\"} 0.875\n\
+             # TYPE queue_utilization gauge\nqueue_utilization 0.75\n"
+        );
+    }
+
+    #[test]
+    fn test_amap_gauges() -> Result<()> {
+        let gauges = AmapGauges::new(&["load", "temp"]);
+        let handles = (0..4)
+            .map(|_| {
+                let gauges = gauges.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        gauges.add("load", 0.25)?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("gauge worker panicked")?;
+        }
+        gauges.set("temp", 61.5)?;
+        assert_eq!(gauges.get("load"), Some(1000.0));
+        assert_eq!(gauges.get("temp"), Some(61.5));
+        assert!(gauges.set("unknown", 1.0).is_err());
+        assert_eq!(gauges.get("unknown"), None);
+        assert_eq!(
+            gauges.prometheus_encode(),
+            "# TYPE load gauge\nload 1000\n# TYPE temp gauge\ntemp 61.5\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+// 进程级别的默认 metrics：调用栈很深的库函数不需要一路把 metrics 当参数传下来，
+// 直接用 inc!("cache.miss") / gauge!("queue.len", n) 记到 global() 上。
+//   inc!("req");                                  // +1
+//   inc!("bytes.read", n);                        // +n
+//   gauge!("queue.len", queue.len() as i64);      // 直接设置成当前值
+// global() 第一次调用时才初始化，之后所有线程拿到的都是同一个 CmapMetrics。
+use std::sync::OnceLock;
+
+use super::cmap::CmapMetrics;
+
+pub fn global() -> &'static CmapMetrics {
+    static GLOBAL: OnceLock<CmapMetrics> = OnceLock::new();
+    GLOBAL.get_or_init(CmapMetrics::new)
+}
+
+// 默认的溢出策略是 Wrap，CmapMetrics 的 inc/set 不会失败，所以宏里直接忽略返回值
+#[macro_export]
+macro_rules! inc {
+    ($key:expr) => {
+        $crate::inc!($key, 1)
+    };
+    ($key:expr, $delta:expr) => {{
+        let _ = $crate::global().inc_by($key, $delta);
+    }};
+}
+
+#[macro_export]
+macro_rules! gauge {
+    ($key:expr, $value:expr) => {{
+        let _ = $crate::global().set($key, $value);
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+    use std::thread;
+
+    fn deep_in_the_call_stack(page: usize) {
+        inc!(Key::new("global.req", &[("page", &page.to_string())]));
+    }
+
+    #[test]
+    fn test_global_metrics() {
+        let handles = (0..4)
+            .map(|i| {
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        deep_in_the_call_stack(i % 2);
+                        inc!("global.bytes", 100);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked");
+        }
+        gauge!("global.queue.len", 7);
+        gauge!("global.queue.len", 3);
+
+        let metrics = global();
+        assert_eq!(
+            metrics.get(Key::new("global.req", &[("page", "1")]).to_string()),
+            Some(20)
+        );
+        assert_eq!(metrics.get("global.bytes"), Some(4000));
+        assert_eq!(metrics.get("global.queue.len"), Some(3));
+        assert!(std::ptr::eq(metrics, global()));
+    }
+}
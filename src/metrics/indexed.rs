@@ -0,0 +1,285 @@
+// 用小整数 id 代替字符串 key：启动时把所有名字注册好，拿到每个名字的 MetricId，
+// 之后 inc 只是 Vec 下标 + 一次 fetch_add，不需要像 CmapMetrics 那样每次都对 String 算 hash。
+// 名字只在导出（snapshot、Display、Prometheus）和按名字查找 id 的时候用到。
+//   let metrics = IndexedMetrics::new(&["req", "conn"]);
+//   let req = metrics.id("req").unwrap();
+//   metrics.inc(req);
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use super::{
+    backend::{MetricsBackend, Snapshot},
+    prometheus,
+    sharded::Padded,
+};
+
+// 只能从 IndexedMetrics::id 拿到。id 记着是哪个 IndexedMetrics 发的（clone 出来的共用同一个），
+// 拿到别的实例上用会直接 panic，而不是越界或者悄悄地写到别的 counter 上
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MetricId {
+    index: usize,
+    owner: usize,
+}
+
+// 每个 IndexedMetrics::new 分配一个不同的 owner
+static NEXT_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
+pub struct IndexedMetrics {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    owner: usize,
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, MetricId>,
+    // 每个 counter 独占一条 cache line，相邻的 id 被不同线程写时不会 false sharing
+    values: Vec<Padded>,
+}
+
+impl IndexedMetrics {
+    pub fn new(metric_names: &[&'static str]) -> Self {
+        let owner = NEXT_OWNER.fetch_add(1, Ordering::Relaxed);
+        let mut names = Vec::new();
+        let mut ids = HashMap::new();
+        for &name in metric_names {
+            // 重复的名字共用同一个 id
+            ids.entry(name).or_insert_with(|| {
+                names.push(name);
+                MetricId {
+                    index: names.len() - 1,
+                    owner,
+                }
+            });
+        }
+        let values = names.iter().map(|_| Padded::default()).collect();
+        IndexedMetrics {
+            inner: Arc::new(Inner {
+                owner,
+                names,
+                ids,
+                values,
+            }),
+        }
+    }
+
+    // 按名字查 id，通常在启动的时候查一次，然后把 id 存下来
+    pub fn id(&self, name: &str) -> Option<MetricId> {
+        self.inner.ids.get(name).copied()
+    }
+
+    // 下面几个方法拿到别的 IndexedMetrics 的 id 时都会 panic
+    pub fn name(&self, id: MetricId) -> &'static str {
+        self.inner.names[self.index(id)]
+    }
+
+    pub fn inc(&self, id: MetricId) {
+        self.inc_by(id, 1);
+    }
+
+    pub fn dec(&self, id: MetricId) {
+        self.inc_by(id, -1);
+    }
+
+    pub fn inc_by(&self, id: MetricId, delta: i64) {
+        self.slot(id).fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, id: MetricId) -> i64 {
+        self.slot(id).load(Ordering::Relaxed)
+    }
+
+    // 按名字合并。名字在 new 的时候就固定了，other 里有、这里没有的名字返回错误，
+    // 先检查完所有名字再加，出错时什么都不会改
+    pub fn merge(&self, other: &IndexedMetrics) -> Result<()> {
+        let ids = other
+            .iter()
+            .map(|(name, value)| {
+                let id = self
+                    .id(name)
+                    .ok_or_else(|| anyhow!("key {} not found", name))?;
+                Ok((id, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (id, value) in ids {
+            self.inc_by(id, value);
+        }
+        Ok(())
+    }
+
+    // 读出当前值并清零，每个值是一次 swap
+    pub fn drain(&self) -> Snapshot {
+        self.inner
+            .names
+            .iter()
+            .zip(&self.inner.values)
+            .map(|(&name, value)| (name.to_string(), value.0.swap(0, Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for value in &self.inner.values {
+            value.0.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // 按注册的顺序遍历
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, i64)> + '_ {
+        self.inner
+            .names
+            .iter()
+            .zip(&self.inner.values)
+            .map(|(&name, value)| (name, value.0.load(Ordering::Relaxed)))
+    }
+
+    pub fn prometheus_encode(&self) -> String {
+        prometheus::encode(self.iter().map(|(name, value)| (name.to_string(), value)))
+    }
+
+    // 只多一次整数比较，热路径上的开销可以忽略
+    fn index(&self, id: MetricId) -> usize {
+        assert_eq!(
+            id.owner, self.inner.owner,
+            "MetricId belongs to another IndexedMetrics"
+        );
+        id.index
+    }
+
+    fn slot(&self, id: MetricId) -> &AtomicI64 {
+        &self.inner.values[self.index(id)].0
+    }
+
+    fn lookup(&self, key: &str) -> Result<MetricId> {
+        self.id(key).ok_or_else(|| anyhow!("key {} not found", key))
+    }
+}
+
+// 按名字访问的通用接口，每次都要查一次 HashMap；热路径上用 MetricId
+impl MetricsBackend for IndexedMetrics {
+    fn inc(&self, key: &str) -> Result<()> {
+        IndexedMetrics::inc(self, self.lookup(key)?);
+        Ok(())
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        IndexedMetrics::dec(self, self.lookup(key)?);
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        self.id(key).map(|id| IndexedMetrics::get(self, id))
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        MetricsBackend::iter(self).collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_> {
+        Box::new(IndexedMetrics::iter(self).map(|(key, value)| (key.to_string(), value)))
+    }
+}
+
+impl fmt::Display for IndexedMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in self.iter() {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_indexed_metrics() -> Result<()> {
+        let metrics = IndexedMetrics::new(&["req", "conn", "req"]);
+        let req = metrics.id("req").unwrap();
+        let conn = metrics.id("conn").unwrap();
+        assert_eq!(metrics.id("unknown"), None);
+        assert_eq!(metrics.name(conn), "conn");
+
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.inc(req);
+                    }
+                    metrics.inc_by(conn, 3);
+                    metrics.dec(conn);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked");
+        }
+        assert_eq!(metrics.get(req), 4000);
+        assert_eq!(metrics.to_string(), "req: 4000\nconn: 8\n");
+
+        MetricsBackend::inc(&metrics, "conn")?;
+        assert!(MetricsBackend::inc(&metrics, "unknown").is_err());
+        assert_eq!(MetricsBackend::get(&metrics, "conn"), Some(9));
+        metrics.reset();
+        assert_eq!(metrics.get(req), 0);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "MetricId belongs to another IndexedMetrics")]
+    fn test_indexed_foreign_id() {
+        let metrics = IndexedMetrics::new(&["req"]);
+        let other = IndexedMetrics::new(&["req", "conn"]);
+        // clone 出来的实例共用 id
+        metrics.clone().inc(metrics.id("req").unwrap());
+        metrics.inc(other.id("req").unwrap());
+    }
+
+    #[test]
+    fn test_indexed_drain() {
+        let metrics = IndexedMetrics::new(&["req", "conn"]);
+        let conn = metrics.id("conn").unwrap();
+        metrics.inc_by(conn, 9);
+        let drained = metrics.drain();
+        assert_eq!(drained["conn"], 9);
+        assert_eq!(drained["req"], 0);
+        assert_eq!(metrics.get(conn), 0);
+    }
+
+    #[test]
+    fn test_indexed_merge() -> Result<()> {
+        let metrics = IndexedMetrics::new(&["req", "conn"]);
+        let conn = metrics.id("conn").unwrap();
+        metrics.inc(conn);
+        let other = IndexedMetrics::new(&["conn"]);
+        other.inc_by(other.id("conn").unwrap(), 11);
+        // 按名字对齐，两边的 id 不一样也没关系
+        metrics.merge(&other)?;
+        assert_eq!(metrics.get(conn), 12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_indexed_merge_foreign_key() {
+        let metrics = IndexedMetrics::new(&["req", "conn"]);
+        let conn = metrics.id("conn").unwrap();
+        let other = IndexedMetrics::new(&["conn", "other"]);
+        other.inc_by(other.id("conn").unwrap(), 11);
+        other.inc(other.id("other").unwrap());
+        // other 里独有的 key 是错误，conn 也不会被加上
+        let err = metrics.merge(&other).unwrap_err();
+        assert!(err.to_string().contains("other"));
+        assert_eq!(metrics.get(conn), 0);
+        assert_eq!(metrics.id("other"), None);
+    }
+}
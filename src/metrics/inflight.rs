@@ -0,0 +1,80 @@
+// "正在处理中" 的 gauge：创建时 inc，drop 时 dec。
+// handler 提前 return、? 出错甚至 panic 时 guard 都会被 drop，gauge 不会只加不减。
+use anyhow::Result;
+use std::fmt;
+
+use super::backend::MetricsBackend;
+
+pub struct InFlight<M: MetricsBackend> {
+    metrics: M,
+    key: String,
+}
+
+impl<M: MetricsBackend> InFlight<M> {
+    // inc 失败（比如 AmapMetrics 里没有这个 key）时不返回 guard，也就不会在 drop 时多 dec 一次
+    pub(crate) fn new(metrics: M, key: &str) -> Result<Self> {
+        metrics.inc(key)?;
+        Ok(InFlight {
+            metrics,
+            key: key.to_string(),
+        })
+    }
+}
+
+impl<M: MetricsBackend> Drop for InFlight<M> {
+    fn drop(&mut self) {
+        // drop 里没有办法返回错误；inc 成功过的 key，dec 只有溢出策略是 Error 时才可能失败
+        let _ = self.metrics.dec(&self.key);
+    }
+}
+
+impl<M: MetricsBackend> fmt::Debug for InFlight<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InFlight({})", self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AmapMetrics, CmapMetrics, MetricsBackend, ShardedMetrics};
+    use std::thread;
+
+    fn handle<M: MetricsBackend + Clone>(metrics: &M, fail: bool) -> anyhow::Result<()> {
+        let _guard = metrics.in_flight("req.active")?;
+        assert_eq!(metrics.get("req.active"), Some(1));
+        if fail {
+            anyhow::bail!("early return");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_flight() -> anyhow::Result<()> {
+        let cmap = CmapMetrics::new();
+        handle(&cmap, false)?;
+        assert!(handle(&cmap, true).is_err());
+        assert_eq!(cmap.get("req.active"), Some(0));
+
+        // panic 的时候 guard 一样会被 drop
+        let metrics = cmap.clone();
+        let result = thread::spawn(move || {
+            let _guard = metrics.in_flight("req.active").unwrap();
+            panic!("handler panicked");
+        })
+        .join();
+        assert!(result.is_err());
+        assert_eq!(cmap.get("req.active"), Some(0));
+
+        let amap = AmapMetrics::new(&["req.active"]);
+        handle(&amap, false)?;
+        assert_eq!(amap.get("req.active"), Some(0));
+        assert!(amap.in_flight("unknown").is_err());
+
+        let sharded = ShardedMetrics::with_shards(&["req.active"], 2);
+        let guard = sharded.in_flight("req.active")?;
+        assert_eq!(sharded.get("req.active"), Some(1));
+        drop(guard);
+        assert_eq!(sharded.get("req.active"), Some(0));
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+// 带 label 的 metric key：name 加上一组 (label, value)，比如 req{method="GET",page="4"}。
+// 三个 backend 内部仍然用字符串做 key，Key 负责把 name 和 label 拼成唯一的规范形式：
+// label 按名字排序，所以 [("page", "4"), ("method", "GET")] 和反过来的顺序是同一个 key。
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key {
+    name: String,
+    labels: Vec<(String, String)>,
+}
+
+impl Key {
+    pub fn new(name: impl Into<String>, labels: &[(&str, &str)]) -> Self {
+        let mut labels = labels
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        labels.sort();
+        Key {
+            name: name.into(),
+            labels,
+        }
+    }
+
+    // 把 Display 输出的字符串拆回 name 和 label；不带 {} 的普通 key 没有 label
+    pub fn parse(s: &str) -> Self {
+        let Some((name, rest)) = s.split_once('{') else {
+            return Key::new(s, &[]);
+        };
+        let body = rest.strip_suffix('}').unwrap_or(rest);
+        let mut labels = Vec::new();
+        let mut chars = body.chars().peekable();
+        while chars.peek().is_some() {
+            let label = chars.by_ref().take_while(|&c| c != '=').collect::<String>();
+            // 跳过开头的引号，读到没有被转义的引号为止
+            chars.next_if_eq(&'"');
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some(c) => value.push(c),
+                        None => break,
+                    },
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            chars.next_if_eq(&',');
+            labels.push((label, value));
+        }
+        labels.sort();
+        Key {
+            name: name.to_string(),
+            labels,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+}
+
+// 与 Prometheus 的 label 写法一致：值放在双引号里，\、" 和换行需要转义
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.labels.is_empty() {
+            return Ok(());
+        }
+        write!(f, "{{")?;
+        for (i, (k, v)) in self.labels.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            let v = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            write!(f, "{}=\"{}\"", k, v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_labels() {
+        let key = Key::new("req", &[("page", "4"), ("method", "GET")]);
+        assert_eq!(key.to_string(), r#"req{method="GET",page="4"}"#);
+        assert_eq!(key, Key::new("req", &[("method", "GET"), ("page", "4")]));
+        assert_eq!(Key::parse(&key.to_string()), key);
+
+        let odd = Key::new("q", &[("v", "a\"b\\c,d=e\nf")]);
+        assert_eq!(Key::parse(&odd.to_string()), odd);
+        assert_eq!(Key::parse("req.page.4"), Key::new("req.page.4", &[]));
+        assert_eq!(Key::new("plain", &[]).to_string(), "plain");
+    }
+}
@@ -0,0 +1,96 @@
+// tracing 和 metrics 之间的桥：已经在用 tracing 打日志的代码（比如 dumyredis）不需要再手动记 metrics。
+// 每个 event 按 level 和 target 计数，每个 span 从创建到关闭的时间记到 histogram 里：
+//   let registry = Registry::new();
+//   tracing_subscriber::registry()
+//       .with(tracing_subscriber::fmt::layer())
+//       .with(MetricsLayer::new(&registry)?)
+//       .init();
+use anyhow::Result;
+use std::time::Instant;
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use super::registry::{CounterFamily, HistogramFamily, Registry};
+
+// span 的耗时从几微秒到几秒
+const SPAN_BUCKETS: &[f64] = &[0.0001, 0.001, 0.01, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Clone)]
+pub struct MetricsLayer {
+    events: CounterFamily,
+    spans: HistogramFamily,
+}
+
+// 存在 span 的 extensions 里，span 关闭的时候取出来
+struct SpanStart(Instant);
+
+impl MetricsLayer {
+    pub fn new(registry: &Registry) -> Result<Self> {
+        Ok(MetricsLayer {
+            events: registry.counter("tracing_events", "tracing events by level and target")?,
+            spans: registry.histogram(
+                "tracing_span_duration_seconds",
+                "time from span creation to close",
+                SPAN_BUCKETS,
+            )?,
+        })
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        self.events
+            .inc(&[("level", meta.level().as_str()), ("target", meta.target())]);
+    }
+
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let elapsed = span.extensions().get::<SpanStart>().map(|s| s.0.elapsed());
+        if let Some(elapsed) = elapsed {
+            self.spans
+                .observe(&[("span", span.name())], elapsed.as_secs_f64());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{info, info_span, warn};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_metrics_layer() -> Result<()> {
+        let registry = Registry::new();
+        let subscriber = tracing_subscriber::registry().with(MetricsLayer::new(&registry)?);
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                let _span = info_span!("request").entered();
+                info!(target: "redis", "command processed");
+            }
+            warn!(target: "redis", "connection closed");
+        });
+
+        let events = registry.counter("tracing_events", "")?;
+        assert_eq!(events.get(&[("level", "INFO"), ("target", "redis")]), 3);
+        assert_eq!(events.get(&[("level", "WARN"), ("target", "redis")]), 1);
+        let spans = registry.histogram("tracing_span_duration_seconds", "", &[])?;
+        assert_eq!(spans.count(&[("span", "request")]), 3);
+        assert!(registry
+            .encode()
+            .contains("# TYPE tracing_span_duration_seconds histogram\n"));
+        Ok(())
+    }
+}
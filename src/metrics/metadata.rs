@@ -0,0 +1,82 @@
+// 每个 metric name 的说明和单位：prometheus_encode 输出成 # HELP / # UNIT，`{:#}` 的表格里单位跟在值的后面。
+// 按 name 登记，不区分 label：req{page="1"} 和 req{page="2"} 共用 req 的说明
+use dashmap::DashMap;
+use std::sync::Arc;
+
+use super::key::Key;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metadata {
+    pub help: String,
+    pub unit: Option<String>,
+}
+
+// 和值分开存：大部分 key 没有说明，写值的时候也不需要碰它
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Descriptions(Arc<DashMap<String, Metadata>>);
+
+impl Descriptions {
+    pub(crate) fn describe(&self, name: &str, help: &str, unit: Option<&str>) {
+        self.0.insert(
+            name.to_string(),
+            Metadata {
+                help: help.to_string(),
+                unit: unit.map(str::to_string),
+            },
+        );
+    }
+
+    // key 可以带 label，按 name 查找
+    pub(crate) fn get(&self, key: &str) -> Option<Metadata> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let key = Key::parse(key);
+        self.0.get(key.name()).map(|meta| meta.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AmapMetrics, CmapMetrics, MetricsBackend};
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.describe("req", "Requests served", None);
+        metrics.describe("latency", "Time spent in the handler", Some("us"));
+        metrics.inc_with_labels("req", &[("page", "1")])?;
+        metrics.inc_by("latency", 1200)?;
+        metrics.inc("conn")?;
+
+        assert_eq!(
+            metrics.prometheus_encode(),
+            "# TYPE conn gauge\n\
+             conn 1\n\
+             # HELP latency Time spent in the handler\n\
+             # TYPE latency gauge\n\
+             # UNIT latency us\n\
+             latency 1200\n\
+             # HELP req Requests served\n\
+             # TYPE req gauge\n\
+             req{page=\"1\"} 1\n"
+        );
+        assert_eq!(
+            format!("{:#}", metrics),
+            "conn              1\n\
+             latency        1200 us\n\
+             req{page=\"1\"}     1\n"
+        );
+
+        let amap = AmapMetrics::new(&["bytes"]);
+        amap.describe("bytes", "Bytes read\nfrom \\ sockets", Some("bytes"));
+        assert_eq!(
+            amap.metadata("bytes").unwrap().unit.as_deref(),
+            Some("bytes")
+        );
+        assert!(amap
+            .prometheus_encode()
+            .starts_with("# HELP bytes Bytes read\\nfrom \\\\ sockets\n# TYPE bytes gauge\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+// EWMA (exponentially-weighted moving average) 速率，和 Unix load average 一样给出 1/5/15 分钟三个窗口。
+// mark 只是一次原子加法；后台线程每 TICK 秒把这段时间的事件数折算进三个平均值里。
+// 比原始计数器更适合做限流、降级之类的决策：最近的负载权重大，很久以前的负载慢慢衰减掉。
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use super::atomic::AtomicF64;
+
+const TICK: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct Meter {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    uncounted: AtomicU64,
+    count: AtomicU64,
+    initialized: AtomicBool,
+    // 读写都不需要锁；只有 tick 线程会写
+    rates: [AtomicF64; 3],
+}
+
+impl Meter {
+    // 创建时启动 tick 线程；线程只持有 Weak，所有 Meter 都 drop 之后它在下一个 tick 退出
+    pub fn new() -> Self {
+        let meter = Meter {
+            inner: Arc::new(Inner {
+                uncounted: AtomicU64::new(0),
+                count: AtomicU64::new(0),
+                initialized: AtomicBool::new(false),
+                rates: Default::default(),
+            }),
+        };
+        let weak: Weak<Inner> = Arc::downgrade(&meter.inner);
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            match weak.upgrade() {
+                Some(inner) => inner.tick(),
+                None => break,
+            }
+        });
+        meter
+    }
+
+    pub fn mark(&self) {
+        self.mark_n(1);
+    }
+
+    pub fn mark_n(&self, n: u64) {
+        self.inner.uncounted.fetch_add(n, Ordering::Relaxed);
+        self.inner.count.fetch_add(n, Ordering::Relaxed);
+    }
+
+    // 从创建到现在的事件总数
+    pub fn count(&self) -> u64 {
+        self.inner.count.load(Ordering::Relaxed)
+    }
+
+    // 每秒事件数
+    pub fn rate_1m(&self) -> f64 {
+        self.inner.rate(0)
+    }
+
+    pub fn rate_5m(&self) -> f64 {
+        self.inner.rate(1)
+    }
+
+    pub fn rate_15m(&self) -> f64 {
+        self.inner.rate(2)
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inner {
+    fn rate(&self, i: usize) -> f64 {
+        self.rates[i].load(Ordering::Relaxed)
+    }
+
+    fn tick(&self) {
+        let secs = TICK.as_secs_f64();
+        let instant = self.uncounted.swap(0, Ordering::Relaxed) as f64 / secs;
+        // 第一次 tick 直接用当前速率，否则平均值要从 0 慢慢爬上来
+        let initialized = self.initialized.swap(true, Ordering::Relaxed);
+        for (rate, minutes) in self.rates.iter().zip([1.0, 5.0, 15.0]) {
+            let alpha = 1.0 - (-secs / 60.0 / minutes).exp();
+            let old = rate.load(Ordering::Relaxed);
+            let new = if initialized {
+                old + alpha * (instant - old)
+            } else {
+                instant
+            };
+            rate.store(new, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_ewma() {
+        let meter = Meter::new();
+        meter.mark_n(50);
+        meter.inner.tick();
+        // 5 秒 50 次 => 每秒 10 次
+        assert_eq!(meter.rate_1m(), 10.0);
+        assert_eq!(meter.rate_15m(), 10.0);
+
+        // 之后没有新的事件：1 分钟的平均值衰减得比 15 分钟的快
+        for _ in 0..12 {
+            meter.inner.tick();
+        }
+        let (m1, m5, m15) = (meter.rate_1m(), meter.rate_5m(), meter.rate_15m());
+        assert!(m1 < m5 && m5 < m15 && m15 < 10.0);
+        // 一分钟之后，1 分钟窗口的平均值大约剩下 1/e
+        assert!((m1 - 10.0 / std::f64::consts::E).abs() < 1e-9);
+        assert_eq!(meter.count(), 50);
+    }
+}
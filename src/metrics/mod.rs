@@ -1,5 +1,53 @@
 mod amap;
+mod atomic;
+mod backend;
 mod cmap;
+mod csv;
+mod gauge;
+mod global;
+mod indexed;
+mod inflight;
+mod key;
+mod layer;
+mod metadata;
+mod meter;
+mod overflow;
+mod persist;
+mod prometheus;
+mod quantile;
+mod rate;
+mod registry;
+mod reporter;
+mod scoped;
+mod sharded;
+mod statsd;
+mod table;
+mod timer;
+mod typed;
+mod window;
 
 pub use amap::*;
+pub use atomic::*;
+pub use backend::*;
 pub use cmap::*;
+pub use csv::*;
+pub use gauge::*;
+pub use global::*;
+pub use indexed::*;
+pub use inflight::*;
+pub use key::*;
+pub use layer::*;
+pub use metadata::*;
+pub use meter::*;
+pub use overflow::*;
+pub use quantile::*;
+pub use rate::*;
+pub use registry::*;
+pub use reporter::*;
+pub use scoped::*;
+pub use sharded::*;
+pub use statsd::*;
+pub use table::*;
+pub use timer::*;
+pub use typed::*;
+pub use window::*;
@@ -0,0 +1,102 @@
+// 计数器溢出时怎么办：i64 的 += 1 / fetch_add 在溢出时会悄悄 wrap 成负数。
+// 每个 metrics 实例可以选择 wrap（默认，与原来的行为一致）、saturate（停在 i64::MAX / i64::MIN）或者返回错误。
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    #[default]
+    Wrap,
+    Saturate,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    Add,
+    Sub,
+}
+
+impl OverflowPolicy {
+    // 溢出并且策略是 Error 时返回 None
+    fn compute(self, current: i64, delta: i64, op: Op) -> Option<i64> {
+        match (self, op) {
+            (OverflowPolicy::Wrap, Op::Add) => Some(current.wrapping_add(delta)),
+            (OverflowPolicy::Wrap, Op::Sub) => Some(current.wrapping_sub(delta)),
+            (OverflowPolicy::Saturate, Op::Add) => Some(current.saturating_add(delta)),
+            (OverflowPolicy::Saturate, Op::Sub) => Some(current.saturating_sub(delta)),
+            (OverflowPolicy::Error, Op::Add) => current.checked_add(delta),
+            (OverflowPolicy::Error, Op::Sub) => current.checked_sub(delta),
+        }
+    }
+
+    // 用于已经拿到锁的值（CmapMetrics 的 entry）
+    pub(crate) fn apply(self, key: &str, current: i64, delta: i64, op: Op) -> Result<i64> {
+        self.compute(current, delta, op)
+            .ok_or_else(|| anyhow!("counter {} overflowed", key))
+    }
+
+    // 用于 atomic：Wrap 直接 fetch_add/fetch_sub；其它策略需要先看当前值，所以用 CAS 循环
+    pub(crate) fn apply_atomic(
+        self,
+        key: &str,
+        counter: &AtomicI64,
+        delta: i64,
+        op: Op,
+    ) -> Result<()> {
+        match (self, op) {
+            (OverflowPolicy::Wrap, Op::Add) => {
+                counter.fetch_add(delta, Ordering::Relaxed);
+            }
+            (OverflowPolicy::Wrap, Op::Sub) => {
+                counter.fetch_sub(delta, Ordering::Relaxed);
+            }
+            _ => {
+                counter
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                        self.compute(v, delta, op)
+                    })
+                    .map_err(|_| anyhow!("counter {} overflowed", key))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics, ShardedMetrics};
+
+    #[test]
+    fn test_overflow_policy() -> Result<()> {
+        let wrap = AmapMetrics::new(&["c"]);
+        wrap.set("c", i64::MAX)?;
+        wrap.inc("c")?;
+        assert_eq!(wrap.get("c"), Some(i64::MIN));
+
+        let saturate = AmapMetrics::new(&["c"]).with_overflow(OverflowPolicy::Saturate);
+        saturate.set("c", i64::MAX - 1)?;
+        saturate.inc_by("c", 10)?;
+        assert_eq!(saturate.get("c"), Some(i64::MAX));
+
+        let error = CmapMetrics::new().with_overflow(OverflowPolicy::Error);
+        error.set("c", i64::MIN)?;
+        let err = error.dec("c").unwrap_err();
+        assert_eq!(err.to_string(), "counter c overflowed");
+        // 出错时值保持不变
+        assert_eq!(error.get("c"), Some(i64::MIN));
+
+        let saturate = CmapMetrics::new().with_overflow(OverflowPolicy::Saturate);
+        saturate.dec_by("c", i64::MAX)?;
+        saturate.dec_by("c", i64::MAX)?;
+        assert_eq!(saturate.get("c"), Some(i64::MIN));
+
+        let sharded =
+            ShardedMetrics::with_shards(&["c"], 2).with_overflow(OverflowPolicy::Saturate);
+        sharded.inc_by("c", i64::MAX)?;
+        sharded.inc_by("c", i64::MAX)?;
+        assert_eq!(sharded.get("c"), Some(i64::MAX));
+        Ok(())
+    }
+}
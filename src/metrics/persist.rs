@@ -0,0 +1,60 @@
+// 把计数器写到磁盘上，进程重启之后再读回来，这样按天统计的计数器不会因为部署而清零。
+// 文件就是一个 JSON object：{"req": 27, "conn": 3}，人可以直接看，也方便别的工具读。
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+use super::backend::Snapshot;
+
+// 先写到同目录下的临时文件再 rename，进程在写的过程中崩溃也不会留下半个文件
+pub(crate) fn save(path: &Path, snapshot: &Snapshot) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let json = serde_json::to_vec_pretty(snapshot)?;
+    fs::write(&tmp, json).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+pub(crate) fn load(path: &Path) -> Result<Snapshot> {
+    let json = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_slice(&json)
+        .with_context(|| format!("invalid metrics file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics, MetricsBackend, ShardedMetrics};
+    use std::env;
+
+    #[test]
+    fn test_save_load() -> Result<()> {
+        let path = env::temp_dir().join(format!("metrics-{}.json", std::process::id()));
+
+        let cmap = CmapMetrics::new();
+        cmap.inc_by("req", 27)?;
+        cmap.dec("conn")?;
+        cmap.save(&path)?;
+        let restored = CmapMetrics::new();
+        restored.load(&path)?;
+        assert_eq!(restored.snapshot(), cmap.snapshot());
+
+        // AmapMetrics 和 ShardedMetrics 的 key 是固定的，文件里多出来的 key 被忽略
+        let amap = AmapMetrics::new(&["req"]);
+        amap.load(&path)?;
+        assert_eq!(amap.get("req"), Some(27));
+        amap.inc("req")?;
+        amap.save(&path)?;
+
+        let sharded = ShardedMetrics::with_shards(&["req", "conn"], 4);
+        sharded.inc_by("req", 100)?;
+        sharded.load(&path)?;
+        assert_eq!(sharded.get("req"), Some(28));
+        assert_eq!(sharded.get("conn"), Some(0));
+
+        fs::write(&path, "not json")?;
+        assert!(restored.load(&path).is_err());
+        fs::remove_file(&path)?;
+        assert!(restored.load(&path).is_err());
+        Ok(())
+    }
+}
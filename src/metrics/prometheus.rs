@@ -0,0 +1,97 @@
+// Prometheus 的 text exposition format：
+//   # TYPE req gauge
+//   req{method="GET",page="4"} 27
+// 同一个 name 的所有 label 组合放在一个 # TYPE 下面。计数器可以 dec，所以统一声明为 gauge。
+use std::{collections::BTreeMap, fmt::Display};
+
+use super::{key::Key, metadata::Metadata};
+
+// 值可以是 i64 的计数器，也可以是 f64 的 gauge
+pub(crate) fn encode<V: Display>(entries: impl IntoIterator<Item = (String, V)>) -> String {
+    encode_with(entries, |_| None)
+}
+
+// metadata 按原始的 name（sanitize 之前）查找，找到时输出 # HELP 和 # UNIT
+pub(crate) fn encode_with<V: Display>(
+    entries: impl IntoIterator<Item = (String, V)>,
+    metadata: impl Fn(&str) -> Option<Metadata>,
+) -> String {
+    // BTreeMap 让输出的顺序固定，不随 HashMap/DashMap 的遍历顺序变化
+    type Family<V> = (Option<Metadata>, Vec<(String, V)>);
+    let mut families: BTreeMap<String, Family<V>> = BTreeMap::new();
+    for (key, value) in entries {
+        let key = Key::parse(&key);
+        let name = sanitize(key.name());
+        let series = Key::new(
+            name.clone(),
+            &key.labels()
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>(),
+        );
+        families
+            .entry(name)
+            .or_insert_with(|| (metadata(key.name()), Vec::new()))
+            .1
+            .push((series.to_string(), value));
+    }
+
+    let mut out = String::new();
+    for (name, (meta, mut series)) in families {
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+        let meta = meta.unwrap_or_default();
+        if !meta.help.is_empty() {
+            let help = meta.help.replace('\\', "\\\\").replace('\n', "\\n");
+            out.push_str(&format!("# HELP {} {}\n", name, help));
+        }
+        out.push_str(&format!("# TYPE {} gauge\n", name));
+        if let Some(unit) = meta.unit {
+            out.push_str(&format!("# UNIT {} {}\n", name, unit));
+        }
+        for (series, value) in series {
+            out.push_str(&format!("{} {}\n", series, value));
+        }
+    }
+    out
+}
+
+// metric name 只能包含 [a-zA-Z0-9_:]，并且不能以数字开头；req.page.4 => req_page_4
+pub(crate) fn sanitize(name: &str) -> String {
+    let mut s = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if s.is_empty() || s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_encode() {
+        let out = encode([
+            (r#"req{page="4",method="GET"}"#.to_string(), 27),
+            ("call.thread.worker.0".to_string(), 8),
+            (r#"req{method="GET",page="1"}"#.to_string(), 32),
+        ]);
+        assert_eq!(
+            out,
+            "# TYPE call_thread_worker_0 gauge\n\
+             call_thread_worker_0 8\n\
+             # TYPE req gauge\n\
+             req{method=\"GET\",page=\"1\"} 32\n\
+             req{method=\"GET\",page=\"4\"} 27\n"
+        );
+        assert_eq!(sanitize("1st-metric"), "_1st_metric");
+    }
+}
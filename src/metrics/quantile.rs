@@ -0,0 +1,236 @@
+// 分位数估计：CKMS (Cormode, Korn, Muthukrishnan, Srivastava) 的 targeted quantiles 版本。
+// 只为关心的几个分位数（默认 p50/p95/p99）保证误差，用远少于全部样本的内存回答 p99 延迟这类问题。
+// record 先写进一个缓冲区，攒满之后排序、合并进压缩过的样本列表；整个结构放在 Mutex 里，多个线程可以共享。
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{backend::Snapshot, key::Key, prometheus};
+
+const BUFFER_SIZE: usize = 512;
+
+// (分位数, 允许的误差)
+const DEFAULT_TARGETS: [(f64, f64); 3] = [(0.5, 0.01), (0.95, 0.005), (0.99, 0.001)];
+
+#[derive(Debug, Clone)]
+pub struct Quantiles {
+    inner: Arc<Mutex<Stream>>,
+}
+
+#[derive(Debug)]
+struct Stream {
+    targets: Vec<(f64, f64)>,
+    samples: Vec<Sample>,
+    buffer: Vec<f64>,
+    n: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    value: f64,
+    // 这个样本代表的排名范围：g 是它和前一个样本之间的排名差，delta 是排名的不确定度
+    g: f64,
+    delta: f64,
+}
+
+impl Quantiles {
+    pub fn new(targets: &[(f64, f64)]) -> Self {
+        Quantiles {
+            inner: Arc::new(Mutex::new(Stream {
+                targets: targets.to_vec(),
+                samples: Vec::new(),
+                buffer: Vec::with_capacity(BUFFER_SIZE),
+                n: 0.0,
+            })),
+        }
+    }
+
+    pub fn record(&self, value: f64) {
+        let mut stream = self.lock();
+        stream.buffer.push(value);
+        if stream.buffer.len() >= BUFFER_SIZE {
+            stream.flush();
+        }
+    }
+
+    // 还没有记录过任何值时返回 None
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let mut stream = self.lock();
+        stream.flush();
+        stream.query(q)
+    }
+
+    pub fn count(&self) -> u64 {
+        let stream = self.lock();
+        stream.n as u64 + stream.buffer.len() as u64
+    }
+
+    // 每个目标分位数一个 key：name{quantile="0.99"}，外加 name_count，可以和其它 backend 的 snapshot 放在一起导出
+    pub fn snapshot(&self, name: &str) -> Snapshot {
+        let targets = self.lock().targets.clone();
+        let mut snapshot = targets
+            .iter()
+            .filter_map(|&(q, _)| {
+                let key = Key::new(name, &[("quantile", &q.to_string())]);
+                self.quantile(q)
+                    .map(|v| (key.to_string(), v.round() as i64))
+            })
+            .collect::<Snapshot>();
+        snapshot.insert(format!("{}_count", name), self.count() as i64);
+        snapshot
+    }
+
+    pub fn prometheus_encode(&self, name: &str) -> String {
+        prometheus::encode(self.snapshot(name))
+    }
+
+    // record 的时候 panic 不会让数据处于不一致的状态，所以 poison 之后继续用
+    fn lock(&self) -> MutexGuard<'_, Stream> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Quantiles {
+    fn default() -> Self {
+        Self::new(&DEFAULT_TARGETS)
+    }
+}
+
+impl Stream {
+    // 排名 r 处允许的误差：离目标分位数越近越小
+    fn invariant(&self, r: f64) -> f64 {
+        self.targets
+            .iter()
+            .map(|&(q, eps)| {
+                if q * self.n <= r {
+                    2.0 * eps * r / q
+                } else {
+                    2.0 * eps * (self.n - r) / (1.0 - q)
+                }
+            })
+            .fold(f64::MAX, f64::min)
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.sort_by(f64::total_cmp);
+
+        // buffer 和 samples 都是有序的，一次遍历就能找到每个新值的位置
+        let mut r = 0.0;
+        let mut i = 0;
+        for &value in &buffer {
+            while i < self.samples.len() && self.samples[i].value <= value {
+                r += self.samples[i].g;
+                i += 1;
+            }
+            // 插在最前或者最后的值排名是确定的
+            let delta = if i == 0 || i == self.samples.len() {
+                0.0
+            } else {
+                (self.invariant(r).floor() - 1.0).max(0.0)
+            };
+            self.samples.insert(
+                i,
+                Sample {
+                    value,
+                    g: 1.0,
+                    delta,
+                },
+            );
+            i += 1;
+            self.n += 1.0;
+            r += 1.0;
+        }
+        buffer.clear();
+        self.buffer = buffer;
+        self.compress();
+    }
+
+    // 从后往前，把误差允许范围内的相邻样本合并
+    fn compress(&mut self) {
+        if self.samples.len() < 2 {
+            return;
+        }
+        let mut xi = self.samples.len() - 1;
+        let mut r = self.n - 1.0 - self.samples[xi].g;
+        for i in (0..self.samples.len() - 1).rev() {
+            let c = self.samples[i];
+            let x = self.samples[xi];
+            if c.g + x.g + x.delta <= self.invariant(r) {
+                self.samples[xi].g += c.g;
+                self.samples.remove(i);
+                xi -= 1;
+            } else {
+                xi = i;
+            }
+            r -= c.g;
+        }
+    }
+
+    fn query(&self, q: f64) -> Option<f64> {
+        let first = self.samples.first()?;
+        let mut t = (q * self.n).ceil();
+        t += (self.invariant(t) / 2.0).ceil();
+        let mut prev = first;
+        let mut r = 0.0;
+        for c in &self.samples[1..] {
+            r += prev.g;
+            if r + c.g + c.delta > t {
+                return Some(prev.value);
+            }
+            prev = c;
+        }
+        Some(prev.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_quantiles() {
+        let quantiles = Quantiles::default();
+        assert_eq!(quantiles.quantile(0.5), None);
+
+        // 4 个线程一共记录 1..=100_000，每个线程按打乱的顺序记录其中的四分之一
+        let n = 100_000;
+        let handles = (0..4)
+            .map(|t| {
+                let quantiles = quantiles.clone();
+                thread::spawn(move || {
+                    for i in 0..n / 4 {
+                        let i = (i * 7919) % (n / 4);
+                        quantiles.record((i * 4 + t + 1) as f64);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(quantiles.count(), n as u64);
+        for (q, eps) in DEFAULT_TARGETS {
+            let v = quantiles.quantile(q).unwrap();
+            let expected = q * n as f64;
+            assert!(
+                (v - expected).abs() <= eps * n as f64 + 1.0,
+                "p{} = {}, expected about {}",
+                q,
+                v,
+                expected
+            );
+        }
+        // 压缩之后保留的样本远少于 n
+        assert!(quantiles.lock().samples.len() < n / 10);
+
+        let snapshot = quantiles.snapshot("latency");
+        assert_eq!(snapshot["latency_count"], n as i64);
+        assert!(snapshot.contains_key(r#"latency{quantile="0.99"}"#));
+        assert!(quantiles
+            .prometheus_encode("latency")
+            .contains("# TYPE latency gauge\nlatency{quantile=\"0.5\"}"));
+    }
+}
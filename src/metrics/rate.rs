@@ -0,0 +1,75 @@
+// 两次 snapshot 之间每个计数器的每秒增量：dashboard 关心的是 req/s，而不是从启动到现在的总数。
+// 上一次的 snapshot 和时间保存在 RateTracker 里，每次调用 rates() 都和上一次比较，然后替换掉它。
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::backend::{MetricsBackend, Snapshot};
+
+pub struct RateTracker<M> {
+    metrics: M,
+    previous: Snapshot,
+    at: Instant,
+}
+
+impl<M: MetricsBackend> RateTracker<M> {
+    pub fn new(metrics: M) -> Self {
+        let previous = metrics.snapshot();
+        RateTracker {
+            metrics,
+            previous,
+            at: Instant::now(),
+        }
+    }
+
+    // 上一次调用（或者 new）以来每个 key 的每秒变化量；新出现的 key 从 0 算起
+    pub fn rates(&mut self) -> HashMap<String, f64> {
+        let current = self.metrics.snapshot();
+        let now = Instant::now();
+        let rates = compute(&self.previous, &current, now - self.at);
+        self.previous = current;
+        self.at = now;
+        rates
+    }
+}
+
+fn compute(previous: &Snapshot, current: &Snapshot, elapsed: Duration) -> HashMap<String, f64> {
+    let secs = elapsed.as_secs_f64();
+    current
+        .iter()
+        .map(|(key, &value)| {
+            let delta = value.wrapping_sub(previous.get(key).copied().unwrap_or(0));
+            // 两次调用之间没有经过时间时，没有办法算速率
+            let rate = if secs > 0.0 { delta as f64 / secs } else { 0.0 };
+            (key.clone(), rate)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CmapMetrics;
+
+    #[test]
+    fn test_rate_tracker() -> anyhow::Result<()> {
+        let previous = HashMap::from([("req".to_string(), 100), ("gone".to_string(), 5)]);
+        let current = HashMap::from([("req".to_string(), 150), ("new".to_string(), 10)]);
+        let rates = compute(&previous, &current, Duration::from_millis(500));
+        assert_eq!(
+            rates,
+            HashMap::from([("req".to_string(), 100.0), ("new".to_string(), 20.0)])
+        );
+
+        let metrics = CmapMetrics::new();
+        let mut tracker = RateTracker::new(metrics.clone());
+        metrics.inc_by("req", 10)?;
+        std::thread::sleep(Duration::from_millis(10));
+        let rate = tracker.rates()["req"];
+        assert!(rate > 0.0 && rate <= 1000.0);
+        // 没有新的请求，速率回到 0
+        assert_eq!(tracker.rates()["req"], 0.0);
+        Ok(())
+    }
+}
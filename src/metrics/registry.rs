@@ -0,0 +1,370 @@
+// 把不同类型的 metric 放到一个地方：每个 name 是一个 family，有自己的类型（counter/gauge/histogram）和 help，
+// 同一个 family 下面按 label 区分不同的 series。encode 一次把所有 family 输出成 Prometheus 的格式：
+//   # HELP http_requests Total HTTP requests
+//   # TYPE http_requests counter
+//   http_requests{method="GET"} 27
+use anyhow::{bail, Result};
+use dashmap::DashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use super::{
+    atomic::AtomicF64, cmap::CmapMetrics, gauge::CmapGauges, key::Key, prometheus::sanitize,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    families: Arc<RwLock<BTreeMap<String, Family>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Family {
+    help: String,
+    metric: Metric,
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(CounterFamily),
+    Gauge(GaugeFamily),
+    Histogram(HistogramFamily),
+}
+
+impl Metric {
+    fn kind(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+
+    fn series(&self) -> Vec<(String, f64)> {
+        match self {
+            Metric::Counter(c) => c.values.iter().map(|(k, v)| (k, v as f64)).collect(),
+            Metric::Gauge(g) => g.values.snapshot().into_iter().collect(),
+            Metric::Histogram(h) => h.series(),
+        }
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 同一个 name 再注册一次返回同一个 family；name 已经被别的类型用掉时返回错误
+    pub fn counter(&self, name: &str, help: &str) -> Result<CounterFamily> {
+        let metric = self.register(name, help, || {
+            Metric::Counter(CounterFamily {
+                name: sanitize(name).into(),
+                values: CmapMetrics::new(),
+            })
+        });
+        match metric {
+            Metric::Counter(c) => Ok(c),
+            other => bail!(
+                "metric {} is already registered as a {}",
+                name,
+                other.kind()
+            ),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> Result<GaugeFamily> {
+        let metric = self.register(name, help, || {
+            Metric::Gauge(GaugeFamily {
+                name: sanitize(name).into(),
+                values: CmapGauges::new(),
+            })
+        });
+        match metric {
+            Metric::Gauge(g) => Ok(g),
+            other => bail!(
+                "metric {} is already registered as a {}",
+                name,
+                other.kind()
+            ),
+        }
+    }
+
+    // buckets 是每个桶的上界（le），不需要包含 +Inf；重复注册时沿用第一次的 buckets
+    pub fn histogram(&self, name: &str, help: &str, buckets: &[f64]) -> Result<HistogramFamily> {
+        let mut bounds = buckets.to_vec();
+        if bounds.iter().any(|b| b.is_nan()) {
+            bail!("histogram {} has a NaN bucket", name);
+        }
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        let metric = self.register(name, help, || {
+            Metric::Histogram(HistogramFamily {
+                name: sanitize(name).into(),
+                bounds: bounds.into(),
+                series: Default::default(),
+            })
+        });
+        match metric {
+            Metric::Histogram(h) => Ok(h),
+            other => bail!(
+                "metric {} is already registered as a {}",
+                name,
+                other.kind()
+            ),
+        }
+    }
+
+    fn register(&self, name: &str, help: &str, metric: impl FnOnce() -> Metric) -> Metric {
+        let name = sanitize(name);
+        let mut families = self.families.write().unwrap_or_else(|e| e.into_inner());
+        let family = families.entry(name).or_insert_with(|| Family {
+            help: help.to_string(),
+            metric: metric(),
+        });
+        family.metric.clone()
+    }
+
+    // 所有 family 的所有 series，key 的格式和 encode 输出的一样
+    pub fn snapshot(&self) -> HashMap<String, f64> {
+        let families = self.families.read().unwrap_or_else(|e| e.into_inner());
+        families
+            .values()
+            .flat_map(|family| family.metric.series())
+            .collect()
+    }
+
+    pub fn encode(&self) -> String {
+        let families = self.families.read().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            // String 实现了 fmt::Write，写入不会失败
+            if !family.help.is_empty() {
+                let help = family.help.replace('\\', "\\\\").replace('\n', "\\n");
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+            }
+            let _ = writeln!(out, "# TYPE {} {}", name, family.metric.kind());
+            let mut series = family.metric.series();
+            // histogram 的 series 已经按 bucket 的顺序排好了，不能再按字符串排
+            if !matches!(family.metric, Metric::Histogram(_)) {
+                series.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+            for (key, value) in series {
+                let _ = writeln!(out, "{} {}", key, value);
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+// 只能增加的计数器
+#[derive(Debug, Clone)]
+pub struct CounterFamily {
+    name: Arc<str>,
+    values: CmapMetrics,
+}
+
+impl CounterFamily {
+    pub fn inc(&self, labels: &[(&str, &str)]) {
+        self.inc_by(labels, 1);
+    }
+
+    // counter 只能增加，所以 delta 是 u64
+    pub fn inc_by(&self, labels: &[(&str, &str)], delta: u64) {
+        let delta = delta.min(i64::MAX as u64) as i64;
+        let _ = self.values.inc_by(Key::new(&*self.name, labels), delta);
+    }
+
+    pub fn get(&self, labels: &[(&str, &str)]) -> u64 {
+        self.values
+            .get(Key::new(&*self.name, labels).to_string())
+            .unwrap_or(0) as u64
+    }
+}
+
+// 可以任意设置的值
+#[derive(Debug, Clone)]
+pub struct GaugeFamily {
+    name: Arc<str>,
+    values: CmapGauges,
+}
+
+impl GaugeFamily {
+    pub fn set(&self, labels: &[(&str, &str)], value: f64) {
+        self.values.set(Key::new(&*self.name, labels), value);
+    }
+
+    pub fn add(&self, labels: &[(&str, &str)], delta: f64) {
+        self.values.add(Key::new(&*self.name, labels), delta);
+    }
+
+    pub fn get(&self, labels: &[(&str, &str)]) -> Option<f64> {
+        self.values.get(Key::new(&*self.name, labels).to_string())
+    }
+}
+
+// 按 bucket 统计分布：每个 observe 落到第一个 value <= le 的桶里，输出时桶是累加的
+#[derive(Debug, Clone)]
+pub struct HistogramFamily {
+    name: Arc<str>,
+    bounds: Arc<[f64]>,
+    // key 是 label 部分，比如 {method="GET"}
+    series: Arc<DashMap<Key, Arc<Histogram>>>,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    // 最后一个是 +Inf
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicF64,
+}
+
+impl HistogramFamily {
+    pub fn observe(&self, labels: &[(&str, &str)], value: f64) {
+        let histogram = self
+            .series
+            .entry(Key::new("", labels))
+            .or_insert_with(|| {
+                Arc::new(Histogram {
+                    buckets: (0..=self.bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+                    count: AtomicU64::new(0),
+                    sum: AtomicF64::new(0.0),
+                })
+            })
+            .clone();
+        let i = self.bounds.partition_point(|&le| le < value);
+        histogram.buckets[i].fetch_add(1, Ordering::Relaxed);
+        histogram.count.fetch_add(1, Ordering::Relaxed);
+        histogram.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn count(&self, labels: &[(&str, &str)]) -> u64 {
+        self.series
+            .get(&Key::new("", labels))
+            .map_or(0, |h| h.count.load(Ordering::Relaxed))
+    }
+
+    pub fn sum(&self, labels: &[(&str, &str)]) -> f64 {
+        self.series
+            .get(&Key::new("", labels))
+            .map_or(0.0, |h| h.sum.load(Ordering::Relaxed))
+    }
+
+    // name_bucket{le="..."} 是累加的，最后是 le="+Inf"，然后是 name_sum 和 name_count
+    fn series(&self) -> Vec<(String, f64)> {
+        let mut keys = self
+            .series
+            .iter()
+            .map(|entry| {
+                (
+                    entry.key().to_string(),
+                    entry.key().clone(),
+                    entry.value().clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut out = Vec::new();
+        for (_, key, histogram) in keys {
+            let labels = key
+                .labels()
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+            let les = self
+                .bounds
+                .iter()
+                .map(|b| b.to_string())
+                .chain(["+Inf".to_string()]);
+            let mut cumulative = 0;
+            for (le, bucket) in les.zip(&histogram.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let mut labels = labels.clone();
+                labels.push(("le", &le));
+                let series = Key::new(format!("{}_bucket", self.name), &labels);
+                out.push((series.to_string(), cumulative as f64));
+            }
+            let sum = histogram.sum.load(Ordering::Relaxed);
+            out.push((
+                Key::new(format!("{}_sum", self.name), &labels).to_string(),
+                sum,
+            ));
+            let count = histogram.count.load(Ordering::Relaxed) as f64;
+            out.push((
+                Key::new(format!("{}_count", self.name), &labels).to_string(),
+                count,
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_registry() -> Result<()> {
+        let registry = Registry::new();
+        let requests = registry.counter("http_requests", "Total HTTP requests")?;
+        let in_progress = registry.gauge("http_in_progress", "Requests being served")?;
+        let latency = registry.histogram("http_latency_seconds", "", &[0.5, 0.1])?;
+
+        let handles = (0..4)
+            .map(|i| {
+                let requests = registry.counter("http_requests", "ignored")?;
+                let latency = latency.clone();
+                Ok(thread::spawn(move || {
+                    let method = if i % 2 == 0 { "GET" } else { "POST" };
+                    for j in 0..10 {
+                        requests.inc(&[("method", method)]);
+                        latency.observe(&[], j as f64 * 0.25);
+                    }
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for handle in handles {
+            handle.join().expect("metrics worker panicked");
+        }
+        in_progress.set(&[], 3.0);
+        in_progress.add(&[], -1.0);
+
+        assert_eq!(requests.get(&[("method", "GET")]), 20);
+        assert_eq!(in_progress.get(&[]), Some(2.0));
+        assert_eq!(latency.count(&[]), 40);
+        assert_eq!(latency.sum(&[]), 45.0);
+        assert!(registry.gauge("http_requests", "").is_err());
+
+        assert_eq!(
+            registry.encode(),
+            "# HELP http_in_progress Requests being served\n\
+             # TYPE http_in_progress gauge\n\
+             http_in_progress 2\n\
+             # TYPE http_latency_seconds histogram\n\
+             http_latency_seconds_bucket{le=\"0.1\"} 4\n\
+             http_latency_seconds_bucket{le=\"0.5\"} 12\n\
+             http_latency_seconds_bucket{le=\"+Inf\"} 40\n\
+             http_latency_seconds_sum 45\n\
+             http_latency_seconds_count 40\n\
+             # HELP http_requests Total HTTP requests\n\
+             # TYPE http_requests counter\n\
+             http_requests{method=\"GET\"} 20\n\
+             http_requests{method=\"POST\"} 20\n"
+        );
+        assert_eq!(registry.snapshot()["http_latency_seconds_count"], 40.0);
+        Ok(())
+    }
+}
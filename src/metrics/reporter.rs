@@ -0,0 +1,191 @@
+// 后台定期上报：一个线程每隔 interval 取一次 snapshot，交给调用者提供的 sink（打印、写文件、推给 statsd ……）。
+// 例子里 main 线程自己 loop + sleep + println 的逻辑搬到这里，并且可以干净地停下来。
+// 需要把 snapshot 格式化成文本再输出时用 builder：
+//   let reporter = MetricsReporter::builder()
+//       .interval(Duration::from_secs(2))
+//       .formatter(|s| format!("{:?}\n", s))
+//       .sink(|text| print!("{}", text))
+//       .start(metrics);
+//   reporter.stop();
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::backend::{MetricsBackend, Snapshot};
+
+pub struct MetricsReporter {
+    shutdown: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsReporter {
+    pub fn builder() -> ReporterBuilder {
+        ReporterBuilder::default()
+    }
+
+    pub fn start<M, F>(metrics: M, interval: Duration, mut sink: F) -> Self
+    where
+        M: MetricsBackend + Send + 'static,
+        F: FnMut(&Snapshot) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            // 用带超时的 recv 代替 sleep：收到 shutdown（或者 MetricsReporter 已经被 drop）时不需要等完整个 interval
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                sink(&metrics.snapshot());
+            }
+        });
+        MetricsReporter {
+            shutdown: Some(tx),
+            handle: Some(handle),
+        }
+    }
+
+    // 停止上报线程并等待它退出；sink panic 时把 panic 传给调用者
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            if let Err(e) = handle.join() {
+                if !thread::panicking() {
+                    std::panic::resume_unwind(e);
+                }
+            }
+        }
+    }
+}
+
+// drop 时也会停止线程，不会留下一个一直在跑的 reporter
+impl Drop for MetricsReporter {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+type Formatter = Box<dyn Fn(&Snapshot) -> String + Send>;
+type Sink = Box<dyn FnMut(&str) + Send>;
+
+// 默认每秒一次，按 key 排序输出 "key: value"，写到 stdout
+pub struct ReporterBuilder {
+    interval: Duration,
+    formatter: Formatter,
+    sink: Sink,
+}
+
+impl Default for ReporterBuilder {
+    fn default() -> Self {
+        ReporterBuilder {
+            interval: Duration::from_secs(1),
+            formatter: Box::new(format_sorted),
+            sink: Box::new(|text| print!("{}", text)),
+        }
+    }
+}
+
+impl ReporterBuilder {
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn formatter(mut self, formatter: impl Fn(&Snapshot) -> String + Send + 'static) -> Self {
+        self.formatter = Box::new(formatter);
+        self
+    }
+
+    pub fn sink(mut self, sink: impl FnMut(&str) + Send + 'static) -> Self {
+        self.sink = Box::new(sink);
+        self
+    }
+
+    pub fn start<M: MetricsBackend + Send + 'static>(self, metrics: M) -> MetricsReporter {
+        let ReporterBuilder {
+            interval,
+            formatter,
+            mut sink,
+        } = self;
+        MetricsReporter::start(metrics, interval, move |snapshot| {
+            sink(&formatter(snapshot))
+        })
+    }
+}
+
+// 每次 report 之后空一行，连续输出的时候容易分辨
+fn format_sorted(snapshot: &Snapshot) -> String {
+    let mut entries = snapshot.iter().collect::<Vec<_>>();
+    entries.sort();
+    let mut out = entries
+        .into_iter()
+        .map(|(key, value)| format!("{}: {}\n", key, value))
+        .collect::<String>();
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CmapMetrics;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_metrics_reporter() -> anyhow::Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("req")?;
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let reports = reports.clone();
+            move |s: &Snapshot| reports.lock().unwrap().push(s.clone())
+        };
+        let reporter = MetricsReporter::start(metrics.clone(), Duration::from_millis(10), sink);
+        thread::sleep(Duration::from_millis(100));
+        reporter.stop();
+
+        let reports = std::mem::take(&mut *reports.lock().unwrap());
+        assert!(!reports.is_empty());
+        assert_eq!(reports[0].get("req"), Some(&1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reporter_builder() -> anyhow::Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_by("req", 3)?;
+        metrics.inc("conn")?;
+
+        // mock sink：把每次输出的文本收集起来
+        let (tx, rx) = mpsc::channel::<String>();
+        let reporter = MetricsReporter::builder()
+            .interval(Duration::from_millis(10))
+            .sink(move |text| tx.send(text.to_string()).unwrap())
+            .start(metrics.clone());
+        let first = rx.recv_timeout(Duration::from_secs(1))?;
+        reporter.stop();
+        assert_eq!(first, "conn: 1\nreq: 3\n\n");
+
+        let (tx, rx) = mpsc::channel::<String>();
+        let reporter = MetricsReporter::builder()
+            .interval(Duration::from_millis(10))
+            .formatter(|s| format!("{} keys", s.len()))
+            .sink(move |text| {
+                let _ = tx.send(text.to_string());
+            })
+            .start(metrics);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1))?, "2 keys");
+        drop(reporter);
+        // stop 之后不会再有新的输出，channel 的发送端随线程一起释放
+        while rx.recv_timeout(Duration::from_secs(1)).is_ok() {}
+        assert!(matches!(
+            rx.try_recv(),
+            Err(mpsc::TryRecvError::Disconnected)
+        ));
+        Ok(())
+    }
+}
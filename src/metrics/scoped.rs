@@ -0,0 +1,107 @@
+// 给 key 自动加前缀的 handle：redis 模块拿到 metrics.scoped("redis.")，之后只写 inc("get")，
+// 实际的 key 是 "redis.get"。底层还是同一个 backend，clone 只是多一份 Arc 和前缀。
+use anyhow::Result;
+use std::sync::Arc;
+
+use super::backend::{MetricsBackend, Snapshot};
+
+#[derive(Debug, Clone)]
+pub struct Scoped<M> {
+    inner: M,
+    prefix: Arc<str>,
+}
+
+impl<M: MetricsBackend> Scoped<M> {
+    pub fn new(inner: M, prefix: &str) -> Self {
+        Scoped {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl<M: MetricsBackend + Clone> Scoped<M> {
+    // 嵌套的 scope 前缀拼在一起：scoped("redis.").scoped("cmd.") => "redis.cmd."
+    pub fn scoped(&self, prefix: &str) -> Scoped<M> {
+        Scoped::new(self.inner.clone(), &self.key(prefix))
+    }
+}
+
+impl<M: MetricsBackend> MetricsBackend for Scoped<M> {
+    fn inc(&self, key: &str) -> Result<()> {
+        self.inner.inc(&self.key(key))
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        self.inner.dec(&self.key(key))
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        self.inner.get(&self.key(key))
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.iter().collect()
+    }
+
+    // 只包含这个前缀下的 key，并且去掉了前缀
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_> {
+        Box::new(self.inner.iter().filter_map(|(key, value)| {
+            key.strip_prefix(&*self.prefix)
+                .map(|key| (key.to_string(), value))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics};
+    use std::{collections::HashMap, thread};
+
+    #[test]
+    fn test_scoped() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let redis = metrics.scoped("redis.");
+        let handles = (0..3)
+            .map(|i| {
+                let worker = redis.scoped(&format!("worker.{}.", i));
+                thread::spawn(move || worker.inc("cmd"))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        redis.dec("conn")?;
+        metrics.inc("other")?;
+
+        assert_eq!(metrics.get("redis.worker.1.cmd"), Some(1));
+        assert_eq!(redis.get("conn"), Some(-1));
+        assert_eq!(redis.scoped("worker.2.").prefix(), "redis.worker.2.");
+        assert_eq!(
+            redis.snapshot(),
+            HashMap::from([
+                ("worker.0.cmd".to_string(), 1),
+                ("worker.1.cmd".to_string(), 1),
+                ("worker.2.cmd".to_string(), 1),
+                ("conn".to_string(), -1),
+            ])
+        );
+
+        // 固定 key 的 backend 也可以用，完整的 key 仍然需要在 new 的时候给出
+        let amap = AmapMetrics::new(&["redis.get", "redis.set"]);
+        let redis = amap.scoped("redis.");
+        redis.inc("get")?;
+        assert!(redis.inc("del").is_err());
+        assert_eq!(amap.get("redis.get"), Some(1));
+        Ok(())
+    }
+}
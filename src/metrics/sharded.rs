@@ -0,0 +1,326 @@
+// 分片计数器：和 AmapMetrics 一样 key 集合在 new 的时候固定，但每个 key 有多个 shard，每个线程只写自己的那个 shard。
+// 高并发下 AmapMetrics 的每个 atomic 都在各个核之间来回传递 cache line；分片之后写操作之间没有共享，
+// 读的时候才把所有 shard 加起来。适合写多读少的计数器。
+use anyhow::{anyhow, Result};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use super::{
+    backend::{MetricsBackend, Snapshot},
+    overflow::{Op, OverflowPolicy},
+    persist,
+    table::FormatOptions,
+};
+
+// 每个 counter 独占一条 64 字节的 cache line，避免 false sharing
+#[derive(Debug, Default)]
+#[repr(align(64))]
+pub(crate) struct Padded(pub(crate) AtomicI64);
+
+#[derive(Debug, Clone)]
+pub struct ShardedMetrics {
+    inner: Arc<Inner>,
+    overflow: OverflowPolicy,
+}
+
+#[derive(Debug)]
+struct Inner {
+    index: HashMap<&'static str, usize>,
+    // shards[shard][key]
+    shards: Vec<Vec<Padded>>,
+}
+
+impl ShardedMetrics {
+    pub fn new(metric_names: &[&'static str]) -> Self {
+        let shards = thread::available_parallelism().map_or(8, |n| n.get());
+        Self::with_shards(metric_names, shards)
+    }
+
+    pub fn with_shards(metric_names: &[&'static str], shards: usize) -> Self {
+        let index = metric_names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name, i))
+            .collect::<HashMap<_, _>>();
+        let shards = (0..shards.max(1))
+            .map(|_| (0..metric_names.len()).map(|_| Padded::default()).collect())
+            .collect();
+        ShardedMetrics {
+            inner: Arc::new(Inner { index, shards }),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+
+    // 溢出策略作用在每个 shard 上；读的时候把 shard 加起来，Saturate 和 Error 时求和是饱和的：
+    // 每个 shard 都没有溢出，但加起来可能超出 i64，这时候停在 i64::MAX / i64::MIN 而不是 wrap
+    pub fn with_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        self.inc_by(key, 1)
+    }
+
+    pub fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        self.inc_by(key, -1)
+    }
+
+    pub fn inc_by(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
+        let key = key.as_ref();
+        let i = self.index(key)?;
+        let shard = &self.inner.shards[shard_id() % self.inner.shards.len()];
+        self.overflow.apply_atomic(key, &shard[i].0, delta, Op::Add)
+    }
+
+    // 读的时候把所有 shard 加起来；并发写入时结果是某个中间状态，但不会丢失已经完成的写入
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        let i = *self.inner.index.get(key.as_ref())?;
+        Some(self.sum(i))
+    }
+
+    // 把 other 的每个值加到同名的 key 上。key 的集合在 new 的时候就固定了，不能像 AmapMetrics 那样 register，
+    // 所以 other 里有这里没有的 key 时返回错误；先检查完所有 key 再加，出错时什么都不会改
+    pub fn merge(&self, other: &ShardedMetrics) -> Result<()> {
+        for &key in other.inner.index.keys() {
+            self.index(key)?;
+        }
+        for (key, value) in other.iter() {
+            self.inc_by(key, value)?;
+        }
+        Ok(())
+    }
+
+    // 读出当前值并清零：每个 shard 各自 swap，所以不会丢失或者重复计数
+    pub fn drain(&self) -> Snapshot {
+        self.inner
+            .index
+            .iter()
+            .map(|(&key, &i)| {
+                let value = self
+                    .inner
+                    .shards
+                    .iter()
+                    .map(|shard| shard[i].0.swap(0, Ordering::Relaxed))
+                    .fold(0, self.add());
+                (key.to_string(), value)
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for counter in self.inner.shards.iter().flatten() {
+            counter.0.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> HashMap<&'static str, i64> {
+        self.iter().collect()
+    }
+
+    // 每走到一个 key 才把它的 shard 加起来，不持有锁
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, i64)> + '_ {
+        self.inner.index.iter().map(|(&key, &i)| (key, self.sum(i)))
+    }
+
+    // 写成 JSON 文件，重启之后用 load 恢复
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        persist::save(path.as_ref(), &MetricsBackend::snapshot(self))
+    }
+
+    // 恢复出来的值放在第一个 shard 上，其它 shard 清零；没有注册的 key 被忽略
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<()> {
+        for (key, value) in persist::load(path.as_ref())? {
+            let Some(&i) = self.inner.index.get(key.as_str()) else {
+                continue;
+            };
+            for (n, shard) in self.inner.shards.iter().enumerate() {
+                shard[i]
+                    .0
+                    .store(if n == 0 { value } else { 0 }, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    fn sum(&self, i: usize) -> i64 {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard[i].0.load(Ordering::Relaxed))
+            .fold(0, self.add())
+    }
+
+    // 把各个 shard 加起来时用的加法；Error 的 checked_add 失败时也只能饱和，因为读操作不返回错误
+    fn add(&self) -> fn(i64, i64) -> i64 {
+        match self.overflow {
+            OverflowPolicy::Wrap => i64::wrapping_add,
+            OverflowPolicy::Saturate | OverflowPolicy::Error => i64::saturating_add,
+        }
+    }
+
+    fn index(&self, key: &str) -> Result<usize> {
+        self.inner
+            .index
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow!("key {} not found", key))
+    }
+}
+
+// 每个线程第一次写的时候领一个编号，之后一直用同一个 shard
+fn shard_id() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static ID: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+    ID.with(|id| {
+        id.get().unwrap_or_else(|| {
+            let next = NEXT.fetch_add(1, Ordering::Relaxed);
+            id.set(Some(next));
+            next
+        })
+    })
+}
+
+impl MetricsBackend for ShardedMetrics {
+    fn inc(&self, key: &str) -> Result<()> {
+        ShardedMetrics::inc(self, key)
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        ShardedMetrics::dec(self, key)
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        ShardedMetrics::get(self, key)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        MetricsBackend::iter(self).collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, i64)> + '_> {
+        Box::new(ShardedMetrics::iter(self).map(|(key, value)| (key.to_string(), value)))
+    }
+}
+
+impl fmt::Display for ShardedMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.write_str(&self.format_with(&FormatOptions::default()));
+        }
+        for (key, &i) in self.inner.index.iter() {
+            writeln!(f, "{}: {}", key, self.sum(i))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_metrics() -> Result<()> {
+        let metrics = ShardedMetrics::with_shards(&["req", "conn"], 3);
+        let handles = (0..8)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.inc("req")?;
+                    }
+                    metrics.inc_by("conn", 5)?;
+                    metrics.dec("conn")
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert_eq!(metrics.get("req"), Some(8000));
+        assert_eq!(metrics.iter().map(|(_, v)| v).sum::<i64>(), 8032);
+        assert_eq!(
+            metrics.snapshot(),
+            HashMap::from([("req", 8000), ("conn", 32)])
+        );
+        assert!(metrics.inc("unknown").is_err());
+        assert_eq!(metrics.get("unknown"), None);
+
+        metrics.reset();
+        assert_eq!(MetricsBackend::get(&metrics, "req"), Some(0));
+        assert_eq!(std::mem::align_of::<Padded>(), 64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_drain() -> Result<()> {
+        let metrics = ShardedMetrics::with_shards(&["req", "conn"], 3);
+        // 分散写到不同的 shard 上，drain 要把所有 shard 加起来再清零
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || metrics.inc_by("req", 5))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("metrics worker panicked")?;
+        }
+        assert_eq!(
+            metrics.drain(),
+            Snapshot::from([("req".to_string(), 20), ("conn".to_string(), 0)])
+        );
+        assert_eq!(metrics.get("req"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_merge() -> Result<()> {
+        let metrics = ShardedMetrics::with_shards(&["req", "conn"], 3);
+        metrics.inc("req")?;
+        let other = ShardedMetrics::with_shards(&["req"], 2);
+        other.inc_by("req", 10)?;
+        metrics.merge(&other)?;
+        assert_eq!(metrics.get("req"), Some(11));
+        assert_eq!(metrics.get("conn"), Some(0));
+
+        // key 集合是固定的，other 里多出来的 key 是错误，而且一个值都不会加上去
+        let foreign = ShardedMetrics::with_shards(&["req", "extra"], 2);
+        foreign.inc_by("req", 10)?;
+        foreign.inc("extra")?;
+        let err = metrics.merge(&foreign).unwrap_err();
+        assert!(err.to_string().contains("extra"));
+        assert_eq!(metrics.get("req"), Some(11));
+        assert_eq!(metrics.get("extra"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_overflow_error() -> Result<()> {
+        let metrics = ShardedMetrics::with_shards(&["c"], 2).with_overflow(OverflowPolicy::Error);
+        // 每个 shard 单独都没有溢出，加起来超出 i64
+        metrics.inner.shards[0][0]
+            .0
+            .store(i64::MAX, Ordering::Relaxed);
+        metrics.inner.shards[1][0].0.store(1, Ordering::Relaxed);
+        assert_eq!(metrics.get("c"), Some(i64::MAX));
+        assert_eq!(metrics.snapshot(), HashMap::from([("c", i64::MAX)]));
+        assert_eq!(metrics.iter().collect::<Vec<_>>(), vec![("c", i64::MAX)]);
+        assert_eq!(
+            metrics.drain(),
+            Snapshot::from([("c".to_string(), i64::MAX)])
+        );
+        assert_eq!(metrics.get("c"), Some(0));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+// StatsD 推送：每个值一行 name:value|type，通过 UDP 发给 statsd/telegraf agent，不需要对方来抓取。
+// label 用 DogStatsD 的扩展写法 |#k:v,k:v，不认识的 agent 会直接忽略它。
+// 定期推送可以和 MetricsReporter 组合：
+//   MetricsReporter::start(metrics, interval, move |s| { let _ = exporter.send_gauges(s); })
+use anyhow::Result;
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use super::{backend::Snapshot, key::Key};
+
+// 一个 UDP 包里最多放这么多字节，避免在常见的 1500 MTU 上被分片
+const MAX_PACKET: usize = 1400;
+
+#[derive(Debug)]
+pub struct StatsdExporter {
+    socket: UdpSocket,
+}
+
+impl StatsdExporter {
+    pub fn new(addr: impl ToSocketAddrs) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        Ok(StatsdExporter { socket })
+    }
+
+    // 当前值，作为 gauge (|g) 发送。
+    // 带符号的 gauge 值（name:-3|g）在 statsd 里表示在原值上增减，而不是设置成 -3；
+    // 所以负数先发 name:0|g 清零，再发 name:-3|g。两行放在同一个包里，agent 按顺序处理
+    pub fn send_gauges(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut entries = snapshot.iter().collect::<Vec<_>>();
+        entries.sort();
+        let lines = entries
+            .into_iter()
+            .map(|(key, &value)| {
+                if value < 0 {
+                    format!("{}\n{}", line(key, 0, "g"), line(key, value, "g"))
+                } else {
+                    line(key, value, "g")
+                }
+            })
+            .collect::<Vec<_>>();
+        self.send_lines(&lines)
+    }
+
+    // 每次更新时直接推送增量，作为 counter (|c) 发送
+    pub fn count(&self, key: &str, delta: i64) -> Result<()> {
+        self.send_lines(&[line(key, delta, "c")])
+    }
+
+    // 多行合并进尽量少的包，行与行之间用 \n 分隔；一个元素里的多行不会被拆到两个包里
+    fn send_lines(&self, lines: &[String]) -> Result<()> {
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET {
+                self.socket.send(packet.as_bytes())?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(line);
+        }
+        if !packet.is_empty() {
+            self.socket.send(packet.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn line(key: &str, value: i64, kind: &str) -> String {
+    let key = Key::parse(key);
+    let mut line = format!("{}:{}|{}", sanitize(key.name()), value, kind);
+    if !key.labels().is_empty() {
+        let tags = key
+            .labels()
+            .iter()
+            .map(|(k, v)| format!("{}:{}", sanitize(k), sanitize(v)))
+            .collect::<Vec<_>>();
+        line.push_str("|#");
+        line.push_str(&tags.join(","));
+    }
+    line
+}
+
+// : | @ # , 和换行在 statsd 的行格式里有特殊含义
+fn sanitize(s: &str) -> String {
+    s.replace([':', '|', '@', '#', ',', '\n'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, time::Duration};
+
+    #[test]
+    fn test_statsd_exporter() -> Result<()> {
+        let agent = UdpSocket::bind("127.0.0.1:0")?;
+        agent.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let exporter = StatsdExporter::new(agent.local_addr()?)?;
+        let mut buf = [0u8; 2048];
+
+        let snapshot = HashMap::from([
+            ("conn".to_string(), 3),
+            (r#"req{page="4"}"#.to_string(), 27),
+        ]);
+        exporter.send_gauges(&snapshot)?;
+        let n = agent.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"conn:3|g\nreq:27|g|#page:4");
+
+        exporter.count("bytes", 512)?;
+        let n = agent.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"bytes:512|c");
+
+        // 负数的 gauge 先清零再减，不能直接发 -2|g
+        let snapshot = HashMap::from([("active".to_string(), -2), ("conn".to_string(), 1)]);
+        exporter.send_gauges(&snapshot)?;
+        let n = agent.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"active:0|g\nactive:-2|g\nconn:1|g");
+
+        // 超过 MAX_PACKET 时拆成多个包
+        let big = (0..200)
+            .map(|i| (format!("metric.{:03}", i), i))
+            .collect::<Snapshot>();
+        exporter.send_gauges(&big)?;
+        let mut received = 0;
+        while received < 200 {
+            let n = agent.recv(&mut buf)?;
+            assert!(n <= MAX_PACKET);
+            received += buf[..n].split(|&b| b == b'\n').count();
+        }
+        assert_eq!(received, 200);
+        Ok(())
+    }
+}
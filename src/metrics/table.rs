@@ -0,0 +1,148 @@
+// 把所有计数器排成一张对齐的表，给人看的。key 一多，Display 那种 "key: value" 一行一个、
+// 顺序还随 HashMap 变化的输出就没法看了。`{:#}` 用默认的 FormatOptions，format_with 可以自己配置。
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    // key 这一列的宽度；None 时按最长的 key 对齐，比这个长的 key 不会被截断
+    pub key_width: Option<usize>,
+    // 数字右对齐，个十百千位对在一起
+    pub align_right: bool,
+    // 最后加一行所有 key 的合计
+    pub totals: bool,
+    // 只显示值最大的 n 个 key，其它的合成一行 "... (k more)"
+    pub top: Option<usize>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            key_width: None,
+            align_right: true,
+            totals: false,
+            top: None,
+        }
+    }
+}
+
+impl FormatOptions {
+    pub fn key_width(mut self, width: usize) -> Self {
+        self.key_width = Some(width);
+        self
+    }
+
+    pub fn align_right(mut self, align_right: bool) -> Self {
+        self.align_right = align_right;
+        self
+    }
+
+    pub fn totals(mut self, totals: bool) -> Self {
+        self.totals = totals;
+        self
+    }
+
+    pub fn top(mut self, n: usize) -> Self {
+        self.top = Some(n);
+        self
+    }
+}
+
+// unit(key) 返回 key 的单位，跟在值的后面
+pub(crate) fn render(
+    entries: impl IntoIterator<Item = (String, i64)>,
+    opts: &FormatOptions,
+    unit: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut entries = entries.into_iter().collect::<Vec<_>>();
+    let total = entries
+        .iter()
+        .fold(0i64, |sum, (_, v)| sum.wrapping_add(*v));
+    // 默认按 key 排序；top-N 时按值从大到小，值相同的再按 key
+    match opts.top {
+        Some(_) => entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+        None => entries.sort(),
+    }
+    let hidden = match opts.top {
+        Some(n) if n < entries.len() => entries.split_off(n).len(),
+        _ => 0,
+    };
+
+    let key_width = opts
+        .key_width
+        .unwrap_or_else(|| entries.iter().map(|(k, _)| k.len()).max().unwrap_or(0));
+    let mut values = entries.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+    if opts.totals {
+        values.push(total);
+    }
+    let value_width = values
+        .iter()
+        .map(|v| v.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    let line = |out: &mut String, key: &str, value: i64, unit: Option<String>| {
+        // String 实现了 fmt::Write，写入不会失败
+        let _ = if opts.align_right {
+            write!(
+                out,
+                "{:<kw$}  {:>vw$}",
+                key,
+                value,
+                kw = key_width,
+                vw = value_width
+            )
+        } else {
+            write!(out, "{:<kw$}  {}", key, value, kw = key_width)
+        };
+        let _ = match unit {
+            Some(unit) => writeln!(out, " {}", unit),
+            None => writeln!(out),
+        };
+    };
+    for (key, value) in &entries {
+        line(&mut out, key, *value, unit(key));
+    }
+    if hidden > 0 {
+        let _ = writeln!(out, "... ({} more)", hidden);
+    }
+    if opts.totals {
+        line(&mut out, "total", total, None);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics, MetricsBackend};
+
+    #[test]
+    fn test_format_with() -> anyhow::Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc_by("req.page.1", 1200)?;
+        metrics.inc_by("req.page.2", 35)?;
+        metrics.inc_by("conn", 7)?;
+        assert_eq!(
+            format!("{:#}", metrics),
+            "conn           7\n\
+             req.page.1  1200\n\
+             req.page.2    35\n"
+        );
+
+        let opts = FormatOptions::default().top(2).totals(true).key_width(12);
+        assert_eq!(
+            metrics.format_with(&opts),
+            "req.page.1    1200\n\
+             req.page.2      35\n\
+             ... (1 more)\n\
+             total         1242\n"
+        );
+
+        let amap = AmapMetrics::new(&["b", "a"]);
+        amap.inc("b")?;
+        let opts = FormatOptions::default().align_right(false);
+        assert_eq!(amap.format_with(&opts), "a  0\nb  1\n");
+        Ok(())
+    }
+}
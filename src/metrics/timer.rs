@@ -0,0 +1,55 @@
+// RAII 计时器：创建时记下开始时间，drop 时把经过的时间（微秒）交给 record 记录下来。
+// handler 里有多个提前 return 或者 ? 的时候，不需要在每个出口手动计时。
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+pub struct Timer {
+    start: Instant,
+    record: Option<Box<dyn FnOnce(Duration) + Send>>,
+}
+
+impl Timer {
+    pub(crate) fn new(record: impl FnOnce(Duration) + Send + 'static) -> Self {
+        Timer {
+            start: Instant::now(),
+            record: Some(Box::new(record)),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // 提前结束计时，返回记录下来的时间
+    pub fn stop(mut self) -> Duration {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Duration {
+        let elapsed = self.start.elapsed();
+        // stop 之后 drop 还会再调用一次，record 已经被 take 走了，不会重复记录
+        if let Some(record) = self.record.take() {
+            record(elapsed);
+        }
+        elapsed
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Timer({:?})", self.elapsed())
+    }
+}
+
+// 记录的单位是微秒，超出 i64 的部分截断
+pub(crate) fn as_micros(d: Duration) -> i64 {
+    d.as_micros().min(i64::MAX as u128) as i64
+}
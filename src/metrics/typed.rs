@@ -0,0 +1,147 @@
+// 用 enum 代替字符串做 key：拼错的 key 在编译时就报错，而不是运行时的 "key not found"。
+// enum 实现 MetricKey，给出所有的 key 和每个 key 的名字；手写 impl 或者用 metric_keys! 宏生成：
+//   metric_keys! {
+//       pub enum Req {
+//           Page1 => "req.page.1",
+//           Page2 => "req.page.2",
+//       }
+//   }
+//   let metrics = TypedMetrics::<Req>::new();
+//   metrics.inc(Req::Page1);
+use std::{fmt, marker::PhantomData};
+
+use super::amap::AmapMetrics;
+
+pub trait MetricKey: Copy + 'static {
+    const ALL: &'static [Self];
+    fn name(&self) -> &'static str;
+}
+
+#[macro_export]
+macro_rules! metric_keys {
+    ($vis:vis enum $name:ident { $($variant:ident => $key:literal),* $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl $crate::MetricKey for $name {
+            const ALL: &'static [Self] = &[$($name::$variant),*];
+
+            fn name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $key),*
+                }
+            }
+        }
+    };
+}
+
+// AmapMetrics 的一层薄包装：所有 K::ALL 的 key 都在 new 的时候注册好了，所以这里的操作不会失败
+pub struct TypedMetrics<K> {
+    metrics: AmapMetrics,
+    _key: PhantomData<K>,
+}
+
+impl<K: MetricKey> TypedMetrics<K> {
+    pub fn new() -> Self {
+        let names = K::ALL.iter().map(|k| k.name()).collect::<Vec<_>>();
+        TypedMetrics {
+            metrics: AmapMetrics::new(&names),
+            _key: PhantomData,
+        }
+    }
+
+    pub fn inc(&self, key: K) {
+        self.inc_by(key, 1);
+    }
+
+    pub fn dec(&self, key: K) {
+        self.inc_by(key, -1);
+    }
+
+    pub fn inc_by(&self, key: K, delta: i64) {
+        self.metrics
+            .inc_by(key.name(), delta)
+            .expect("every MetricKey is registered in new");
+    }
+
+    pub fn set(&self, key: K, value: i64) {
+        self.metrics
+            .set(key.name(), value)
+            .expect("every MetricKey is registered in new");
+    }
+
+    pub fn get(&self, key: K) -> i64 {
+        self.metrics.get(key.name()).unwrap_or_default()
+    }
+
+    // 导出、Prometheus、MetricsBackend 之类的功能都直接用底下的 AmapMetrics
+    pub fn as_amap(&self) -> &AmapMetrics {
+        &self.metrics
+    }
+}
+
+impl<K: MetricKey> Default for TypedMetrics<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for TypedMetrics<K> {
+    fn clone(&self) -> Self {
+        TypedMetrics {
+            metrics: self.metrics.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K> fmt::Debug for TypedMetrics<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TypedMetrics({:?})", self.metrics)
+    }
+}
+
+impl<K> fmt::Display for TypedMetrics<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    metric_keys! {
+        enum Req {
+            Page1 => "req.page.1",
+            Page2 => "req.page.2",
+        }
+    }
+
+    #[test]
+    fn test_typed_metrics() {
+        assert_eq!(Req::ALL, &[Req::Page1, Req::Page2]);
+        let metrics = TypedMetrics::<Req>::new();
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        metrics.inc(Req::Page1);
+                    }
+                    metrics.inc_by(Req::Page2, 3);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(metrics.get(Req::Page1), 400);
+        assert_eq!(metrics.get(Req::Page2), 12);
+        metrics.dec(Req::Page2);
+        assert_eq!(metrics.as_amap().get("req.page.2"), Some(11));
+    }
+}
@@ -0,0 +1,143 @@
+// 滑动窗口计数器：把时间切成固定长度的 bucket，每个 bucket 记录自己那一段时间里的事件数，
+// "最近 N 秒有多少次" 就是把最近 N 秒的 bucket 加起来，而不是从启动到现在的总数。
+// bucket 是一个环形数组，每个槽位同时记着它属于哪一段时间（epoch）；槽位被新的 epoch 复用时就相当于旧的 bucket 被淘汰了，
+// 所以不需要单独的清理线程，记录和查询都只用原子操作。
+use std::{
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct WindowedCounter {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    start: Instant,
+    bucket: Duration,
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    epoch: AtomicU64,
+    count: AtomicI64,
+    // 槽位换 epoch 时的 "清零 + 改 epoch" 必须一起完成，否则并发的 record 可能被清掉
+    reset: Mutex<()>,
+}
+
+impl WindowedCounter {
+    // window 是能查询的最长时间，bucket 是精度；比如 60 秒的窗口、1 秒一个 bucket
+    pub fn new(window: Duration, bucket: Duration) -> Self {
+        let bucket = bucket.max(Duration::from_millis(1));
+        let n = (window.as_nanos().div_ceil(bucket.as_nanos()) as usize).max(1);
+        WindowedCounter {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                bucket,
+                // 多一个槽位给正在写的当前 bucket，保证整个 window 的数据都还在
+                buckets: (0..=n)
+                    .map(|_| Bucket {
+                        epoch: AtomicU64::new(0),
+                        count: AtomicI64::new(0),
+                        reset: Mutex::new(()),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: i64) {
+        self.add_at(n, Instant::now());
+    }
+
+    // 最近 window 时间内的事件数；window 超过 new 时给的长度时按最长的算
+    pub fn count(&self, window: Duration) -> i64 {
+        self.count_at(window, Instant::now())
+    }
+
+    fn epoch(&self, now: Instant) -> u64 {
+        // epoch 从 1 开始，0 表示这个槽位还没用过
+        (now.saturating_duration_since(self.inner.start).as_nanos() / self.inner.bucket.as_nanos())
+            as u64
+            + 1
+    }
+
+    fn add_at(&self, n: i64, now: Instant) {
+        let epoch = self.epoch(now);
+        let bucket = &self.inner.buckets[epoch as usize % self.inner.buckets.len()];
+        if bucket.epoch.load(Ordering::Acquire) != epoch {
+            let _guard = bucket.reset.lock().unwrap_or_else(|e| e.into_inner());
+            // 拿到锁之后再检查一次：可能另一个线程已经把它换成了当前 epoch
+            if bucket.epoch.load(Ordering::Acquire) != epoch {
+                bucket.count.store(0, Ordering::Relaxed);
+                bucket.epoch.store(epoch, Ordering::Release);
+            }
+        }
+        bucket.count.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn count_at(&self, window: Duration, now: Instant) -> i64 {
+        let current = self.epoch(now);
+        let len = self.inner.buckets.len() as u64;
+        let wanted =
+            (window.as_nanos().div_ceil(self.inner.bucket.as_nanos()) as u64).clamp(1, len - 1);
+        // 当前 bucket 加上之前的 wanted - 1 个
+        let oldest = current.saturating_sub(wanted - 1);
+        self.inner
+            .buckets
+            .iter()
+            .filter(|b| {
+                let epoch = b.epoch.load(Ordering::Acquire);
+                epoch >= oldest && epoch <= current
+            })
+            .map(|b| b.count.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_windowed_counter() {
+        let counter = WindowedCounter::new(Duration::from_secs(10), Duration::from_secs(1));
+        let t0 = counter.inner.start;
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+
+        counter.add_at(5, at(0));
+        counter.add_at(3, at(4));
+        counter.add_at(2, at(9));
+        assert_eq!(counter.count_at(Duration::from_secs(10), at(9)), 10);
+        assert_eq!(counter.count_at(Duration::from_secs(6), at(9)), 5);
+        assert_eq!(counter.count_at(Duration::from_secs(1), at(9)), 2);
+        // 第 0 秒的 bucket 已经滑出窗口
+        assert_eq!(counter.count_at(Duration::from_secs(10), at(12)), 5);
+        // 第 0 秒的槽位被第 11 秒复用，旧的值被清掉
+        counter.add_at(1, at(11));
+        assert_eq!(counter.count_at(Duration::from_secs(60), at(11)), 6);
+        assert_eq!(counter.count_at(Duration::from_secs(10), at(30)), 0);
+
+        let counter = WindowedCounter::new(Duration::from_secs(60), Duration::from_secs(1));
+        let handles = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || (0..1000).for_each(|_| counter.inc()))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(counter.count(Duration::from_secs(60)), 4000);
+    }
+}
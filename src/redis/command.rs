@@ -0,0 +1,165 @@
+// 从连接的读缓冲区里切出一条完整的命令：
+//   redis-cli 发送的是 RESP 数组：*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n
+//   telnet / nc 直接敲的是 inline 命令：GET foo\r\n，按空白分开
+// bulk string 按 $ 后面的长度取内容，所以 value 里可以有空格和 \r\n。
+//
+// 一次 read 不等于一条命令：TCP 可能把一条命令拆成几次读到，pipeline 的客户端
+// 也会一次发来很多条。所以数据先追加到缓冲区，再循环 decode，直到剩下的不够一条完整的命令。
+use anyhow::{anyhow, Result};
+use bytes::{Buf, Bytes, BytesMut};
+
+// 长度都来自客户端，和 redis 一样都要限制，否则缓冲区会为了等一条永远不完整的命令无限增长：
+// inline 命令和 *、$ 的头部一行最长 64KB，超过还没有换行就当作协议错误；
+// bulk string 最长 512MB（proto-max-bulk-len），一条命令最多 1024 * 1024 个参数
+const MAX_INLINE: usize = 64 * 1024;
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const MAX_ARGS: usize = 1024 * 1024;
+
+// 缓冲区里有完整的命令时取出并返回 Some，数据不够时返回 None 并保持缓冲区不变。
+// 返回 Err 说明数据不是合法的 RESP，连接应该回复错误后关闭
+pub fn decode_command(buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>> {
+    loop {
+        let Some((args, consumed)) = parse(buf)? else {
+            return Ok(None);
+        };
+        buf.advance(consumed);
+        // 空行直接跳过，redis 也是这样
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
+fn parse(input: &[u8]) -> Result<Option<(Vec<Bytes>, usize)>> {
+    if input.is_empty() {
+        return Ok(None);
+    }
+    if !input.starts_with(b"*") {
+        let Some(pos) = input.iter().position(|&b| b == b'\n') else {
+            if input.len() > MAX_INLINE {
+                return Err(anyhow!("too big inline request"));
+            }
+            return Ok(None);
+        };
+        let args = String::from_utf8_lossy(&input[..pos])
+            .split_whitespace()
+            .map(|s| Bytes::copy_from_slice(s.as_bytes()))
+            .collect();
+        return Ok(Some((args, pos + 1)));
+    }
+
+    let Some((count, mut pos)) = read_line(input, 1)? else {
+        return Ok(None);
+    };
+    let count = parse_len(count)?;
+    if count > MAX_ARGS {
+        return Err(anyhow!("invalid multibulk length"));
+    }
+    // count 来自客户端，不能直接拿来分配内存
+    let mut args = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        match input.get(pos) {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(_) => return Err(anyhow!("expected '$'")),
+        }
+        let Some((len, start)) = read_line(input, pos + 1)? else {
+            return Ok(None);
+        };
+        let len = parse_len(len)?;
+        if len > MAX_BULK_LEN {
+            return Err(anyhow!("invalid bulk length"));
+        }
+        // len 已经有上限，这里的 checked_add 只是不依赖这个前提
+        let end = start
+            .checked_add(len)
+            .filter(|end| end.checked_add(2).is_some())
+            .ok_or_else(|| anyhow!("invalid bulk length"))?;
+        if input.len() < end + 2 {
+            return Ok(None);
+        }
+        if &input[end..end + 2] != b"\r\n" {
+            return Err(anyhow!("bulk string is not terminated by \\r\\n"));
+        }
+        args.push(Bytes::copy_from_slice(&input[start..end]));
+        pos = end + 2;
+    }
+    Ok(Some((args, pos)))
+}
+
+// 从 start 开始找 \r\n，返回之前的内容和 \r\n 之后的位置；超过 MAX_INLINE 还没有 \r\n 时返回错误
+fn read_line(input: &[u8], start: usize) -> Result<Option<(&[u8], usize)>> {
+    let line = &input[start..];
+    match line.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => Ok(Some((&line[..pos], start + pos + 2))),
+        None if line.len() > MAX_INLINE => Err(anyhow!("too big header line")),
+        None => Ok(None),
+    }
+}
+
+fn parse_len(s: &[u8]) -> Result<usize> {
+    std::str::from_utf8(s)?
+        .parse()
+        .map_err(|_| anyhow!("invalid length {:?}", String::from_utf8_lossy(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_command() -> Result<()> {
+        let mut buf = BytesMut::from(&b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$5\r\na b\r\n\r\n"[..]);
+        assert_eq!(
+            decode_command(&mut buf)?.unwrap(),
+            ["SET", "foo", "a b\r\n"]
+        );
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&b"get foo\r\n\r\nPING\n"[..]);
+        assert_eq!(decode_command(&mut buf)?.unwrap(), ["get", "foo"]);
+        assert_eq!(decode_command(&mut buf)?.unwrap(), ["PING"]);
+        assert_eq!(decode_command(&mut buf)?, None);
+
+        assert!(decode_command(&mut BytesMut::from(&b"*1\r\n+GET\r\n"[..])).is_err());
+        assert!(decode_command(&mut BytesMut::from(&b"*1\r\n$3\r\nGETX\r\n"[..])).is_err());
+        assert!(decode_command(&mut BytesMut::from(&b"*x\r\n"[..])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let too_long = format!("*1\r\n${}\r\n", MAX_BULK_LEN + 1);
+        assert!(decode_command(&mut BytesMut::from(too_long.as_bytes())).is_err());
+        let overflow = format!("*1\r\n${}\r\n", usize::MAX);
+        assert!(decode_command(&mut BytesMut::from(overflow.as_bytes())).is_err());
+        let too_many = format!("*{}\r\n", MAX_ARGS + 1);
+        assert!(decode_command(&mut BytesMut::from(too_many.as_bytes())).is_err());
+
+        // 头部一直不发 \r\n：没超过上限时等待，超过之后报错
+        let mut header = BytesMut::from(&b"*1"[..]);
+        assert!(decode_command(&mut header).unwrap().is_none());
+        header.extend_from_slice(&vec![b'1'; MAX_INLINE + 1]);
+        assert!(decode_command(&mut header).is_err());
+        let mut bulk_header = BytesMut::from(&b"*1\r\n$"[..]);
+        bulk_header.extend_from_slice(&vec![b'1'; MAX_INLINE + 1]);
+        assert!(decode_command(&mut bulk_header).is_err());
+    }
+
+    #[test]
+    fn test_decode_partial_and_pipelined() -> Result<()> {
+        let input = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*1\r\n$4\r\nPING\r\n".to_vec();
+        // 一个字节一个字节地到达，只有最后一个字节到了才能 decode 出完整的命令
+        let mut buf = BytesMut::new();
+        let mut commands = Vec::new();
+        for b in input {
+            buf.extend_from_slice(&[b]);
+            while let Some(args) = decode_command(&mut buf)? {
+                commands.push(args);
+            }
+        }
+        assert_eq!(commands, [vec!["GET", "foo"], vec!["PING"]]);
+        assert!(buf.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,792 @@
+// 所有连接共享同一个 Db，clone 只是增加 Arc 的引用计数。
+// DashMap 按 key 分片加锁，不同 key 的读写不会互相阻塞。
+// 过期有两条路：访问 key 的时候发现过期就删掉（惰性），后台的 sweeper 定期清掉没人访问的过期 key。
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use dashmap::{
+    mapref::{entry::Entry as MapEntry, one::RefMut},
+    DashMap,
+};
+
+use super::{glob::glob_match, PubSub, Reply};
+
+// 参数个数不对时回复 wrong number of arguments，而不是 unknown command
+const COMMANDS: &[&str] = &[
+    "GET", "SET", "GETSET", "INCR", "DECR", "INCRBY", "DECRBY", "DEL", "EXISTS", "KEYS", "EXPIRE",
+    "PEXPIRE", "TTL", "PTTL", "PUBLISH", "LPUSH", "RPUSH", "LPOP", "RPOP", "LLEN", "LRANGE",
+    "HSET", "HGET", "HGETALL", "HDEL", "HLEN",
+];
+
+// Db::execute 和 Subscriber::execute 认识的命令名（大写），比如统计每种命令的次数时，
+// 用它把客户端随便发的命令名归到一起，不然 label 的取值没有上限
+pub fn is_command(name: &str) -> bool {
+    COMMANDS.contains(&name) || name == "SUBSCRIBE" || name == "UNSUBSCRIBE"
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[derive(Debug, Clone, Default)]
+pub struct Db {
+    data: Arc<DashMap<String, Entry>>,
+    pubsub: PubSub,
+}
+
+// 过期时间和值放在同一个 entry 里，一次加锁就能同时看到两者
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+// 每个 key 只有一种类型，用另一种类型的命令访问时回复 WRONGTYPE
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+}
+
+impl Value {
+    fn as_string(&self) -> Result<&Bytes> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(anyhow!(WRONGTYPE)),
+        }
+    }
+
+    fn as_list(&self) -> Result<&VecDeque<Bytes>> {
+        match self {
+            Value::List(l) => Ok(l),
+            _ => Err(anyhow!(WRONGTYPE)),
+        }
+    }
+
+    fn as_list_mut(&mut self) -> Result<&mut VecDeque<Bytes>> {
+        match self {
+            Value::List(l) => Ok(l),
+            _ => Err(anyhow!(WRONGTYPE)),
+        }
+    }
+
+    fn as_hash(&self) -> Result<&HashMap<Bytes, Bytes>> {
+        match self {
+            Value::Hash(h) => Ok(h),
+            _ => Err(anyhow!(WRONGTYPE)),
+        }
+    }
+
+    fn as_hash_mut(&mut self) -> Result<&mut HashMap<Bytes, Bytes>> {
+        match self {
+            Value::Hash(h) => Ok(h),
+            _ => Err(anyhow!(WRONGTYPE)),
+        }
+    }
+}
+
+impl Entry {
+    fn new(value: Value) -> Self {
+        Entry {
+            value,
+            expires_at: None,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // SUBSCRIBE 是连接自己的状态，连接用它创建 Subscriber；PUBLISH 直接在 execute 里处理
+    pub fn pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    // key 不是 string 时返回 WRONGTYPE 错误
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        self.expire_if_needed(key);
+        match self.data.get(key) {
+            Some(e) => Ok(Some(e.value.as_string()?.clone())),
+            None => Ok(None),
+        }
+    }
+
+    // 和 redis 一样，SET 不管原来是什么类型都直接覆盖，并且清掉原来的过期时间
+    pub fn set(&self, key: impl Into<String>, value: impl Into<Bytes>) {
+        self.data
+            .insert(key.into(), Entry::new(Value::String(value.into())));
+    }
+
+    // 返回旧值；原来的值不是 string 时不修改，返回 WRONGTYPE 错误
+    pub fn getset(&self, key: impl Into<String>, value: impl Into<Bytes>) -> Result<Option<Bytes>> {
+        let value = Entry::new(Value::String(value.into()));
+        match self.data.entry(key.into()) {
+            MapEntry::Occupied(mut o) if !o.get().is_expired(Instant::now()) => {
+                let old = o.get().value.as_string()?.clone();
+                o.insert(value);
+                Ok(Some(old))
+            }
+            e => {
+                e.insert(value);
+                Ok(None)
+            }
+        }
+    }
+
+    // 返回 push 之后的长度；key 不存在时新建一个 list
+    pub fn lpush(&self, key: impl Into<String>, values: &[Bytes]) -> Result<usize> {
+        let mut entry = self.entry_or(key.into(), || Value::List(VecDeque::new()));
+        let list = entry.value.as_list_mut()?;
+        for v in values {
+            list.push_front(v.clone());
+        }
+        Ok(list.len())
+    }
+
+    pub fn rpush(&self, key: impl Into<String>, values: &[Bytes]) -> Result<usize> {
+        let mut entry = self.entry_or(key.into(), || Value::List(VecDeque::new()));
+        let list = entry.value.as_list_mut()?;
+        list.extend(values.iter().cloned());
+        Ok(list.len())
+    }
+
+    pub fn lpop(&self, key: &str) -> Result<Option<Bytes>> {
+        self.pop(key, VecDeque::pop_front)
+    }
+
+    pub fn rpop(&self, key: &str) -> Result<Option<Bytes>> {
+        self.pop(key, VecDeque::pop_back)
+    }
+
+    pub fn llen(&self, key: &str) -> Result<usize> {
+        self.expire_if_needed(key);
+        match self.data.get(key) {
+            Some(e) => Ok(e.value.as_list()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    // start/stop 都包含在内，负数从末尾数起，-1 是最后一个；超出范围的部分忽略
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.data.get(key) else {
+            return Ok(Vec::new());
+        };
+        let list = entry.value.as_list()?;
+        let len = list.len() as i64;
+        // 负数从尾部数；只有 start 截到 0，stop 算出来还是负数就说明整个范围在 list 前面
+        let index = |i: i64| if i < 0 { len + i } else { i };
+        let (start, stop) = (index(start).max(0), index(stop).min(len - 1));
+        if stop < 0 || start >= len || start > stop {
+            return Ok(Vec::new());
+        }
+        Ok(list
+            .range(start as usize..=stop as usize)
+            .cloned()
+            .collect())
+    }
+
+    // 和 redis 一样，list 空了之后 key 也删掉
+    fn pop(
+        &self,
+        key: &str,
+        pop: impl Fn(&mut VecDeque<Bytes>) -> Option<Bytes>,
+    ) -> Result<Option<Bytes>> {
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.data.get_mut(key) else {
+            return Ok(None);
+        };
+        let list = entry.value.as_list_mut()?;
+        let value = pop(list);
+        let empty = list.is_empty();
+        // 先释放 entry 的写锁，remove_if 要拿同一个分片的锁
+        drop(entry);
+        if empty {
+            self.data
+                .remove_if(key, |_, e| e.value.as_list().is_ok_and(|l| l.is_empty()));
+        }
+        Ok(value)
+    }
+
+    // 返回新增的 field 个数，已经存在的 field 只更新值不计数
+    pub fn hset(&self, key: impl Into<String>, pairs: &[(Bytes, Bytes)]) -> Result<usize> {
+        let mut entry = self.entry_or(key.into(), || Value::Hash(HashMap::new()));
+        let hash = entry.value.as_hash_mut()?;
+        Ok(pairs
+            .iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count())
+    }
+
+    pub fn hget(&self, key: &str, field: &[u8]) -> Result<Option<Bytes>> {
+        self.expire_if_needed(key);
+        match self.data.get(key) {
+            Some(e) => Ok(e.value.as_hash()?.get(field).cloned()),
+            None => Ok(None),
+        }
+    }
+
+    // 按 field 排序，输出稳定
+    pub fn hgetall(&self, key: &str) -> Result<Vec<(Bytes, Bytes)>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.data.get(key) else {
+            return Ok(Vec::new());
+        };
+        let mut pairs = entry
+            .value
+            .as_hash()?
+            .iter()
+            .map(|(f, v)| (f.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        pairs.sort();
+        Ok(pairs)
+    }
+
+    pub fn hlen(&self, key: &str) -> Result<usize> {
+        self.expire_if_needed(key);
+        match self.data.get(key) {
+            Some(e) => Ok(e.value.as_hash()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    // 返回实际删除的 field 个数；和 list 一样，hash 空了之后 key 也删掉
+    pub fn hdel(&self, key: &str, fields: &[Bytes]) -> Result<usize> {
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.data.get_mut(key) else {
+            return Ok(0);
+        };
+        let hash = entry.value.as_hash_mut()?;
+        let removed = fields.iter().filter(|f| hash.remove(*f).is_some()).count();
+        let empty = hash.is_empty();
+        drop(entry);
+        if empty {
+            self.data
+                .remove_if(key, |_, e| e.value.as_hash().is_ok_and(|h| h.is_empty()));
+        }
+        Ok(removed)
+    }
+
+    // 拿到 key 的写锁，key 不存在或者已经过期时先放入 default()。
+    // 检查过期和写入在同一把锁里完成，不会和别的连接交错
+    fn entry_or(&self, key: String, default: impl Fn() -> Value) -> RefMut<'_, String, Entry> {
+        let mut entry = self
+            .data
+            .entry(key)
+            .or_insert_with(|| Entry::new(default()));
+        if entry.is_expired(Instant::now()) {
+            *entry = Entry::new(default());
+        }
+        entry
+    }
+
+    // key 不存在时返回 false。ttl 为 0 时 key 立即过期
+    pub fn expire(&self, key: &str, ttl: Duration) -> bool {
+        self.expire_if_needed(key);
+        match self.data.get_mut(key) {
+            Some(mut entry) => {
+                entry.expires_at = Some(Instant::now() + ttl);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // EXPIRE/PEXPIRE 的回复：key 存在返回 1，否则 0。负数的过期时间让 key 立即过期
+    fn expire_ms(&self, key: &str, ms: i64) -> i64 {
+        self.expire(key, Duration::from_millis(ms.max(0) as u64)) as i64
+    }
+
+    // key 不存在时返回 None，没有过期时间时返回 Some(None)
+    pub fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        self.expire_if_needed(key);
+        let now = Instant::now();
+        self.data
+            .get(key)
+            .map(|e| e.expires_at.map(|t| t.saturating_duration_since(now)))
+    }
+
+    // 清掉所有过期的 key，返回删除的个数。retain 每次只锁一个分片，不会让所有命令一起停下来
+    pub fn sweep(&self) -> usize {
+        sweep(&self.data)
+    }
+
+    // 在当前的 tokio runtime 里每隔 interval 调用一次 sweep；
+    // 只持有 Weak，所有的 Db 都 drop 之后 task 自己退出
+    pub fn spawn_sweeper(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let data = Arc::downgrade(&self.data);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 第一次 tick 立即返回
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(data) = Weak::upgrade(&data) else {
+                    break;
+                };
+                let removed = sweep(&data);
+                if removed > 0 {
+                    tracing::debug!("swept {} expired keys", removed);
+                }
+            }
+        })
+    }
+
+    // 先用读锁检查，确实过期了才拿写锁删除；remove_if 里再检查一次，
+    // 防止两次加锁之间别的连接刚好 SET 了新值
+    fn expire_if_needed(&self, key: &str) {
+        let now = Instant::now();
+        let expired = self.data.get(key).is_some_and(|e| e.is_expired(now));
+        if expired {
+            self.data.remove_if(key, |_, e| e.is_expired(now));
+        }
+    }
+
+    // 在 entry 的写锁里读出、加上、写回，并发的 INCR 不会丢失更新。
+    // 不存在的 key 当作 0；值不是整数或者加完溢出时不修改原值
+    pub fn incr_by(&self, key: impl Into<String>, delta: i64) -> Result<i64> {
+        let mut entry = self.entry_or(key.into(), || Value::String(Bytes::from("0")));
+        let value = parse_int(entry.value.as_string()?)?
+            .checked_add(delta)
+            .ok_or_else(|| anyhow!("ERR increment or decrement would overflow"))?;
+        // 和 redis 一样，INCR 保留原来的过期时间
+        entry.value = Value::String(Bytes::from(value.to_string()));
+        Ok(value)
+    }
+
+    // 返回实际删除的 key 的个数
+    pub fn del<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> usize {
+        let now = Instant::now();
+        keys.into_iter()
+            .filter(|key| {
+                self.data
+                    .remove(*key)
+                    .is_some_and(|(_, e)| !e.is_expired(now))
+            })
+            .count()
+    }
+
+    // 和 redis 一样，重复的 key 会重复计数
+    pub fn exists<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> usize {
+        let now = Instant::now();
+        keys.into_iter()
+            .filter(|key| self.data.get(*key).is_some_and(|e| !e.is_expired(now)))
+            .count()
+    }
+
+    // DashMap 的 iter 每次只持有一个分片的读锁，遍历期间其它分片照常读写；
+    // 先把匹配的 key 收集起来再排序，不在持有锁的时候做别的事情
+    pub fn keys(&self, pattern: &str) -> Vec<String> {
+        let now = Instant::now();
+        let mut keys = self
+            .data
+            .iter()
+            .filter(|entry| !entry.is_expired(now) && glob_match(pattern, entry.key()))
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    // args[0] 是命令名，不区分大小写
+    pub fn execute(&self, args: &[Bytes]) -> Reply {
+        let Some((name, args)) = args.split_first() else {
+            return Reply::error("ERR empty command");
+        };
+        let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+        match (name.as_str(), args) {
+            ("GET", [key]) => reply(self.get(&to_key(key)).map(Reply::Bulk)),
+            ("SET", [key, value]) => {
+                self.set(to_key(key), value.clone());
+                Reply::ok()
+            }
+            ("GETSET", [key, value]) => {
+                reply(self.getset(to_key(key), value.clone()).map(Reply::Bulk))
+            }
+            ("INCR", [key]) => integer(self.incr_by(to_key(key), 1)),
+            ("DECR", [key]) => integer(self.incr_by(to_key(key), -1)),
+            ("INCRBY", [key, delta]) => {
+                integer(parse_int(delta).and_then(|d| self.incr_by(to_key(key), d)))
+            }
+            ("DECRBY", [key, delta]) => integer(parse_int(delta).and_then(|d| {
+                let d = d
+                    .checked_neg()
+                    .ok_or_else(|| anyhow!("ERR decrement would overflow"))?;
+                self.incr_by(to_key(key), d)
+            })),
+            ("DEL", [_, ..]) => {
+                let keys = args.iter().map(to_key).collect::<Vec<_>>();
+                Reply::Integer(self.del(keys.iter().map(|k| k.as_str())) as i64)
+            }
+            ("EXISTS", [_, ..]) => {
+                let keys = args.iter().map(to_key).collect::<Vec<_>>();
+                Reply::Integer(self.exists(keys.iter().map(|k| k.as_str())) as i64)
+            }
+            ("KEYS", [pattern]) => Reply::Array(
+                self.keys(&to_key(pattern))
+                    .into_iter()
+                    .map(|key| Reply::Bulk(Some(Bytes::from(key))))
+                    .collect(),
+            ),
+            ("EXPIRE", [key, secs]) => integer(
+                parse_int(secs).map(|s| self.expire_ms(&to_key(key), s.saturating_mul(1000))),
+            ),
+            ("PEXPIRE", [key, ms]) => {
+                integer(parse_int(ms).map(|ms| self.expire_ms(&to_key(key), ms)))
+            }
+            // 四舍五入到秒，和 redis 一样
+            ("TTL", [key]) => Reply::Integer(ttl_reply(self.ttl(&to_key(key)), |d| {
+                ((d.as_millis() + 500) / 1000) as i64
+            })),
+            ("PTTL", [key]) => {
+                Reply::Integer(ttl_reply(self.ttl(&to_key(key)), |d| d.as_millis() as i64))
+            }
+            ("LPUSH", [key, values @ ..]) if !values.is_empty() => {
+                integer(self.lpush(to_key(key), values).map(|n| n as i64))
+            }
+            ("RPUSH", [key, values @ ..]) if !values.is_empty() => {
+                integer(self.rpush(to_key(key), values).map(|n| n as i64))
+            }
+            ("LPOP", [key]) => reply(self.lpop(&to_key(key)).map(Reply::Bulk)),
+            ("RPOP", [key]) => reply(self.rpop(&to_key(key)).map(Reply::Bulk)),
+            ("LLEN", [key]) => integer(self.llen(&to_key(key)).map(|n| n as i64)),
+            ("LRANGE", [key, start, stop]) => reply(
+                parse_int(start)
+                    .and_then(|start| Ok((start, parse_int(stop)?)))
+                    .and_then(|(start, stop)| self.lrange(&to_key(key), start, stop))
+                    .map(|items| {
+                        Reply::Array(items.into_iter().map(|v| Reply::Bulk(Some(v))).collect())
+                    }),
+            ),
+            // field value 必须成对出现
+            ("HSET", [key, pairs @ ..]) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+                let pairs = pairs
+                    .chunks(2)
+                    .map(|p| (p[0].clone(), p[1].clone()))
+                    .collect::<Vec<_>>();
+                integer(self.hset(to_key(key), &pairs).map(|n| n as i64))
+            }
+            ("HGET", [key, field]) => reply(self.hget(&to_key(key), field).map(Reply::Bulk)),
+            ("HGETALL", [key]) => reply(self.hgetall(&to_key(key)).map(|pairs| {
+                Reply::Array(
+                    pairs
+                        .into_iter()
+                        .flat_map(|(f, v)| [Reply::Bulk(Some(f)), Reply::Bulk(Some(v))])
+                        .collect(),
+                )
+            })),
+            ("HDEL", [key, fields @ ..]) if !fields.is_empty() => {
+                integer(self.hdel(&to_key(key), fields).map(|n| n as i64))
+            }
+            ("HLEN", [key]) => integer(self.hlen(&to_key(key)).map(|n| n as i64)),
+            ("PUBLISH", [channel, message]) => {
+                Reply::Integer(self.pubsub.publish(&to_key(channel), message.clone()) as i64)
+            }
+            (name, _) if COMMANDS.contains(&name) => Reply::error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                name.to_ascii_lowercase()
+            )),
+            _ => Reply::error(format!("ERR unknown command '{}'", name)),
+        }
+    }
+}
+
+// Db::sweep 和 sweeper task 共用；sweeper 只有 data 的 Weak，没有完整的 Db
+fn sweep(data: &DashMap<String, Entry>) -> usize {
+    let now = Instant::now();
+    let mut removed = 0;
+    data.retain(|_, e| {
+        let expired = e.is_expired(now);
+        removed += expired as usize;
+        !expired
+    });
+    removed
+}
+
+// TTL/PTTL：key 不存在返回 -2，没有过期时间返回 -1
+fn ttl_reply(ttl: Option<Option<Duration>>, unit: impl Fn(Duration) -> i64) -> i64 {
+    match ttl {
+        None => -2,
+        Some(None) => -1,
+        Some(Some(d)) => unit(d),
+    }
+}
+
+fn to_key(key: &Bytes) -> String {
+    String::from_utf8_lossy(key).into_owned()
+}
+
+fn parse_int(value: &[u8]) -> Result<i64> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("ERR value is not an integer or out of range"))
+}
+
+// 错误信息本身就是 RESP 的错误内容
+fn reply(result: Result<Reply>) -> Reply {
+    result.unwrap_or_else(|e| Reply::error(e.to_string()))
+}
+
+fn integer(result: Result<i64>) -> Reply {
+    reply(result.map(Reply::Integer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec(db: &Db, args: &[&str]) -> Reply {
+        let args = args
+            .iter()
+            .map(|s| Bytes::copy_from_slice(s.as_bytes()))
+            .collect::<Vec<_>>();
+        db.execute(&args)
+    }
+
+    fn bulk(s: &str) -> Reply {
+        Reply::Bulk(Some(Bytes::copy_from_slice(s.as_bytes())))
+    }
+
+    #[test]
+    fn test_is_command() {
+        assert!(is_command("GET"));
+        assert!(is_command("SUBSCRIBE"));
+        assert!(!is_command("get"));
+        assert!(!is_command("NOPE"));
+    }
+
+    #[test]
+    fn test_db_get_set() {
+        let db = Db::new();
+        assert_eq!(exec(&db, &["GET", "foo"]), Reply::Bulk(None));
+        assert_eq!(exec(&db, &["set", "foo", "bar"]), Reply::ok());
+        assert_eq!(exec(&db, &["GET", "foo"]), bulk("bar"));
+        assert_eq!(exec(&db, &["GETSET", "foo", "baz"]), bulk("bar"));
+        // clone 出来的 Db 看到的是同一份数据
+        assert_eq!(exec(&db.clone(), &["GET", "foo"]), bulk("baz"));
+
+        assert_eq!(
+            exec(&db, &["GET"]),
+            Reply::error("ERR wrong number of arguments for 'get' command")
+        );
+        assert_eq!(
+            exec(&db, &["NOPE"]),
+            Reply::error("ERR unknown command 'NOPE'")
+        );
+    }
+
+    #[test]
+    fn test_db_incr() {
+        let db = Db::new();
+        assert_eq!(exec(&db, &["INCR", "n"]), Reply::Integer(1));
+        assert_eq!(exec(&db, &["INCRBY", "n", "10"]), Reply::Integer(11));
+        assert_eq!(exec(&db, &["DECR", "n"]), Reply::Integer(10));
+        assert_eq!(exec(&db, &["DECRBY", "n", "3"]), Reply::Integer(7));
+        assert_eq!(exec(&db, &["GET", "n"]), bulk("7"));
+
+        let not_int = Reply::error("ERR value is not an integer or out of range");
+        exec(&db, &["SET", "s", "abc"]);
+        assert_eq!(exec(&db, &["INCR", "s"]), not_int);
+        assert_eq!(exec(&db, &["INCRBY", "n", "x"]), not_int);
+        assert_eq!(exec(&db, &["GET", "s"]), bulk("abc"));
+
+        exec(&db, &["SET", "max", &i64::MAX.to_string()]);
+        assert_eq!(
+            exec(&db, &["INCR", "max"]),
+            Reply::error("ERR increment or decrement would overflow")
+        );
+        assert_eq!(exec(&db, &["GET", "max"]), bulk(&i64::MAX.to_string()));
+    }
+
+    #[test]
+    fn test_db_incr_concurrent() {
+        let db = Db::new();
+        let handles = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        db.incr_by("n", 1).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(db.get("n").unwrap(), Some(Bytes::from("4000")));
+    }
+
+    #[test]
+    fn test_db_keyspace() {
+        let db = Db::new();
+        for key in ["user:1", "user:2", "session:1"] {
+            exec(&db, &["SET", key, "x"]);
+        }
+        assert_eq!(
+            exec(&db, &["KEYS", "user:*"]),
+            Reply::Array(vec![bulk("user:1"), bulk("user:2")])
+        );
+        assert_eq!(
+            exec(&db, &["EXISTS", "user:1", "user:1", "nope"]),
+            Reply::Integer(2)
+        );
+        assert_eq!(
+            exec(&db, &["DEL", "user:1", "session:1", "nope"]),
+            Reply::Integer(2)
+        );
+        assert_eq!(
+            exec(&db, &["KEYS", "*"]),
+            Reply::Array(vec![bulk("user:2")])
+        );
+        assert_eq!(
+            exec(&db, &["DEL"]),
+            Reply::error("ERR wrong number of arguments for 'del' command")
+        );
+    }
+
+    #[test]
+    fn test_db_expire() {
+        let db = Db::new();
+        exec(&db, &["SET", "a", "1"]);
+        exec(&db, &["SET", "b", "1"]);
+        assert_eq!(exec(&db, &["TTL", "a"]), Reply::Integer(-1));
+        assert_eq!(exec(&db, &["TTL", "nope"]), Reply::Integer(-2));
+        assert_eq!(exec(&db, &["EXPIRE", "nope", "10"]), Reply::Integer(0));
+
+        assert_eq!(exec(&db, &["EXPIRE", "a", "10"]), Reply::Integer(1));
+        assert_eq!(exec(&db, &["TTL", "a"]), Reply::Integer(10));
+        // INCR 保留过期时间，SET 清掉过期时间
+        exec(&db, &["INCR", "a"]);
+        assert_eq!(exec(&db, &["TTL", "a"]), Reply::Integer(10));
+        exec(&db, &["SET", "a", "1"]);
+        assert_eq!(exec(&db, &["TTL", "a"]), Reply::Integer(-1));
+
+        // 访问的时候发现过期，直接删除
+        assert_eq!(exec(&db, &["PEXPIRE", "a", "20"]), Reply::Integer(1));
+        assert_eq!(exec(&db, &["PEXPIRE", "b", "20"]), Reply::Integer(1));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(exec(&db, &["GET", "a"]), Reply::Bulk(None));
+        assert_eq!(exec(&db, &["EXISTS", "a", "b"]), Reply::Integer(0));
+        assert_eq!(exec(&db, &["INCR", "b"]), Reply::Integer(1));
+        assert_eq!(exec(&db, &["TTL", "b"]), Reply::Integer(-1));
+
+        exec(&db, &["EXPIRE", "b", "0"]);
+        assert_eq!(db.sweep(), 1);
+        assert_eq!(db.data.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_db_sweeper() {
+        let db = Db::new();
+        db.set("a", "1");
+        db.set("b", "1");
+        db.expire("a", Duration::from_millis(10));
+        let sweeper = db.spawn_sweeper(Duration::from_millis(5));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // 没有人访问 a，是 sweeper 删掉的
+        assert!(!db.data.contains_key("a"));
+        assert!(db.data.contains_key("b"));
+
+        drop(db);
+        tokio::time::timeout(Duration::from_secs(1), sweeper)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_db_list() {
+        let db = Db::new();
+        assert_eq!(exec(&db, &["RPUSH", "l", "b", "c"]), Reply::Integer(2));
+        assert_eq!(exec(&db, &["LPUSH", "l", "a", "z"]), Reply::Integer(4));
+        let range = |items: &[&str]| Reply::Array(items.iter().map(|s| bulk(s)).collect());
+        assert_eq!(
+            exec(&db, &["LRANGE", "l", "0", "-1"]),
+            range(&["z", "a", "b", "c"])
+        );
+        assert_eq!(exec(&db, &["LRANGE", "l", "1", "2"]), range(&["a", "b"]));
+        assert_eq!(exec(&db, &["LRANGE", "l", "-2", "100"]), range(&["b", "c"]));
+        assert_eq!(exec(&db, &["LRANGE", "l", "3", "1"]), range(&[]));
+        assert_eq!(exec(&db, &["LRANGE", "l", "0", "-100"]), range(&[]));
+        assert_eq!(exec(&db, &["LRANGE", "l", "-100", "-100"]), range(&[]));
+        assert_eq!(exec(&db, &["LRANGE", "nope", "0", "-1"]), range(&[]));
+
+        assert_eq!(exec(&db, &["LPOP", "l"]), bulk("z"));
+        assert_eq!(exec(&db, &["RPOP", "l"]), bulk("c"));
+        assert_eq!(exec(&db, &["LLEN", "l"]), Reply::Integer(2));
+        exec(&db, &["LPOP", "l"]);
+        exec(&db, &["LPOP", "l"]);
+        // 空的 list 会被删掉
+        assert_eq!(exec(&db, &["LPOP", "l"]), Reply::Bulk(None));
+        assert_eq!(exec(&db, &["EXISTS", "l"]), Reply::Integer(0));
+
+        let wrongtype = Reply::error(WRONGTYPE);
+        exec(&db, &["SET", "s", "x"]);
+        assert_eq!(exec(&db, &["LPUSH", "s", "a"]), wrongtype);
+        assert_eq!(exec(&db, &["LRANGE", "s", "0", "-1"]), wrongtype);
+        exec(&db, &["RPUSH", "l", "a"]);
+        assert_eq!(exec(&db, &["GET", "l"]), wrongtype);
+        assert_eq!(exec(&db, &["GETSET", "l", "x"]), wrongtype);
+        assert_eq!(exec(&db, &["INCR", "l"]), wrongtype);
+        // SET 直接覆盖其它类型
+        assert_eq!(exec(&db, &["SET", "l", "x"]), Reply::ok());
+        assert_eq!(exec(&db, &["GET", "l"]), bulk("x"));
+        assert_eq!(
+            exec(&db, &["LPUSH", "l"]),
+            Reply::error("ERR wrong number of arguments for 'lpush' command")
+        );
+    }
+
+    #[test]
+    fn test_db_hash() {
+        let db = Db::new();
+        assert_eq!(
+            exec(&db, &["HSET", "h", "name", "bob", "age", "3"]),
+            Reply::Integer(2)
+        );
+        assert_eq!(
+            exec(&db, &["HSET", "h", "age", "4", "city", "x"]),
+            Reply::Integer(1)
+        );
+        assert_eq!(exec(&db, &["HGET", "h", "age"]), bulk("4"));
+        assert_eq!(exec(&db, &["HGET", "h", "nope"]), Reply::Bulk(None));
+        assert_eq!(exec(&db, &["HLEN", "h"]), Reply::Integer(3));
+        assert_eq!(
+            exec(&db, &["HGETALL", "h"]),
+            Reply::Array(
+                ["age", "4", "city", "x", "name", "bob"]
+                    .iter()
+                    .map(|s| bulk(s))
+                    .collect()
+            )
+        );
+
+        assert_eq!(exec(&db, &["HDEL", "h", "age", "nope"]), Reply::Integer(1));
+        assert_eq!(exec(&db, &["HDEL", "h", "city", "name"]), Reply::Integer(2));
+        // 空的 hash 会被删掉
+        assert_eq!(exec(&db, &["EXISTS", "h"]), Reply::Integer(0));
+        assert_eq!(exec(&db, &["HGETALL", "h"]), Reply::Array(vec![]));
+
+        exec(&db, &["RPUSH", "l", "a"]);
+        assert_eq!(exec(&db, &["HSET", "l", "f", "v"]), Reply::error(WRONGTYPE));
+        exec(&db, &["HSET", "h", "f", "v"]);
+        assert_eq!(exec(&db, &["LPOP", "h"]), Reply::error(WRONGTYPE));
+        assert_eq!(exec(&db, &["GET", "h"]), Reply::error(WRONGTYPE));
+        assert_eq!(
+            exec(&db, &["HSET", "h", "f"]),
+            Reply::error("ERR wrong number of arguments for 'hset' command")
+        );
+    }
+}
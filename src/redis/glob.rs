@@ -0,0 +1,133 @@
+// KEYS 用的 glob 匹配，规则和 redis 的 stringmatchlen 一样：
+//   *      任意长度（包括空）
+//   ?      任意一个字符
+//   [abc]  其中一个字符，[a-z] 范围，[^a] 取反
+//   \x     转义，匹配字符 x 本身
+pub(crate) fn glob_match(pattern: &str, s: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let s = s.chars().collect::<Vec<_>>();
+    matches(&p, &s)
+}
+
+// 两个指针往前走，只记住最后一个 * 的位置：匹配失败时回到那个 *，让它多吃一个字符再试。
+// 前面的 * 不需要再回溯，所以最坏也只是 O(p.len() * s.len())，不会因为很多个 * 变成指数级
+fn matches(p: &[char], s: &[char]) -> bool {
+    let (mut pi, mut si) = (0, 0);
+    // (* 后面的 pattern 位置, 这个 * 已经吃到的 s 的位置)
+    let mut star = None;
+    while si < s.len() {
+        if p.get(pi) == Some(&'*') {
+            // 连续的 * 等价于一个
+            while p.get(pi) == Some(&'*') {
+                pi += 1;
+            }
+            if pi == p.len() {
+                return true;
+            }
+            star = Some((pi, si));
+            continue;
+        }
+        if let Some(len) = match_one(p, pi, s[si]) {
+            pi += len;
+            si += 1;
+            continue;
+        }
+        match star {
+            Some((sp, ss)) => {
+                pi = sp;
+                si = ss + 1;
+                star = Some((sp, ss + 1));
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// p[pi] 开始的一个 token（?、[...]、\x 或者普通字符）能不能匹配 c，能的话返回 token 的长度
+fn match_one(p: &[char], pi: usize, c: char) -> Option<usize> {
+    let (ok, len) = match p.get(pi)? {
+        '?' => (true, 1),
+        '[' => match class_end(&p[pi..]) {
+            Some(end) => (in_class(&p[pi + 1..pi + end], c), end + 1),
+            // 没有闭合的 [ 当作普通字符
+            None => (c == '[', 1),
+        },
+        '\\' if pi + 1 < p.len() => (p[pi + 1] == c, 2),
+        &x => (x == c, 1),
+    };
+    ok.then_some(len)
+}
+
+// 返回和 p[0] 的 [ 配对的 ] 的下标，[] 里面的 \] 不算
+fn class_end(p: &[char]) -> Option<usize> {
+    let mut i = 1;
+    while i < p.len() {
+        match p[i] {
+            '\\' => i += 2,
+            ']' if i > 1 => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let (negate, class) = match class.first() {
+        Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if class[i] == '\\' && i + 1 < class.len() {
+            found |= class[i + 1] == c;
+            i += 2;
+        } else if i + 2 < class.len() && class[i + 1] == '-' {
+            let (lo, hi) = (class[i].min(class[i + 2]), class[i].max(class[i + 2]));
+            found |= (lo..=hi).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("user:*", "user:42"));
+        assert!(!glob_match("user:*", "session:42"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-c]llo", "hbllo"));
+        assert!(glob_match("*\\*", "a*"));
+        assert!(!glob_match("*\\*", "ab"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("a*", "a"));
+        assert!(!glob_match("a?", "a"));
+        assert!(glob_match("*[0-9]", "id7"));
+        assert!(glob_match("[abc", "[abc"));
+    }
+
+    #[test]
+    fn test_glob_match_many_stars() {
+        // 递归回溯的写法在这里是指数级的，要在一瞬间返回
+        let pattern = format!("{}x", "*".repeat(30));
+        let s = "a".repeat(100);
+        assert!(!glob_match(&pattern, &s));
+        assert!(glob_match(&pattern, &format!("{}x", s)));
+        assert!(!glob_match(&"a*".repeat(30), &"a".repeat(29)));
+    }
+}
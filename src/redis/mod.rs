@@ -0,0 +1,12 @@
+// dumyredis 例子背后的 key-value store：解析命令、在共享的 DashMap 上执行、编码成 RESP 回复。
+// 网络部分仍然在 examples/dumyredis.rs 里，这里只管命令本身，方便单独测试。
+mod command;
+mod db;
+mod glob;
+mod pubsub;
+mod reply;
+
+pub use command::*;
+pub use db::*;
+pub use pubsub::*;
+pub use reply::*;
@@ -0,0 +1,279 @@
+// PUBLISH/SUBSCRIBE：每个 channel 一个 tokio broadcast channel，PUBLISH 只 send 一次，
+// 由 broadcast 复制给所有订阅者（fan-out），发布者不需要知道有哪些连接。
+//
+// 一个连接可以订阅多个 channel。Subscriber 给每个 channel 起一个转发 task，
+// 把 broadcast 收到的消息汇总到连接自己的 mpsc 里，连接只需要 select 一个 recv。
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+use tracing::warn;
+
+use super::Reply;
+
+// 订阅者处理得太慢时，broadcast 最多缓存这么多条消息，再多就丢掉最旧的
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct PubSub {
+    channels: Arc<DashMap<String, broadcast::Sender<Bytes>>>,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 返回收到消息的订阅者个数。没有订阅者的 channel 顺便删掉
+    pub fn publish(&self, channel: &str, message: impl Into<Bytes>) -> usize {
+        let Some(sent) = self.channels.get(channel).map(|tx| tx.send(message.into())) else {
+            return 0;
+        };
+        match sent {
+            Ok(n) => n,
+            Err(_) => {
+                self.channels
+                    .remove_if(channel, |_, tx| tx.receiver_count() == 0);
+                0
+            }
+        }
+    }
+
+    pub fn subscribe(&self, channel: &str) -> broadcast::Receiver<Bytes> {
+        self.channels
+            .entry(channel.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+}
+
+// 一个连接的订阅状态，连接断开时 drop，所有转发 task 跟着停止
+pub struct Subscriber {
+    pubsub: PubSub,
+    tx: mpsc::UnboundedSender<(String, Bytes)>,
+    rx: mpsc::UnboundedReceiver<(String, Bytes)>,
+    tasks: HashMap<String, JoinHandle<()>>,
+}
+
+impl Subscriber {
+    pub fn new(pubsub: PubSub) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Subscriber {
+            pubsub,
+            tx,
+            rx,
+            tasks: HashMap::new(),
+        }
+    }
+
+    // 返回订阅之后的 channel 个数；重复订阅同一个 channel 不会收到两份消息
+    pub fn subscribe(&mut self, channel: &str) -> usize {
+        if !self.tasks.contains_key(channel) {
+            let mut rx = self.pubsub.subscribe(channel);
+            let tx = self.tx.clone();
+            let name = channel.to_string();
+            let task = tokio::spawn(async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(message) => {
+                            if tx.send((name.clone(), message)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("subscriber of {} lagged, dropped {} messages", name, n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            self.tasks.insert(channel.to_string(), task);
+        }
+        self.tasks.len()
+    }
+
+    // 返回退订之后剩下的 channel 个数
+    pub fn unsubscribe(&mut self, channel: &str) -> usize {
+        if let Some(task) = self.tasks.remove(channel) {
+            task.abort();
+        }
+        self.tasks.len()
+    }
+
+    pub fn channels(&self) -> Vec<String> {
+        let mut channels = self.tasks.keys().cloned().collect::<Vec<_>>();
+        channels.sort();
+        channels
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    // 等待任意一个订阅的 channel 的下一条消息；没有订阅时一直等待，可以直接放进 select!
+    pub async fn recv(&mut self) -> Option<(String, Bytes)> {
+        self.rx.recv().await
+    }
+
+    // 处理 SUBSCRIBE/UNSUBSCRIBE，每个 channel 回复一条；不是这两个命令时返回 None
+    pub fn execute(&mut self, args: &[Bytes]) -> Option<Vec<Reply>> {
+        let (name, channels) = args.split_first()?;
+        let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+        let channels = channels
+            .iter()
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .collect::<Vec<_>>();
+        let replies = match name.as_str() {
+            "subscribe" if channels.is_empty() => vec![wrong_args(&name)],
+            "subscribe" => channels
+                .iter()
+                .map(|c| {
+                    let n = self.subscribe(c);
+                    confirm(&name, Some(c), n)
+                })
+                .collect(),
+            // 不带参数的 UNSUBSCRIBE 退订所有的 channel
+            "unsubscribe" => {
+                let channels = if channels.is_empty() {
+                    self.channels()
+                } else {
+                    channels
+                };
+                if channels.is_empty() {
+                    return Some(vec![confirm(&name, None, 0)]);
+                }
+                channels
+                    .iter()
+                    .map(|c| {
+                        let n = self.unsubscribe(c);
+                        confirm(&name, Some(c), n)
+                    })
+                    .collect()
+            }
+            _ => return None,
+        };
+        Some(replies)
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+// 推送给订阅者的消息：*3\r\n$7\r\nmessage\r\n$<channel>\r\n$<message>\r\n
+pub fn message_reply(channel: &str, message: Bytes) -> Reply {
+    Reply::Array(vec![
+        Reply::Bulk(Some(Bytes::from("message"))),
+        Reply::Bulk(Some(Bytes::copy_from_slice(channel.as_bytes()))),
+        Reply::Bulk(Some(message)),
+    ])
+}
+
+// SUBSCRIBE/UNSUBSCRIBE 的确认：命令名、channel、当前订阅的个数
+fn confirm(kind: &str, channel: Option<&str>, count: usize) -> Reply {
+    Reply::Array(vec![
+        Reply::Bulk(Some(Bytes::copy_from_slice(kind.as_bytes()))),
+        Reply::Bulk(channel.map(|c| Bytes::copy_from_slice(c.as_bytes()))),
+        Reply::Integer(count as i64),
+    ])
+}
+
+fn wrong_args(name: &str) -> Reply {
+    Reply::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter()
+            .map(|s| Bytes::copy_from_slice(s.as_bytes()))
+            .collect()
+    }
+
+    async fn recv(sub: &mut Subscriber) -> (String, Bytes) {
+        tokio::time::timeout(Duration::from_secs(1), sub.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pubsub_fan_out() {
+        let pubsub = PubSub::new();
+        assert_eq!(pubsub.publish("news", "nobody"), 0);
+
+        let mut a = Subscriber::new(pubsub.clone());
+        let mut b = Subscriber::new(pubsub.clone());
+        assert_eq!(a.subscribe("news"), 1);
+        assert_eq!(a.subscribe("sport"), 2);
+        assert_eq!(a.subscribe("news"), 2);
+        assert_eq!(b.subscribe("news"), 1);
+
+        assert_eq!(pubsub.publish("news", "hello"), 2);
+        assert_eq!(pubsub.publish("sport", "goal"), 1);
+        assert_eq!(
+            recv(&mut b).await,
+            ("news".to_string(), Bytes::from("hello"))
+        );
+        let mut got = vec![recv(&mut a).await, recv(&mut a).await];
+        got.sort();
+        assert_eq!(
+            got,
+            [
+                ("news".to_string(), Bytes::from("hello")),
+                ("sport".to_string(), Bytes::from("goal"))
+            ]
+        );
+
+        // 退订和 drop 之后不再计入订阅者
+        assert_eq!(a.unsubscribe("news"), 1);
+        drop(b);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(pubsub.publish("news", "again"), 0);
+        assert!(!pubsub.channels.contains_key("news"));
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_execute() {
+        let mut sub = Subscriber::new(PubSub::new());
+        assert_eq!(sub.execute(&args(&["GET", "foo"])), None);
+        assert_eq!(
+            sub.execute(&args(&["SUBSCRIBE", "a", "b"])),
+            Some(vec![
+                confirm("subscribe", Some("a"), 1),
+                confirm("subscribe", Some("b"), 2)
+            ])
+        );
+        assert_eq!(
+            sub.execute(&args(&["unsubscribe"])),
+            Some(vec![
+                confirm("unsubscribe", Some("a"), 1),
+                confirm("unsubscribe", Some("b"), 0)
+            ])
+        );
+        assert!(!sub.is_subscribed());
+        assert_eq!(
+            sub.execute(&args(&["unsubscribe"])),
+            Some(vec![confirm("unsubscribe", None, 0)])
+        );
+        assert_eq!(
+            message_reply("a", Bytes::from("hi")).encode(),
+            b"*3\r\n$7\r\nmessage\r\n$1\r\na\r\n$2\r\nhi\r\n"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+// RESP 的几种回复：
+//   +OK\r\n             simple string
+//   -ERR message\r\n    error，第一个单词是错误类型，比如 ERR、WRONGTYPE
+//   :42\r\n             integer
+//   $3\r\nbar\r\n       bulk string，$-1\r\n 表示 nil
+//   *2\r\n...           array，每个元素又是一个回复
+use bytes::Bytes;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Bytes>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Reply::Simple("OK".to_string())
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Reply::Error(message.into())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(s) => encode_line(out, b'+', s),
+            Reply::Error(e) => encode_line(out, b'-', e),
+            Reply::Integer(n) => out.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Bulk(Some(b)) => {
+                out.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
+                out.extend_from_slice(b);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode_into(out);
+                }
+            }
+        }
+    }
+}
+
+// simple string 和 error 只有一行，内容里可能带着客户端发来的命令名；
+// 和 redis 一样把 \r \n 换成空格，否则客户端可以往自己的回复流里塞额外的回复
+fn encode_line(out: &mut Vec<u8>, prefix: u8, s: &str) {
+    out.push(prefix);
+    out.extend(
+        s.bytes()
+            .map(|b| if b == b'\r' || b == b'\n' { b' ' } else { b }),
+    );
+    out.extend_from_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_encode() {
+        assert_eq!(Reply::ok().encode(), b"+OK\r\n");
+        assert_eq!(Reply::error("ERR boom").encode(), b"-ERR boom\r\n");
+        assert_eq!(Reply::Integer(-3).encode(), b":-3\r\n");
+        assert_eq!(Reply::Bulk(None).encode(), b"$-1\r\n");
+        assert_eq!(
+            Reply::Array(vec![
+                Reply::Bulk(Some(Bytes::from("bar"))),
+                Reply::Integer(1)
+            ])
+            .encode(),
+            b"*2\r\n$3\r\nbar\r\n:1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_reply_encode_strips_crlf() {
+        assert_eq!(
+            Reply::error("ERR unknown command 'A\r\n+OK'").encode(),
+            b"-ERR unknown command 'A  +OK'\r\n"
+        );
+        assert_eq!(Reply::Simple("a\nb".to_string()).encode(), b"+a b\r\n");
+    }
+}
@@ -0,0 +1,94 @@
+// SIMD 版本的 dot product kernel（cargo build --features simd）
+// 每次处理 8 个 f32/i32（或 4 个 f64），最后把各个 lane 加起来，余下不足一组的元素用普通的标量循环处理。
+// 注意：浮点数的累加顺序与标量版本不同，所以结果可能在最后几位上有差别。
+use std::any::{Any, TypeId};
+
+use wide::{f32x8, f64x4, i32x8};
+
+// 如果 T 和累加器 A 是同一种 f32/f64/i32，就走 SIMD 路径，否则返回 None，由调用者回退到标量版本。
+pub(crate) fn try_dot<T: 'static, A: Clone + 'static>(a: &[T], b: &[T]) -> Option<A> {
+    if TypeId::of::<T>() != TypeId::of::<A>() {
+        return None;
+    }
+
+    let t = TypeId::of::<T>();
+    let sum: Box<dyn Any> = if t == TypeId::of::<f32>() {
+        Box::new(dot_f32(cast(a), cast(b)))
+    } else if t == TypeId::of::<f64>() {
+        Box::new(dot_f64(cast(a), cast(b)))
+    } else if t == TypeId::of::<i32>() {
+        Box::new(dot_i32(cast(a), cast(b)))
+    } else {
+        return None;
+    };
+    sum.downcast_ref::<A>().cloned()
+}
+
+// 只在上面确认了 T 与 U 是同一个类型之后调用
+fn cast<T: 'static, U: 'static>(s: &[T]) -> &[U] {
+    assert_eq!(TypeId::of::<T>(), TypeId::of::<U>());
+    // SAFETY: T 和 U 是同一个类型，内存布局完全相同
+    unsafe { std::slice::from_raw_parts(s.as_ptr() as *const U, s.len()) }
+}
+
+fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+    let (xs, ys) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail = xs
+        .remainder()
+        .iter()
+        .zip(ys.remainder())
+        .map(|(x, y)| x * y);
+    let mut acc = f32x8::ZERO;
+    for (x, y) in xs.zip(ys) {
+        acc += f32x8::new(x.try_into().unwrap()) * f32x8::new(y.try_into().unwrap());
+    }
+    acc.reduce_add() + tail.sum::<f32>()
+}
+
+fn dot_f64(a: &[f64], b: &[f64]) -> f64 {
+    let (xs, ys) = (a.chunks_exact(4), b.chunks_exact(4));
+    let tail = xs
+        .remainder()
+        .iter()
+        .zip(ys.remainder())
+        .map(|(x, y)| x * y);
+    let mut acc = f64x4::ZERO;
+    for (x, y) in xs.zip(ys) {
+        acc += f64x4::new(x.try_into().unwrap()) * f64x4::new(y.try_into().unwrap());
+    }
+    acc.reduce_add() + tail.sum::<f64>()
+}
+
+// i32 的 SIMD 乘法和加法都是 wrapping 的，需要检测溢出请用 dot_product_checked
+fn dot_i32(a: &[i32], b: &[i32]) -> i32 {
+    let (xs, ys) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail = xs.remainder().iter().zip(ys.remainder());
+    let mut acc = i32x8::ZERO;
+    for (x, y) in xs.zip(ys) {
+        acc += i32x8::new(x.try_into().unwrap()) * i32x8::new(y.try_into().unwrap());
+    }
+    tail.fold(acc.reduce_add(), |sum, (x, y)| {
+        sum.wrapping_add(x.wrapping_mul(*y))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simd_dot_matches_scalar() {
+        let a = (0..19).map(|v| v as f32 * 0.5).collect::<Vec<_>>();
+        let b = (0..19).map(|v| 3.0 - v as f32).collect::<Vec<_>>();
+        let scalar = a.iter().zip(&b).map(|(x, y)| x * y).sum::<f32>();
+        assert!((try_dot::<f32, f32>(&a, &b).unwrap() - scalar).abs() < 1e-3);
+
+        let a = (0..19).collect::<Vec<i32>>();
+        let b = (0..19).map(|v| v - 7).collect::<Vec<i32>>();
+        let scalar = a.iter().zip(&b).map(|(x, y)| x * y).sum::<i32>();
+        assert_eq!(try_dot::<i32, i32>(&a, &b), Some(scalar));
+
+        // i32 累加到 i64 不走 SIMD
+        assert_eq!(try_dot::<i32, i64>(&a, &b), None);
+    }
+}
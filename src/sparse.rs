@@ -0,0 +1,322 @@
+// 稀疏矩阵：CSR (Compressed Sparse Row) 格式，只存非零元素
+// 图、推荐系统中的矩阵绝大部分元素都是 0，用稠密的 Matrix 存既浪费内存，也浪费计算。
+//
+// 例如 {1 0 2, 0 0 3, 4 0 0}：
+// values  = [1, 2, 3, 4]      // 按行依次排列的非零元素
+// col_idx = [0, 2, 2, 0]      // 每个非零元素所在的列
+// row_ptr = [0, 2, 3, 4]      // 第 i 行的非零元素是 values[row_ptr[i]..row_ptr[i + 1]]
+use anyhow::Result;
+use std::{
+    ops::{AddAssign, Mul, Range},
+    thread,
+};
+
+use crate::{
+    matrix::{panic_message, work_queue},
+    Error, Layout, Matrix,
+};
+
+// 每个 worker 消息处理的行数
+const ROWS_PER_MSG: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix<T> {
+    row: usize,
+    col: usize,
+    row_ptr: Vec<usize>,
+    col_idx: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<T> SparseMatrix<T> {
+    // 从 (row, col, value) 三元组构造；同一个位置出现多次时，值会被累加
+    pub fn from_triplets(row: usize, col: usize, triplets: &[(usize, usize, T)]) -> Result<Self>
+    where
+        T: AddAssign + Clone,
+    {
+        let mut sorted = triplets.to_vec();
+        sorted.sort_by_key(|&(i, j, _)| (i, j));
+
+        let mut sm = SparseMatrix {
+            row,
+            col,
+            row_ptr: vec![0; row + 1],
+            col_idx: Vec::with_capacity(sorted.len()),
+            values: Vec::with_capacity(sorted.len()),
+        };
+        let mut last = None;
+        for (i, j, v) in sorted {
+            if i >= row || j >= col {
+                return Err(Error::OutOfBounds {
+                    row: i,
+                    col: j,
+                    nrows: row,
+                    ncols: col,
+                }
+                .into());
+            }
+            if last == Some((i, j)) {
+                *sm.values.last_mut().expect("last value exists") += v;
+                continue;
+            }
+            last = Some((i, j));
+            sm.row_ptr[i + 1] += 1;
+            sm.col_idx.push(j);
+            sm.values.push(v);
+        }
+        // 每行的非零元素个数 => 前缀和
+        for i in 0..row {
+            sm.row_ptr[i + 1] += sm.row_ptr[i];
+        }
+        Ok(sm)
+    }
+
+    // 非零元素个数 (number of non-zeros)
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    // 第 i 行的非零元素：(列号, 值)
+    fn row_entries(&self, i: usize) -> impl Iterator<Item = (usize, &T)> {
+        let range = self.row_ptr[i]..self.row_ptr[i + 1];
+        self.col_idx[range.clone()]
+            .iter()
+            .copied()
+            .zip(&self.values[range])
+    }
+}
+
+impl<T> SparseMatrix<T>
+where
+    T: Default + PartialEq + Clone,
+{
+    // 等于 T::default() 的元素被当作 0，不存储
+    pub fn from_dense(m: &Matrix<T>) -> Self {
+        let mut row_ptr = Vec::with_capacity(m.row + 1);
+        let mut col_idx = Vec::new();
+        let mut values = Vec::new();
+        row_ptr.push(0);
+        for i in 0..m.row {
+            for j in 0..m.col {
+                let v = &m.data[m.offset(i, j)];
+                if *v != T::default() {
+                    col_idx.push(j);
+                    values.push(v.clone());
+                }
+            }
+            row_ptr.push(values.len());
+        }
+        SparseMatrix {
+            row: m.row,
+            col: m.col,
+            row_ptr,
+            col_idx,
+            values,
+        }
+    }
+
+    pub fn to_dense(&self) -> Matrix<T> {
+        let mut data = vec![T::default(); self.row * self.col];
+        for i in 0..self.row {
+            for (j, v) in self.row_entries(i) {
+                data[i * self.col + j] = v.clone();
+            }
+        }
+        Matrix::with_layout(data, self.row, self.col, Layout::RowMajor)
+    }
+}
+
+impl<T> SparseMatrix<T>
+where
+    T: Mul<Output = T> + AddAssign + Default + Clone + Send + Sync,
+{
+    // 稀疏 * 稠密 => 稠密。结果的第 i 行 = sum(a[i][k] * b 的第 k 行)，只需要遍历 a 第 i 行的非零元素
+    pub fn multiply_dense(&self, b: &Matrix<T>) -> Result<Matrix<T>> {
+        if self.col != b.row {
+            return Err(Error::DimensionMismatch {
+                what: "Matrix inner",
+                expected: self.col,
+                got: b.row,
+            }
+            .into());
+        }
+        let n = b.col;
+        let b = b.to_layout(Layout::RowMajor);
+
+        let chunks = par_rows(self.row, |rows| {
+            let mut out = vec![T::default(); rows.len() * n];
+            for (r, i) in rows.enumerate() {
+                let out_row = &mut out[r * n..(r + 1) * n];
+                for (k, v) in self.row_entries(i) {
+                    for (o, x) in out_row.iter_mut().zip(&b.data[k * n..(k + 1) * n]) {
+                        *o += v.clone() * x.clone();
+                    }
+                }
+            }
+            Ok(out)
+        })?;
+
+        let data = chunks.into_iter().flatten().collect::<Vec<_>>();
+        Ok(Matrix::with_layout(data, self.row, n, Layout::RowMajor))
+    }
+
+    // 稀疏 * 稀疏 => 稀疏。每一行用一个长度为 b.col 的稠密累加器，外加记录哪些列被写过
+    pub fn multiply_sparse(&self, b: &SparseMatrix<T>) -> Result<SparseMatrix<T>> {
+        if self.col != b.row {
+            return Err(Error::DimensionMismatch {
+                what: "Matrix inner",
+                expected: self.col,
+                got: b.row,
+            }
+            .into());
+        }
+        let n = b.col;
+
+        // 每批返回：(每行的非零个数, col_idx, values)
+        let chunks = par_rows(self.row, |rows| {
+            let mut acc = vec![T::default(); n];
+            let mut touched = vec![false; n];
+            let mut counts = Vec::with_capacity(rows.len());
+            let mut col_idx = Vec::new();
+            let mut values = Vec::new();
+            for i in rows {
+                let mut cols = Vec::new();
+                for (k, v) in self.row_entries(i) {
+                    for (j, x) in b.row_entries(k) {
+                        if !touched[j] {
+                            touched[j] = true;
+                            cols.push(j);
+                        }
+                        acc[j] += v.clone() * x.clone();
+                    }
+                }
+                cols.sort_unstable();
+                counts.push(cols.len());
+                for j in cols {
+                    col_idx.push(j);
+                    values.push(std::mem::take(&mut acc[j]));
+                    touched[j] = false;
+                }
+            }
+            Ok((counts, col_idx, values))
+        })?;
+
+        let mut sm = SparseMatrix {
+            row: self.row,
+            col: n,
+            row_ptr: Vec::with_capacity(self.row + 1),
+            col_idx: Vec::new(),
+            values: Vec::new(),
+        };
+        sm.row_ptr.push(0);
+        for (counts, col_idx, values) in chunks {
+            for c in counts {
+                sm.row_ptr
+                    .push(sm.row_ptr.last().expect("row_ptr is not empty") + c);
+            }
+            sm.col_idx.extend(col_idx);
+            sm.values.extend(values);
+        }
+        Ok(sm)
+    }
+}
+
+// 与 multiply 相同的 worker/channel 结构：每 ROWS_PER_MSG 行一批放进共享队列，worker 算完后通过 oneshot 把结果发回。
+// 这里用 thread::scope，worker 可以直接借用 self 和 b，不需要把稀疏矩阵 copy 到 'static 的消息里。
+// 返回值按批次顺序排列。
+fn par_rows<R, F>(nrows: usize, f: F) -> Result<Vec<R>>
+where
+    R: Send,
+    F: Fn(Range<usize>) -> Result<R> + Sync,
+{
+    type Job<R> = (Range<usize>, oneshot::Sender<Result<R>>);
+
+    let f = &f;
+    let (sender, receivers) = work_queue::<Job<R>>();
+    thread::scope(|s| {
+        let handles = receivers
+            .into_iter()
+            .map(|rx| {
+                s.spawn(move || {
+                    while let Ok((rows, tx)) = rx.recv() {
+                        // 发送失败说明调用者已经因为别的错误提前返回了
+                        let _ = tx.send(f(rows));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // sender 被 move 进这个闭包，闭包结束时 drop，worker 的 recv 循环随之结束
+        let result = (move || {
+            let mut results = Vec::with_capacity(nrows.div_ceil(ROWS_PER_MSG));
+            for i0 in (0..nrows).step_by(ROWS_PER_MSG) {
+                let (tx, rx) = oneshot::channel();
+                sender
+                    .send((i0..(i0 + ROWS_PER_MSG).min(nrows), tx))
+                    .map_err(|_| Error::QueueClosed { what: "Sparse" })?;
+                results.push(rx);
+            }
+            drop(sender);
+            results
+                .into_iter()
+                .map(|rx| rx.recv()?)
+                .collect::<Result<Vec<_>>>()
+        })();
+
+        for handle in handles {
+            handle.join().map_err(|e| Error::WorkerPanicked {
+                what: "Sparse",
+                message: panic_message(&e),
+            })?;
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiply;
+
+    #[test]
+    fn test_sparse_from_triplets() -> Result<()> {
+        let sm = SparseMatrix::from_triplets(3, 3, &[(2, 0, 4), (0, 2, 2), (0, 0, 1), (1, 2, 3)])?;
+        assert_eq!(sm.row_ptr, vec![0, 2, 3, 4]);
+        assert_eq!(sm.col_idx, vec![0, 2, 2, 0]);
+        assert_eq!(sm.values, vec![1, 2, 3, 4]);
+        assert_eq!(format!("{}", sm.to_dense()), "{1 0 2, 0 0 3, 4 0 0}");
+        assert_eq!(SparseMatrix::from_dense(&sm.to_dense()), sm);
+
+        assert!(SparseMatrix::from_triplets(2, 2, &[(2, 0, 1)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_multiply() -> Result<()> {
+        // 每 7 个元素有一个非零，行数超过 ROWS_PER_MSG，会被分成多批
+        let (m, k, n) = (150, 40, 30);
+        let a = Matrix::new(
+            (0..m * k)
+                .map(|v| if v % 7 == 0 { (v % 5) as i64 - 2 } else { 0 })
+                .collect::<Vec<_>>(),
+            m,
+            k,
+        );
+        let b = Matrix::new(
+            (0..k * n)
+                .map(|v| if v % 3 == 0 { (v % 11) as i64 } else { 0 })
+                .collect::<Vec<_>>(),
+            k,
+            n,
+        );
+        let expected = multiply(&a, &b)?;
+
+        let sa = SparseMatrix::from_dense(&a);
+        assert_eq!(sa.multiply_dense(&b)?.data, expected.data);
+        let sb = SparseMatrix::from_dense(&b);
+        assert_eq!(sa.multiply_sparse(&sb)?.to_dense().data, expected.data);
+
+        assert!(sa.multiply_dense(&a).is_err());
+        Ok(())
+    }
+}
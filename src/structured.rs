@@ -0,0 +1,299 @@
+// 有特殊结构的方阵：三角矩阵有一半在结构上为 0，对称矩阵只需要读上三角（另一半是镜像），
+// gram 这样结果对称的乘法只需要算一半再镜像。
+// 两者都复用 matrix 中的 worker pool，只是给每个输出单元一个更短的 active 范围，大约省掉一半的乘法。
+use anyhow::Result;
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Mul},
+};
+
+use crate::{
+    matrix::{full_range, par_dot_product_into, ActiveRange},
+    vector::dot_slice_acc,
+    Error, Layout, Matrix, MultiplyOptions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Triangle {
+    Upper, // j < i 的部分为 0
+    Lower, // j > i 的部分为 0
+}
+
+pub struct TriangularMatrix<T> {
+    matrix: Matrix<T>,
+    triangle: Triangle,
+}
+
+pub struct SymmetricMatrix<T> {
+    matrix: Matrix<T>,
+}
+
+// Matrix 的 Debug 需要 T: Display，所以不能直接 derive
+impl<T: fmt::Display> fmt::Debug for TriangularMatrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TriangularMatrix({:?}, {:?})",
+            self.triangle, self.matrix
+        )
+    }
+}
+
+impl<T: fmt::Display> fmt::Debug for SymmetricMatrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SymmetricMatrix({:?})", self.matrix)
+    }
+}
+
+impl<T: Default + Clone> TriangularMatrix<T> {
+    // 另一半的元素会被清成 T::default()，所以 as_matrix() 拿到的就是真正参与计算的矩阵
+    pub fn new(matrix: Matrix<T>, triangle: Triangle) -> Result<Self> {
+        if matrix.row != matrix.col {
+            return Err(Error::NotSquare {
+                what: "Triangular",
+                row: matrix.row,
+                col: matrix.col,
+            }
+            .into());
+        }
+        let mut matrix = matrix.to_layout(Layout::RowMajor);
+        let n = matrix.col;
+        for i in 0..n {
+            for j in 0..n {
+                let zero = match triangle {
+                    Triangle::Upper => j < i,
+                    Triangle::Lower => j > i,
+                };
+                if zero {
+                    matrix.data[i * n + j] = T::default();
+                }
+            }
+        }
+        Ok(Self { matrix, triangle })
+    }
+
+    pub fn upper(matrix: Matrix<T>) -> Result<Self> {
+        Self::new(matrix, Triangle::Upper)
+    }
+
+    pub fn lower(matrix: Matrix<T>) -> Result<Self> {
+        Self::new(matrix, Triangle::Lower)
+    }
+}
+
+impl<T> TriangularMatrix<T> {
+    pub fn triangle(&self) -> Triangle {
+        self.triangle
+    }
+
+    pub fn as_matrix(&self) -> &Matrix<T> {
+        &self.matrix
+    }
+
+    pub fn into_matrix(self) -> Matrix<T> {
+        self.matrix
+    }
+}
+
+impl<T> TriangularMatrix<T>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    // self * b：上三角的第 i 行只有 i..n 列非 0，下三角只有 0..=i 列非 0
+    pub fn multiply(&self, b: &Matrix<T>) -> Result<Matrix<T>> {
+        let active: ActiveRange = match self.triangle {
+            Triangle::Upper => |i, _, k| i.min(k)..k,
+            Triangle::Lower => |i, _, k| 0..(i + 1).min(k),
+        };
+        let mut data = vec![T::default(); self.matrix.row * b.col];
+        par_dot_product_into(
+            &self.matrix,
+            b,
+            &MultiplyOptions::default(),
+            dot_slice_acc::<T, T>,
+            active,
+            &mut data,
+        )?;
+        Ok(Matrix::with_layout(
+            data,
+            self.matrix.row,
+            b.col,
+            Layout::RowMajor,
+        ))
+    }
+}
+
+impl<T: PartialEq> SymmetricMatrix<T> {
+    pub fn new(matrix: Matrix<T>) -> Result<Self> {
+        if matrix.row != matrix.col {
+            return Err(Error::NotSquare {
+                what: "Symmetric",
+                row: matrix.row,
+                col: matrix.col,
+            }
+            .into());
+        }
+        for i in 0..matrix.row {
+            for j in i + 1..matrix.col {
+                if matrix.data[matrix.offset(i, j)] != matrix.data[matrix.offset(j, i)] {
+                    return Err(Error::NotSymmetric { row: i, col: j }.into());
+                }
+            }
+        }
+        Ok(Self { matrix })
+    }
+}
+
+impl<T> SymmetricMatrix<T> {
+    pub fn as_matrix(&self) -> &Matrix<T> {
+        &self.matrix
+    }
+
+    pub fn into_matrix(self) -> Matrix<T> {
+        self.matrix
+    }
+}
+
+impl<T> SymmetricMatrix<T>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    // self * b，只读上三角：第 i 行的 k >= i 部分就是 self 的第 i 行，
+    // k < i 部分 self[i][k] == self[k][i]，从转置的第 i 行（也就是上三角的第 i 列）里读
+    pub fn multiply(&self, b: &Matrix<T>) -> Result<Matrix<T>> {
+        let (n, opts) = (self.matrix.row, MultiplyOptions::default());
+        let mut data = vec![T::default(); n * b.col];
+        par_dot_product_into(
+            &self.matrix,
+            b,
+            &opts,
+            dot_slice_acc::<T, T>,
+            |i, _, k| i.min(k)..k,
+            &mut data,
+        )?;
+        let mut lower = vec![T::default(); n * b.col];
+        par_dot_product_into(
+            &self.matrix.transpose(),
+            b,
+            &opts,
+            dot_slice_acc::<T, T>,
+            |i, _, k| 0..i.min(k),
+            &mut lower,
+        )?;
+        for (v, l) in data.iter_mut().zip(lower) {
+            *v += l;
+        }
+        Ok(Matrix::with_layout(data, n, b.col, Layout::RowMajor))
+    }
+
+    // Gram 矩阵 a * a^T 一定是对称的：只算 j >= i 的上半部分，下半部分直接镜像过去
+    pub fn gram(a: &Matrix<T>) -> Result<Self> {
+        let n = a.row;
+        let mut data = vec![T::default(); n * n];
+        par_dot_product_into(
+            a,
+            &a.transpose(),
+            &MultiplyOptions::default(),
+            dot_slice_acc::<T, T>,
+            |i, j, k| if j < i { 0..0 } else { full_range(i, j, k) },
+            &mut data,
+        )?;
+        for i in 0..n {
+            for j in 0..i {
+                data[i * n + j] = data[j * n + i].clone();
+            }
+        }
+        Ok(Self {
+            matrix: Matrix::with_layout(data, n, n, Layout::RowMajor),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiply;
+
+    #[test]
+    fn test_triangular_multiply() -> Result<()> {
+        let n = 70;
+        let m = Matrix::new(
+            (0..n * n).map(|v| (v % 9) as i64 - 4).collect::<Vec<_>>(),
+            n,
+            n,
+        );
+        // 70 * 70 * 10 超过了 SEQUENTIAL_THRESHOLD，会走多线程
+        let b = Matrix::new(
+            (0..n * 10).map(|v| (v % 5) as i64).collect::<Vec<_>>(),
+            n,
+            10,
+        );
+        for triangle in [Triangle::Upper, Triangle::Lower] {
+            let t = TriangularMatrix::new(m.to_layout(Layout::RowMajor), triangle)?;
+            assert_eq!(t.triangle(), triangle);
+            // 另一半已经被清成 0，所以普通的 multiply 结果就是正确答案
+            let expected = multiply(t.as_matrix(), &b)?;
+            assert_eq!(t.multiply(&b)?.data, expected.data);
+        }
+
+        let t = TriangularMatrix::upper(Matrix::new([1, 2, 3, 4], 2, 2))?;
+        assert_eq!(format!("{}", t.as_matrix()), "{1 2, 0 4}");
+        let t = TriangularMatrix::lower(Matrix::new([1, 2, 3, 4], 2, 2))?;
+        assert_eq!(format!("{}", t.into_matrix()), "{1 0, 3 4}");
+        assert!(TriangularMatrix::upper(Matrix::new([1, 2], 1, 2)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_symmetric_gram() -> Result<()> {
+        let a = Matrix::new(
+            (0..40 * 7).map(|v| (v % 11) as i64 - 5).collect::<Vec<_>>(),
+            40,
+            7,
+        );
+        let g = SymmetricMatrix::gram(&a)?;
+        assert_eq!(g.as_matrix().data, multiply(&a, &a.transpose())?.data);
+
+        assert!(SymmetricMatrix::new(Matrix::new([1, 2, 2, 3], 2, 2)).is_ok());
+        assert!(SymmetricMatrix::new(Matrix::new([1, 2], 1, 2)).is_err());
+        let err = SymmetricMatrix::new(Matrix::new([1, 2, 3, 4], 2, 2)).unwrap_err();
+        assert_eq!(err.to_string(), "Matrix is not symmetric at (0, 1)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_symmetric_multiply() -> Result<()> {
+        let a = Matrix::new(
+            (0..70 * 9).map(|v| (v % 7) as i64 - 3).collect::<Vec<_>>(),
+            70,
+            9,
+        );
+        let s = SymmetricMatrix::gram(&a)?;
+        // 70 * 70 * 10 超过了 SEQUENTIAL_THRESHOLD，会走多线程
+        let b = Matrix::new(
+            (0..70 * 10).map(|v| (v % 5) as i64).collect::<Vec<_>>(),
+            70,
+            10,
+        );
+        let expected = multiply(s.as_matrix(), &b)?;
+        assert_eq!(s.multiply(&b)?.data, expected.data);
+
+        // 下三角不会被读到：把它弄坏之后结果不变
+        let mut s = s;
+        let n = s.matrix.row;
+        for i in 0..n {
+            for j in 0..i {
+                s.matrix.data[i * n + j] = 1000;
+            }
+        }
+        assert_eq!(s.multiply(&b)?.data, expected.data);
+
+        // ColMajor 的 self 和 b 也一样
+        let dense = multiply(&a, &a.transpose())?;
+        let s = SymmetricMatrix::new(dense.to_layout(Layout::ColMajor))?;
+        let b = b.to_layout(Layout::ColMajor);
+        assert_eq!(s.multiply(&b)?.data, expected.data);
+        assert!(s.multiply(&Matrix::new([1, 2], 1, 2)).is_err());
+        Ok(())
+    }
+}
@@ -1,31 +1,305 @@
-use anyhow::{anyhow, Result};
-use std::ops::{Add, AddAssign, Deref, Mul};
+use anyhow::Result;
+use num_traits::{CheckedAdd, CheckedMul, Float, One, ToPrimitive, Zero};
+use rand::{
+    distributions::{uniform::SampleUniform, Uniform},
+    Rng,
+};
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Deref, DerefMut, Mul, Sub},
+    thread,
+};
+
+use crate::{
+    matrix::{panic_message, par_reduce_slice, NUM_THREADS},
+    Error,
+};
+
+// 元素个数小于这个值时，dot_product_par 不开线程
+const PAR_THRESHOLD: usize = 1 << 14;
+
 // use std::ops::{Index, Deref};
+#[derive(Clone)]
 pub struct Vector<T> {
     data: Vec<T>,
 }
 
+// 借用的向量视图：一段切片，每隔 stride 个元素取一个。
+// 比如按行存储的矩阵的第 j 列就是 &data[j..] 每隔 col 个取一个，不需要 step_by().collect() 出一个新的 Vec。
+#[derive(Debug, Clone, Copy)]
+pub struct VectorView<'a, T> {
+    data: &'a [T],
+    stride: usize,
+}
+
 // pretend this is a heavy computation, CPU intensive, so we want to move it to a thread. // 假装这是一个计算量重的任务，CPU 密集型，所以我们想把它移到一个线程中。
-pub fn dot_product<T>(a: Vector<T>, b: Vector<T>) -> Result<T>
+// 参数是借用的视图：&Vector、&[T]、&Vec<T> 和带 stride 的 VectorView 都可以直接传进来，并且可以在多次调用之间复用
+pub fn dot_product<'a, T>(
+    a: impl Into<VectorView<'a, T>>,
+    b: impl Into<VectorView<'a, T>>,
+) -> Result<T>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + 'static,
+{
+    dot_product_acc(a, b)
+}
+
+// 累加器类型 A 可以比元素类型 T 更宽，比如 i32 的向量累加到 i64 里，避免真实规模的 i32 数据几乎必然的溢出。
+// A: From<T> 表示 T 可以无损地转换成 A；dot_product 就是 A = T 的特例（任何 T 都实现了 From<T>）。
+pub fn dot_product_acc<'a, T, A>(
+    a: impl Into<VectorView<'a, T>>,
+    b: impl Into<VectorView<'a, T>>,
+) -> Result<A>
+where
+    T: Clone + 'static,
+    A: From<T> + Mul<Output = A> + AddAssign + Default + Clone + 'static,
+{
+    let (a, b) = (a.into(), b.into());
+    // 两边都是连续的切片时走切片 kernel（可以用上 SIMD），否则逐个元素累加
+    if let (Some(a), Some(b)) = (a.as_slice(), b.as_slice()) {
+        return dot_slice_acc(a, b);
+    }
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    let mut sum = A::default();
+    for (x, y) in a.iter().zip(b.iter()) {
+        sum += A::from(x.clone()) * A::from(y.clone());
+    }
+    Ok(sum)
+}
+
+// 与 dot_product 相同，但用 checked_mul/checked_add 检查整数溢出：溢出时返回 Ok(None)，而不是悄悄 wrap。
+pub fn dot_product_checked<'a, T>(
+    a: impl Into<VectorView<'a, T>>,
+    b: impl Into<VectorView<'a, T>>,
+) -> Result<Option<T>>
 where
-    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Copy,
+    T: CheckedMul + CheckedAdd + Default + Clone + 'a,
 {
+    let (a, b) = (a.into(), b.into());
+    if let (Some(a), Some(b)) = (a.as_slice(), b.as_slice()) {
+        return dot_slice_checked(a, b);
+    }
     if a.len() != b.len() {
-        // a.len => a.data.len(), (通过 deref trait 实现的)
-        return Err(anyhow!("Vector dimensions do not match"));
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
     }
     let mut sum = T::default();
-    for i in 0..a.len() {
-        sum += a[i] * b[i];
+    for (x, y) in a.iter().zip(b.iter()) {
+        match x.checked_mul(y).and_then(|v| sum.checked_add(&v)) {
+            Some(v) => sum = v,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(sum))
+}
+
+// Kahan（补偿）求和版本，只用于浮点数：额外记录每次加法丢掉的低位误差，并在下一次加法时补回去。
+// 长向量用普通累加时误差大约随长度线性增长，Kahan 求和的误差与长度基本无关，代价是每个元素多做几次加减法。
+pub fn dot_product_stable<'a, T: Float + 'a>(
+    a: impl Into<VectorView<'a, T>>,
+    b: impl Into<VectorView<'a, T>>,
+) -> Result<T> {
+    let (a, b) = (a.into(), b.into());
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    let mut sum = T::zero();
+    let mut compensation = T::zero(); // 上一次加法丢掉的部分
+    for (&x, &y) in a.iter().zip(b.iter()) {
+        let v = x * y - compensation;
+        let t = sum + v;
+        compensation = (t - sum) - v;
+        sum = t;
     }
     Ok(sum)
 }
 
+// 大向量的并行版本：切成 NUM_THREADS 块，每块在一个线程里算部分和，最后在当前线程里按块的顺序合并。
+// 向量很短时开线程不划算，直接用单线程的 dot_product。
+pub fn dot_product_par<T>(a: &Vector<T>, b: &Vector<T>) -> Result<T>
+where
+    T: Mul<Output = T> + Add<Output = T> + AddAssign + Default + Clone + Send + Sync + 'static,
+{
+    dot_product_par_acc(a, b)
+}
+
+// 与 dot_product_acc 一样可以指定更宽的累加器：并行版本处理的正是最容易溢出的长向量
+pub fn dot_product_par_acc<T, A>(a: &Vector<T>, b: &Vector<T>) -> Result<A>
+where
+    T: Clone + Send + Sync + 'static,
+    A: From<T> + Mul<Output = A> + AddAssign + Default + Clone + Send + 'static,
+{
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    if a.len() < PAR_THRESHOLD {
+        return dot_slice_acc(a, b);
+    }
+
+    let chunk_size = a.len().div_ceil(NUM_THREADS);
+    // thread::scope 允许子线程直接借用 a、b 的切片
+    thread::scope(|s| {
+        let handles = a
+            .chunks(chunk_size)
+            .zip(b.chunks(chunk_size))
+            .map(|(x, y)| s.spawn(move || dot_slice_acc::<T, A>(x, y)))
+            .collect::<Vec<_>>();
+
+        let mut sum = A::default();
+        for handle in handles {
+            sum += handle.join().map_err(|e| Error::WorkerPanicked {
+                what: "Vector",
+                message: panic_message(&e),
+            })??;
+        }
+        Ok(sum)
+    })
+}
+
+// 以下是基于切片的 kernel，matrix 的 worker 直接在 tile 的切片上调用它们，不需要为每个单元构造 Vector
+pub(crate) fn dot_slice_acc<T, A>(a: &[T], b: &[T]) -> Result<A>
+where
+    T: Clone + 'static,
+    A: From<T> + Mul<Output = A> + AddAssign + Default + Clone + 'static,
+{
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    #[cfg(feature = "simd")]
+    if let Some(sum) = crate::simd::try_dot::<T, A>(a, b) {
+        return Ok(sum);
+    }
+    Ok(dot_slice_unrolled(a, b))
+}
+
+// 没有显式 SIMD 时（没开 simd feature，或者类型不是 f32/f64/i32）的快速路径：
+// UNROLL 个互相独立的累加器打破了 sum += ... 之间的依赖链，编译器可以把内层循环自动向量化（autovectorize）。
+// 与 SIMD 版本一样，浮点数的累加顺序与 dot_slice_scalar 不同；调用者保证 a、b 长度相同。
+fn dot_slice_unrolled<T, A>(a: &[T], b: &[T]) -> A
+where
+    T: Clone,
+    A: From<T> + Mul<Output = A> + AddAssign + Default,
+{
+    const UNROLL: usize = 4;
+    let mut acc: [A; UNROLL] = std::array::from_fn(|_| A::default());
+    let (xs, ys) = (a.chunks_exact(UNROLL), b.chunks_exact(UNROLL));
+    let (x_tail, y_tail) = (xs.remainder(), ys.remainder());
+    for (x, y) in xs.zip(ys) {
+        for (lane, (x, y)) in acc.iter_mut().zip(x.iter().zip(y)) {
+            *lane += A::from(x.clone()) * A::from(y.clone());
+        }
+    }
+    let mut sum = A::default();
+    for lane in acc {
+        sum += lane;
+    }
+    for (x, y) in x_tail.iter().zip(y_tail) {
+        sum += A::from(x.clone()) * A::from(y.clone());
+    }
+    sum
+}
+
+// 不走 SIMD 的版本：严格按下标从小到大累加，浮点结果与 feature、CPU 无关，与 multiply_seq 逐位相同
+pub(crate) fn dot_slice_scalar<T, A>(a: &[T], b: &[T]) -> Result<A>
+where
+    T: Clone,
+    A: From<T> + Mul<Output = A> + AddAssign + Default,
+{
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    let mut sum = A::default();
+    // 元素可能是 BigInt 这种堆上分配的类型，按引用遍历，只在交给累加器时 clone 一次
+    for (x, y) in a.iter().zip(b) {
+        sum += A::from(x.clone()) * A::from(y.clone());
+    }
+    Ok(sum)
+}
+
+pub(crate) fn dot_slice_checked<T>(a: &[T], b: &[T]) -> Result<Option<T>>
+where
+    T: CheckedMul + CheckedAdd + Default + Clone,
+{
+    if a.len() != b.len() {
+        return Err(Error::DimensionMismatch {
+            what: "Vector",
+            expected: a.len(),
+            got: b.len(),
+        }
+        .into());
+    }
+    let mut sum = T::default();
+    for (x, y) in a.iter().zip(b) {
+        match x.checked_mul(y).and_then(|v| sum.checked_add(&v)) {
+            Some(v) => sum = v,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(sum))
+}
+
 impl<T> Vector<T> {
     pub fn new(data: impl Into<Vec<T>>) -> Self {
         Self { data: data.into() }
     }
 
+    // 生成任意长度的输入，例子、benchmark 和测试里不需要手写字面量
+    pub fn zeros(n: usize) -> Self
+    where
+        T: Zero + Clone,
+    {
+        Self::new(vec![T::zero(); n])
+    }
+
+    pub fn ones(n: usize) -> Self
+    where
+        T: One + Clone,
+    {
+        Self::new(vec![T::one(); n])
+    }
+
+    // range 可以是 a..b 或者 a..=b，与 rng.gen_range 一样；空的 range 会 panic
+    pub fn random(n: usize, range: impl Into<Uniform<T>>) -> Self
+    where
+        T: SampleUniform,
+    {
+        rand::thread_rng()
+            .sample_iter(range.into())
+            .take(n)
+            .collect()
+    }
+
     // pub fn len(&self) -> usize {
     //     self.data.len()
     // }
@@ -35,6 +309,352 @@ impl<T> Vector<T> {
     // }
 }
 
+impl<T: Clone> Vector<T> {
+    // 逐元素相加/相减，长度不同时返回错误；+ 和 - 运算符在此基础上实现
+    pub fn try_add(&self, other: &Vector<T>) -> Result<Vector<T>>
+    where
+        T: Add<Output = T>,
+    {
+        self.zip_with(other, |x, y| x + y)
+    }
+
+    pub fn try_sub(&self, other: &Vector<T>) -> Result<Vector<T>>
+    where
+        T: Sub<Output = T>,
+    {
+        self.zip_with(other, |x, y| x - y)
+    }
+
+    // 数乘：每个元素都乘以 k
+    pub fn scale(&self, k: T) -> Vector<T>
+    where
+        T: Mul<Output = T>,
+    {
+        Vector::new(
+            self.data
+                .iter()
+                .map(|x| x.clone() * k.clone())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // 叉积，只对 3 维向量有定义：a x b = (a1 b2 - a2 b1, a2 b0 - a0 b2, a0 b1 - a1 b0)
+    pub fn cross(&self, other: &Vector<T>) -> Result<Vector<T>>
+    where
+        T: Mul<Output = T> + Sub<Output = T>,
+    {
+        if self.len() != 3 || other.len() != 3 {
+            return Err(Error::CrossDimension {
+                lhs: self.len(),
+                rhs: other.len(),
+            }
+            .into());
+        }
+        let (a, b) = (&self.data, &other.data);
+        let term = |i: usize, j: usize| a[i].clone() * b[j].clone() - a[j].clone() * b[i].clone();
+        Ok(Vector::new([term(1, 2), term(2, 0), term(0, 1)]))
+    }
+
+    fn zip_with(&self, other: &Vector<T>, f: impl Fn(T, T) -> T) -> Result<Vector<T>> {
+        if self.len() != other.len() {
+            return Err(Error::DimensionMismatch {
+                what: "Vector",
+                expected: self.len(),
+                got: other.len(),
+            }
+            .into());
+        }
+        Ok(Vector::new(
+            self.data
+                .iter()
+                .zip(&other.data)
+                .map(|(x, y)| f(x.clone(), y.clone()))
+                .collect::<Vec<_>>(),
+        ))
+    }
+}
+
+// 范数只对浮点数有意义（需要 sqrt/abs），所以用 num_traits::Float 约束
+impl<T: Float> Vector<T> {
+    // L2 范数：sqrt(sum(x^2))
+    pub fn norm(&self) -> T {
+        self.data
+            .iter()
+            .fold(T::zero(), |acc, &x| acc + x * x)
+            .sqrt()
+    }
+
+    // L1 范数：sum(|x|)
+    pub fn norm_l1(&self) -> T {
+        self.data.iter().fold(T::zero(), |acc, &x| acc + x.abs())
+    }
+
+    // 除以 L2 范数得到单位向量；零向量没有方向，返回错误
+    pub fn normalize(&self) -> Result<Vector<T>> {
+        let norm = self.norm();
+        if norm == T::zero() {
+            return Err(Error::ZeroVector("Normalization").into());
+        }
+        Ok(Vector::new(
+            self.data.iter().map(|&x| x / norm).collect::<Vec<_>>(),
+        ))
+    }
+
+    // 逐个元素比较，差的绝对值都不超过 epsilon 时认为相等；长度不同或者有 NaN 时返回 false。
+    // SIMD 与标量、并行与单线程的浮点结果累加顺序不同，测试里不能用 == 比较
+    pub fn approx_eq(&self, other: &Vector<T>, epsilon: T) -> bool {
+        self.len() == other.len()
+            && self
+                .data
+                .iter()
+                .zip(&other.data)
+                .all(|(&x, &y)| (x - y).abs() <= epsilon)
+    }
+
+    // 余弦相似度：dot(a, b) / (|a| * |b|)，范围是 [-1, 1]；比较 embedding 时最常用。
+    // 长度不同或者有一边是零向量时返回错误
+    pub fn cosine_similarity(&self, other: &Vector<T>) -> Result<f64>
+    where
+        T: AddAssign + Default + 'static,
+    {
+        let dot = dot_product(self, other)?;
+        let norms = self.norm() * other.norm();
+        if norms == T::zero() {
+            return Err(Error::ZeroVector("Cosine similarity").into());
+        }
+        (dot / norms)
+            .to_f64()
+            .ok_or_else(|| Error::NotRepresentable("Cosine similarity").into())
+    }
+}
+
+impl<T: Sync> Vector<T> {
+    // 并行的 map：切成 NUM_THREADS 块，每块在一个线程里 map，再按块的顺序拼回一个 Vector。
+    // 与 dot_product_par 一样，向量很短时直接在当前线程里 map
+    pub fn par_map<U, F>(&self, f: F) -> Vector<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        if self.len() < PAR_THRESHOLD {
+            return self.iter().map(f).collect();
+        }
+        let f = &f;
+        let chunk_size = self.len().div_ceil(NUM_THREADS);
+        thread::scope(|s| {
+            let handles = self
+                .data
+                .chunks(chunk_size)
+                .map(|chunk| s.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("Vector map worker panicked!"))
+                .collect()
+        })
+    }
+}
+
+// 与 Matrix::sum/min/max 一样的 map-reduce：切成 NUM_THREADS 块，每块在一个线程里做局部 reduce，再在当前线程合并
+impl<T: Clone + Send + Sync> Vector<T> {
+    pub fn sum(&self) -> T
+    where
+        T: Add<Output = T> + Default,
+    {
+        par_reduce_slice(&self.data, T::default(), |a, b| a + b)
+    }
+
+    // 空向量没有平均值
+    pub fn mean(&self) -> Option<f64>
+    where
+        T: Add<Output = T> + Default + ToPrimitive,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.sum().to_f64()? / self.len() as f64)
+    }
+
+    // 最大/最小元素的下标；有多个相同的最大值时返回第一个
+    pub fn argmax(&self) -> Option<usize>
+    where
+        T: PartialOrd,
+    {
+        self.par_arg(|x, best| x > best)
+    }
+
+    pub fn argmin(&self) -> Option<usize>
+    where
+        T: PartialOrd,
+    {
+        self.par_arg(|x, best| x < best)
+    }
+
+    // 每块在一个线程里找出局部的最优下标，再按块的顺序合并；better 是严格比较，所以相等时保留下标较小的那个
+    fn par_arg(&self, better: fn(&T, &T) -> bool) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let chunk_size = self.len().div_ceil(NUM_THREADS);
+        thread::scope(|s| {
+            let handles = self
+                .data
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(c, chunk)| {
+                    s.spawn(move || {
+                        let mut best = 0;
+                        for (i, x) in chunk.iter().enumerate().skip(1) {
+                            if better(x, &chunk[best]) {
+                                best = i;
+                            }
+                        }
+                        c * chunk_size + best
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Reduce worker panicked!"))
+                .reduce(|best, i| {
+                    if better(&self.data[i], &self.data[best]) {
+                        i
+                    } else {
+                        best
+                    }
+                })
+        })
+    }
+}
+
+// 与 Matrix 的 * 一样：运算符不能返回 Result，所以长度不同时 panic；需要处理错误时请用 try_add/try_sub
+impl<T: Add<Output = T> + Clone> Add for Vector<T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.try_add(&rhs).expect("Vector add error!")
+    }
+}
+
+impl<T: Sub<Output = T> + Clone> Sub for Vector<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.try_sub(&rhs).expect("Vector sub error!")
+    }
+}
+
+// 标量乘法：v * k
+impl<T: Mul<Output = T> + Clone> Mul<T> for Vector<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        self.scale(rhs)
+    }
+}
+
+impl<'a, T> VectorView<'a, T> {
+    pub fn new(data: &'a [T]) -> Self {
+        Self { data, stride: 1 }
+    }
+
+    // data[0], data[stride], data[2 * stride], ...
+    pub fn with_stride(data: &'a [T], stride: usize) -> Result<Self> {
+        if stride == 0 {
+            return Err(Error::InvalidStride.into());
+        }
+        Ok(Self { data, stride })
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len().div_ceil(self.stride)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'a T> {
+        self.data.get(i.checked_mul(self.stride)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> {
+        self.data.iter().step_by(self.stride)
+    }
+
+    // stride 为 1 时就是一段连续的切片
+    pub fn as_slice(&self) -> Option<&'a [T]> {
+        (self.stride == 1).then_some(self.data)
+    }
+}
+
+impl<'a, T> From<&'a [T]> for VectorView<'a, T> {
+    fn from(data: &'a [T]) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T> From<&'a Vec<T>> for VectorView<'a, T> {
+    fn from(data: &'a Vec<T>) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T> From<&'a Vector<T>> for VectorView<'a, T> {
+    fn from(v: &'a Vector<T>) -> Self {
+        Self::new(&v.data)
+    }
+}
+
+// 让 Vector 可以直接用在迭代器链里：for x in v / for x in &v / iter.collect::<Vector<_>>()
+impl<T> IntoIterator for Vector<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Vector<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<T> FromIterator<T> for Vector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            data: iter.into_iter().collect(),
+        }
+    }
+}
+
+// 与 Matrix 的格式一致：{1 2 3}
+impl<T: fmt::Display> fmt::Display for Vector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, v) in self.data.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<T: fmt::Display> fmt::Debug for Vector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Vector(len={}, {})", self.len(), self)
+    }
+}
+
 // 方法一：实现 Deref trait
 // 为 Vector<T> 实现 Deref trait，这样，我们就可以通过 *a 来访问 Vector<T> 中的 Vec<T>。
 impl<T> Deref for Vector<T> {
@@ -45,9 +665,23 @@ impl<T> Deref for Vector<T> {
     }
 }
 
+// 再实现 DerefMut，v[i] = x、v.push(x)、v.extend(iter) 这些 Vec 的可变方法就都可以直接用在 Vector 上了
+impl<T> DerefMut for Vector<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+// Extend trait 本身也实现一下，这样 Vector 可以用在要求 T: Extend 的泛型代码里
+impl<T> Extend<T> for Vector<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.data.extend(iter)
+    }
+}
+
 // 方法二：实现 Index trait
 // 为 Vector<T> 实现 Index trait，这样，我们就可以通过 a[i] 来访问 Vector<T> 中的元素。
-// 这样，在 fn dot_product<T>(a: Vector<T>, b: Vector<T>) -> Result<T>，可以实现 a[i] * b[i] 的累加。
+// 这样，在 fn dot_product<T>(a: &Vector<T>, b: &Vector<T>) -> Result<T>，可以实现 a[i] * b[i] 的累加。
 // impl<T> Index<usize> for Vector<T> {
 //     type Output = T;
 
@@ -55,3 +689,277 @@ impl<T> Deref for Vector<T> {
 //         &self.data[index]
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_product() -> Result<()> {
+        let a = Vector::new([1, 2, 3]);
+        let b = Vector::new([4, 5, 6]);
+        // a、b 只是被借用，可以继续使用
+        assert_eq!(dot_product(&a, &b)?, 32);
+        assert_eq!(dot_product(&a, &a)?, 14);
+        assert_eq!(dot_product_acc::<i32, i64>(&a, &b)?, 32);
+        assert_eq!(dot_product_checked(&a, &b)?, Some(32));
+        // 可以按具体的原因 match，而不是比较字符串
+        let err = dot_product(&a, &Vector::new([1, 2])).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::DimensionMismatch {
+                expected: 3,
+                got: 2,
+                ..
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Vector dimensions do not match, expected 3, got 2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_arithmetic() -> Result<()> {
+        let a = Vector::new([1, 2, 3]);
+        let b = Vector::new([4, 5, 6]);
+        assert_eq!(*a.try_add(&b)?, vec![5, 7, 9]);
+        assert_eq!(*(b.clone() - a.clone()), vec![3, 3, 3]);
+        assert_eq!(*(a.clone() + b.clone()), vec![5, 7, 9]);
+        assert_eq!(*(a.clone() * 2), vec![2, 4, 6]);
+        assert!(a.try_sub(&Vector::new([1])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_norm() -> Result<()> {
+        let v = Vector::new([3.0, -4.0]);
+        assert_eq!(v.norm(), 5.0);
+        assert_eq!(v.norm_l1(), 7.0);
+        let u = v.normalize()?;
+        assert_eq!(*u, vec![0.6, -0.8]);
+        assert!((u.norm() - 1.0f64).abs() < 1e-12);
+
+        let zero = Vector::new([0.0f32; 3]);
+        assert_eq!(zero.norm(), 0.0);
+        assert!(zero.normalize().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_cosine_similarity() -> Result<()> {
+        let a = Vector::new([1.0, 2.0, 3.0]);
+        assert!((a.cosine_similarity(&a.scale(2.0))? - 1.0).abs() < 1e-12);
+        assert!((a.cosine_similarity(&a.scale(-1.0))? + 1.0).abs() < 1e-12);
+
+        let x = Vector::new([1.0f32, 0.0]);
+        let y = Vector::new([0.0f32, 3.0]);
+        assert_eq!(x.cosine_similarity(&y)?, 0.0);
+        let d = Vector::new([1.0f32, 1.0]);
+        assert!((x.cosine_similarity(&d)? - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        assert!(x.cosine_similarity(&Vector::new([0.0, 0.0])).is_err());
+        assert!(x.cosine_similarity(&Vector::new([1.0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_display() {
+        let v = Vector::new([1, 2, 3]);
+        assert_eq!(format!("{}", v), "{1 2 3}");
+        assert_eq!(format!("{:?}", v), "Vector(len=3, {1 2 3})");
+        assert_eq!(format!("{}", Vector::<i32>::new([])), "{}");
+    }
+
+    #[test]
+    fn test_vector_mut() {
+        let mut v = Vector::new(Vec::new());
+        v.push(1);
+        v.extend([2, 3]);
+        Extend::extend(&mut v, vec![4]);
+        v[0] = 10;
+        v.iter_mut().for_each(|x| *x *= 2);
+        assert_eq!(format!("{}", v), "{20 4 6 8}");
+    }
+
+    #[test]
+    fn test_vector_stats() {
+        let v = Vector::new([3, -1, 7, 2, 9, 4, 9, 0, -6]);
+        assert_eq!(v.sum(), 27);
+        assert_eq!(v.mean(), Some(3.0));
+        // 两个 9：返回第一个
+        assert_eq!(v.argmax(), Some(4));
+        assert_eq!(v.argmin(), Some(8));
+
+        let big = (0..100_000)
+            .map(|i| (i * 7919) % 100_003)
+            .collect::<Vector<i64>>();
+        let max = big.iter().max().unwrap();
+        assert_eq!(big[big.argmax().unwrap()], *max);
+        assert_eq!(big.sum(), big.iter().sum::<i64>());
+
+        let empty = Vector::<f64>::new([]);
+        assert_eq!(empty.sum(), 0.0);
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.argmax(), None);
+    }
+
+    #[test]
+    fn test_vector_constructors() {
+        assert_eq!(*Vector::<i32>::zeros(3), vec![0, 0, 0]);
+        assert_eq!(*Vector::<f64>::ones(2), vec![1.0, 1.0]);
+        assert!(Vector::<u8>::zeros(0).is_empty());
+
+        let v = Vector::random(1000, -5..5);
+        assert_eq!(v.len(), 1000);
+        assert!(v.iter().all(|x| (-5..5).contains(x)));
+        let v = Vector::random(1000, 0.0..=1.0);
+        assert!(v.iter().all(|x| (0.0..=1.0).contains(x)));
+    }
+
+    #[test]
+    fn test_vector_approx_eq() {
+        let a = Vector::new([0.1 + 0.2, 1.0]);
+        let b = Vector::new([0.3, 1.0]);
+        assert_ne!(a[0], b[0]);
+        assert!(a.approx_eq(&b, 1e-12));
+        assert!(!a.approx_eq(&Vector::new([0.31, 1.0]), 1e-3));
+        assert!(!a.approx_eq(&Vector::new([0.3]), 1.0));
+        assert!(!Vector::new([f64::NAN]).approx_eq(&Vector::new([f64::NAN]), 1.0));
+
+        // x * 0.1 和 x / 10.0 在最后一位上可能不同
+        let v = Vector::random(PAR_THRESHOLD * 2, -1.0..1.0f64);
+        let divided = v.par_map(|x| x / 10.0);
+        assert!((v * 0.1).approx_eq(&divided, 1e-15));
+    }
+
+    #[test]
+    fn test_vector_par_map() {
+        let v = Vector::new([1, 2, 3]);
+        assert_eq!(*v.par_map(|x| x * 10), vec![10, 20, 30]);
+
+        // 超过 PAR_THRESHOLD 会走多线程；结果的顺序必须和输入一致
+        let n = PAR_THRESHOLD * 3 + 7;
+        let v = (0..n).collect::<Vector<usize>>();
+        let m = v.par_map(|&x| x as f64 * 0.5);
+        assert_eq!(m.len(), n);
+        assert!(m.iter().enumerate().all(|(i, &x)| x == i as f64 * 0.5));
+    }
+
+    #[test]
+    fn test_vector_cross() -> Result<()> {
+        let x = Vector::new([1, 0, 0]);
+        let y = Vector::new([0, 1, 0]);
+        assert_eq!(*x.cross(&y)?, vec![0, 0, 1]);
+        assert_eq!(*y.cross(&x)?, vec![0, 0, -1]);
+
+        let a = Vector::new([1, 2, 3]);
+        let b = Vector::new([4, 5, 6]);
+        let c = a.cross(&b)?;
+        assert_eq!(*c, vec![-3, 6, -3]);
+        // 叉积与两个输入都正交
+        assert_eq!(dot_product(&a, &c)?, 0);
+        assert_eq!(dot_product(&b, &c)?, 0);
+
+        let err = a.cross(&Vector::new([1, 2])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cross product requires 3-element vectors, got 3 and 2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_iter() -> Result<()> {
+        let a = (1..=3).collect::<Vector<i32>>();
+        let b = a.iter().map(|x| x * 10).collect::<Vector<_>>();
+        assert_eq!(dot_product(&a, &b)?, 140);
+
+        let mut sum = 0;
+        for x in &a {
+            sum += x;
+        }
+        assert_eq!(sum, 6);
+        assert_eq!(a.into_iter().rev().collect::<Vec<_>>(), vec![3, 2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_slice_unrolled() -> Result<()> {
+        // 长度覆盖了 0、不足一组、正好整组以及带余数的情况
+        for n in [0, 3, 4, 17] {
+            let a = (0..n).map(|v| v * 3 - 7).collect::<Vec<i64>>();
+            let b = (0..n).map(|v| 11 - v).collect::<Vec<i64>>();
+            let expected = dot_slice_scalar::<i64, i64>(&a, &b)?;
+            assert_eq!(dot_slice_unrolled::<i64, i64>(&a, &b), expected);
+            assert_eq!(dot_slice_acc::<i64, i64>(&a, &b)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_product_stable() -> Result<()> {
+        // 1 + 1e-8 * 1e6：f32 的普通累加里每个 1e-8 都会被 1.0 吞掉
+        let n = 1_000_000;
+        let a = Vector::new(
+            std::iter::once(1.0f32)
+                .chain(std::iter::repeat_n(1e-8, n))
+                .collect::<Vec<_>>(),
+        );
+        let b = Vector::new(vec![1.0f32; n + 1]);
+        let naive = dot_slice_scalar::<f32, f32>(&a, &b)?;
+        let stable = dot_product_stable(&a, &b)?;
+        assert_eq!(naive, 1.0);
+        assert!((stable - 1.01).abs() < 1e-6);
+
+        assert_eq!(dot_product_stable(&[1.0, 2.0][..], &[3.0, 4.0][..])?, 11.0);
+        assert!(dot_product_stable(&a, &[1.0][..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_view() -> Result<()> {
+        // 按行存储的 3x3 矩阵，第 1 列是 [2, 5, 8]
+        let m = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let col = VectorView::with_stride(&m[1..], 3)?;
+        assert_eq!(col.len(), 3);
+        assert_eq!(col.iter().copied().collect::<Vec<_>>(), vec![2, 5, 8]);
+        assert_eq!(col.get(2), Some(&8));
+        assert_eq!(col.get(3), None);
+
+        let row = VectorView::new(&m[3..6]);
+        assert_eq!(dot_product(row, col)?, 4 * 2 + 5 * 5 + 6 * 8);
+        assert_eq!(dot_product_checked(col, col)?, Some(4 + 25 + 64));
+        assert_eq!(dot_product(&m[..3], &Vector::new([1, 1, 1]))?, 6);
+
+        assert!(dot_product(col, VectorView::new(&m[..2])).is_err());
+        assert!(VectorView::with_stride(&m, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_vector_add_panic() {
+        let _ = Vector::new([1, 2, 3]) + Vector::new([1, 2]);
+    }
+
+    #[test]
+    fn test_dot_product_par() -> Result<()> {
+        // 长度不是 NUM_THREADS 的整数倍，最后一块比较短
+        let n = 1_000_003;
+        let a = Vector::new((0..n).map(|v| (v % 7) as i64 - 3).collect::<Vec<_>>());
+        let b = Vector::new((0..n).map(|v| (v % 5) as i64).collect::<Vec<_>>());
+        assert_eq!(dot_product_par(&a, &b)?, dot_product(&a, &b)?);
+
+        let short = Vector::new([1, 2, 3]);
+        assert_eq!(dot_product_par(&short, &short)?, 14);
+        assert!(dot_product_par(&a, &short).is_err());
+
+        // 100_000 个 i16：每一项 300 * 300 已经超出 i16，总和也超出了 i32，只能累加到 i64
+        let w = Vector::new(vec![300i16; 100_000]);
+        assert_eq!(dot_product_par_acc::<i16, i64>(&w, &w)?, 9_000_000_000);
+        assert_eq!(dot_product_acc::<i16, i64>(&w, &w)?, 9_000_000_000);
+        Ok(())
+    }
+}